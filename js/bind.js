@@ -5,3 +5,87 @@ export function get_url_fragment() {
 export function console_log(s) {
     console.log(s);
 }
+
+// Navigating the tree adds entries to the browser history, so that its back button moves through
+// the tree too. The fragment of the entry the user went back or forward to is kept until the
+// viewer asks for it.
+let poppedFragment = null;
+window.addEventListener("popstate", () => {
+    poppedFragment = window.location.hash.substring(1);
+});
+
+export function take_popped_fragment() {
+    const fragment = poppedFragment;
+    poppedFragment = null;
+    return fragment;
+}
+
+export function push_url_fragment(fragment) {
+    history.pushState(null, "", "#" + fragment);
+}
+
+export function replace_url_fragment(fragment) {
+    history.replaceState(null, "", "#" + fragment);
+}
+
+export function reload_page() {
+    window.location.reload();
+}
+
+// Keep track of how many bytes are being downloaded, to show the progress while loading. Bevy
+// downloads assets through `fetch` and reads the whole body at once, so we wrap `fetch` to count
+// the bytes of each response as they arrive.
+let downloadedBytes = 0;
+let totalBytes = 0;
+let unknownSizes = 0;
+
+const originalFetch = window.fetch.bind(window);
+window.fetch = async function (...args) {
+    const response = await originalFetch(...args);
+    if (!response.body) {
+        return response;
+    }
+
+    const length = Number(response.headers.get("Content-Length"));
+    if (length > 0) {
+        totalBytes += length;
+    } else {
+        unknownSizes += 1;
+    }
+
+    const reader = response.body.getReader();
+    const body = new ReadableStream({
+        async pull(controller) {
+            const { done, value } = await reader.read();
+            if (done) {
+                controller.close();
+                return;
+            }
+            downloadedBytes += value.byteLength;
+            controller.enqueue(value);
+        },
+        cancel(reason) {
+            return reader.cancel(reason);
+        },
+    });
+    return new Response(body, {
+        status: response.status,
+        statusText: response.statusText,
+        headers: response.headers,
+    });
+};
+
+export function get_downloaded_bytes() {
+    return downloadedBytes;
+}
+
+// zero if the size of some download is unknown
+export function get_total_bytes() {
+    return unknownSizes > 0 ? 0 : totalBytes;
+}
+
+export function reset_download_progress() {
+    downloadedBytes = 0;
+    totalBytes = 0;
+    unknownSizes = 0;
+}
@@ -2,5 +2,13 @@ set -xe
 RUSTFLAGS='--cfg getrandom_backend="wasm_js"' trunk build "$@"
 static-web-server -p 8080 -x -d ./dist --cache-control-headers false -w static-web-server.toml
 
+# http://localhost:8080/
+# http://localhost:8080/#manifest=http://localhost:8080/tree.json
+# http://localhost:8080/#node=2
+# http://localhost:8080/#page_size=4&prefetch=1
+# http://localhost:8080/#node=2&density=1.04
+# http://localhost:8080/#node=2&render=wireframe
+# http://localhost:8080/#node=2&build=-y&nozzle=0.6
 # http://localhost:8080/#http://localhost:8080/benchy.stl
-# http://localhost:8080/#https://files.printables.com/media/prints/2236/stls/14012_b9139bd5-c68b-46a5-ba28-6513f9715d83/3dbenchy.stl
\ No newline at end of file
+# http://localhost:8080/#https://files.printables.com/media/prints/2236/stls/14012_b9139bd5-c68b-46a5-ba28-6513f9715d83/3dbenchy.stl
+# cargo run --release -p stlviewer-thumbs --target host-tuple -- tree.json
@@ -5,6 +5,20 @@ extern "C" {
     pub fn get_url_fragment() -> String;
 
     pub fn console_log(s: &str);
+
+    pub fn take_popped_fragment() -> Option<String>;
+
+    pub fn push_url_fragment(fragment: &str);
+
+    pub fn replace_url_fragment(fragment: &str);
+
+    pub fn reload_page();
+
+    pub fn get_downloaded_bytes() -> f64;
+
+    pub fn get_total_bytes() -> f64;
+
+    pub fn reset_download_progress();
 }
 
 macro_rules! console_log {
@@ -0,0 +1,132 @@
+//! Shows the path from the root to the current node at the top of the window, with each
+//! ancestor clickable to jump straight back to it.
+
+use std::sync::{Arc, Weak};
+
+use bevy::{color::palettes::tailwind::{CYAN_300, GRAY_400}, prelude::*};
+
+use crate::{loading::VisualizationComponents, meshes_tree::MeshTreeNode, MeshTreeRes, OneShotSystemsRes};
+
+pub struct BreadcrumbPlugin;
+
+impl Plugin for BreadcrumbPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, update_breadcrumb.run_if(resource_changed::<MeshTreeRes>));
+    }
+}
+
+// Marker component for the root of the breadcrumb.
+#[derive(Component)]
+pub struct Breadcrumb;
+
+/// A crumb leading to `node`, an ancestor of the current node.
+#[derive(Component)]
+pub struct Crumb(Weak<MeshTreeNode>);
+
+/// The nodes from the root down to `node`.
+fn ancestors(node: &Arc<MeshTreeNode>) -> Vec<Arc<MeshTreeNode>> {
+    let mut ancestors = vec![node.clone()];
+    while let Some(parent) = ancestors.last().and_then(|node| node.parent.upgrade()) {
+        ancestors.push(parent);
+    }
+    ancestors.reverse();
+    ancestors
+}
+
+fn update_breadcrumb(
+    mut commands: Commands,
+    mesh_tree: Res<MeshTreeRes>,
+    breadcrumbs: Query<Entity, With<Breadcrumb>>,
+    mut shown: Local<Weak<MeshTreeNode>>,
+) {
+    // the resource also changes when its materials are used, rebuild only if the node changed
+    if shown.ptr_eq(&mesh_tree.current) {
+        return;
+    }
+    *shown = mesh_tree.current.clone();
+    breadcrumbs.iter().for_each(|entity| commands.entity(entity).despawn());
+
+    let Some(current) = mesh_tree.current.upgrade() else { return; };
+    let ancestors = ancestors(&current);
+    if ancestors.len() < 2 {
+        // there is nowhere to go from the root
+        return;
+    }
+
+    // centered at the top, clear of the back button, and only the bar itself takes clicks
+    commands.spawn((
+        Breadcrumb,
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(12.),
+            width: Val::Percent(100.),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        Pickable::IGNORE,
+        VisualizationComponents,
+        Visibility::Hidden,
+    )).with_children(|breadcrumb| {
+        breadcrumb.spawn((
+            Node {
+                max_width: Val::Percent(60.),
+                flex_wrap: FlexWrap::Wrap,
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(6.),
+                padding: UiRect::axes(Val::Px(10.), Val::Px(6.)),
+                ..default()
+            },
+            BackgroundColor(Color::BLACK.with_alpha(0.6)),
+        )).with_children(|bar| {
+            let last = ancestors.len() - 1;
+            for (index, node) in ancestors.iter().enumerate() {
+                if index > 0 {
+                    bar.spawn((
+                        Text::new("›"),
+                        TextFont { font_size: 14., ..default() },
+                        TextColor(Color::from(GRAY_400)),
+                    ));
+                }
+                let mut crumb = bar.spawn((
+                    Text::new(node.display_name()),
+                    TextFont { font_size: 14., ..default() },
+                    TextColor(if index == last { Color::WHITE } else { Color::from(CYAN_300) }),
+                ));
+                if index < last {
+                    crumb.insert(Crumb(Arc::downgrade(node))).observe(open_crumb);
+                }
+            }
+        });
+    });
+}
+
+fn open_crumb(
+    click: On<Pointer<Click>>,
+    mut commands: Commands,
+    crumbs: Query<&Crumb>,
+    mut mesh_tree: ResMut<MeshTreeRes>,
+    one_shot_systems: Res<OneShotSystemsRes>,
+) {
+    let Ok(crumb) = crumbs.get(click.event().event_target()) else { return; };
+    if crumb.0.strong_count() > 0 {
+        mesh_tree.current = crumb.0.clone();
+        commands.run_system(one_shot_systems.update_current_sys);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{breadcrumb::ancestors, meshes_tree::MeshTreeNode};
+
+    #[test]
+    fn test_ancestors() {
+        let (root, _) = MeshTreeNode::from_json(br#"{
+            "title": "Catalog",
+            "children": [{ "url": "/a.stl" }, { "title": "Boats", "children": [{ "url": "/boats/benchy.stl" }] }]
+        }"#, "/tree.json").unwrap();
+        let benchy = &root.children()[1].children()[0];
+        let names: Vec<_> = ancestors(benchy).iter().map(|node| node.display_name().to_string()).collect();
+        assert_eq!(names, vec!["Catalog", "Boats", "benchy.stl"]);
+        assert_eq!(ancestors(&root).len(), 1);
+    }
+}
@@ -0,0 +1,137 @@
+//! A bounding volume hierarchy over the triangles of meshes, to cast rays against them
+//! without testing every triangle.
+
+use bevy::prelude::*;
+
+/// The number of triangles under which the nodes of the hierarchy aren't split anymore.
+const LEAF_SIZE: usize = 4;
+
+/// The hierarchy of the boxes bounding the triangles, built once for them.
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    /// the indices of the triangles, ordered so that each node covers a range of them
+    order: Vec<usize>,
+}
+
+struct BvhNode {
+    min: Vec3,
+    max: Vec3,
+    start: usize,
+    end: usize,
+    children: Option<(usize, usize)>,
+}
+
+impl Bvh {
+    pub fn new(triangles: &[[Vec3; 3]]) -> Bvh {
+        let mut bvh = Bvh { nodes: Vec::new(), order: (0..triangles.len()).collect() };
+        if !triangles.is_empty() {
+            bvh.split(triangles, 0, triangles.len());
+        }
+        bvh
+    }
+
+    /// Adds the node covering the triangles from `start` to `end` in the order, and its children
+    /// splitting them in half along the longest axis of their centers. Returns its index.
+    fn split(&mut self, triangles: &[[Vec3; 3]], start: usize, end: usize) -> usize {
+        let center = |index: usize| (triangles[index][0] + triangles[index][1] + triangles[index][2]) / 3.;
+        let (mut min, mut max) = (Vec3::INFINITY, Vec3::NEG_INFINITY);
+        let (mut center_min, mut center_max) = (Vec3::INFINITY, Vec3::NEG_INFINITY);
+        for index in &self.order[start..end] {
+            for corner in triangles[*index] {
+                min = min.min(corner);
+                max = max.max(corner);
+            }
+            center_min = center_min.min(center(*index));
+            center_max = center_max.max(center(*index));
+        }
+        let node = self.nodes.len();
+        self.nodes.push(BvhNode { min, max, start, end, children: None });
+
+        let extent = center_max - center_min;
+        if end - start <= LEAF_SIZE || extent.max_element() <= 0. {
+            return node;
+        }
+        let axis = extent.max_position();
+        let middle = (start + end) / 2;
+        self.order[start..end].select_nth_unstable_by(middle - start, |a, b| center(*a)[axis].total_cmp(&center(*b)[axis]));
+        let left = self.split(triangles, start, middle);
+        let right = self.split(triangles, middle, end);
+        self.nodes[node].children = Some((left, right));
+        node
+    }
+
+    /// Whether the ray from `origin` along `direction` hits, within `max_distance`, one of the
+    /// triangles `accept` returns true for.
+    pub fn any_hit(
+        &self,
+        triangles: &[[Vec3; 3]],
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        accept: impl Fn(usize) -> bool,
+    ) -> bool {
+        let mut stack = if self.nodes.is_empty() { vec![] } else { vec![0] };
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if !ray_hits_box(origin, direction, max_distance, node.min, node.max) {
+                continue;
+            }
+            match node.children {
+                Some((left, right)) => stack.extend([left, right]),
+                None => {
+                    let hit = self.order[node.start..node.end].iter().any(|index| {
+                        accept(*index)
+                            && ray_triangle_distance(origin, direction, triangles[*index]).is_some_and(|distance| distance <= max_distance)
+                    });
+                    if hit {
+                        return true;
+                    }
+                },
+            }
+        }
+        false
+    }
+}
+
+/// Whether the ray crosses the box within `max_distance`.
+fn ray_hits_box(origin: Vec3, direction: Vec3, max_distance: f32, min: Vec3, max: Vec3) -> bool {
+    let (mut near, mut far) = (0_f32, max_distance);
+    for axis in 0..3 {
+        if direction[axis] == 0. {
+            if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                return false;
+            }
+            continue;
+        }
+        let a = (min[axis] - origin[axis]) / direction[axis];
+        let b = (max[axis] - origin[axis]) / direction[axis];
+        near = near.max(a.min(b));
+        far = far.min(a.max(b));
+        if near > far {
+            return false;
+        }
+    }
+    true
+}
+
+/// The distance along the ray to where it crosses the triangle, from either side
+/// (Möller–Trumbore).
+pub fn ray_triangle_distance(origin: Vec3, direction: Vec3, [a, b, c]: [Vec3; 3]) -> Option<f32> {
+    let (edge1, edge2) = (b - a, c - a);
+    let p = direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < f32::EPSILON * edge1.length() * edge2.length() {
+        return None;
+    }
+    let to_origin = origin - a;
+    let u = to_origin.dot(p) / determinant;
+    if !(0. ..=1.).contains(&u) {
+        return None;
+    }
+    let q = to_origin.cross(edge1);
+    let v = direction.dot(q) / determinant;
+    if v < 0. || u + v > 1. {
+        return None;
+    }
+    Some(edge2.dot(q) / determinant).filter(|distance| *distance >= 0.)
+}
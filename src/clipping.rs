@@ -0,0 +1,433 @@
+//! Cuts the model rendered on its own with a plane, to look inside it. Pressing C switches the
+//! plane between the X, Y and Z axes (Z pointing up, as when printing), the direction the camera
+//! is looking from, and no plane. The plane is moved along its normal by dragging the slider
+//! below the model, or with the `[` and `]` keys, and the section is filled with a flat color.
+//!
+//! While clipping, the meshes are rendered with [`ClipMaterial`], which extends the
+//! [`StandardMaterial`] they had with the plane.
+
+use bevy::{
+    asset::{embedded_asset, embedded_path, AssetPath},
+    color::palettes::tailwind::{CYAN_300, FUCHSIA_500, GRAY_400, GRAY_700},
+    mesh::MeshVertexBufferLayoutRef,
+    pbr::{ExtendedMaterial, MaterialExtension, MaterialExtensionKey, MaterialExtensionPipeline},
+    platform::collections::HashMap,
+    prelude::*,
+    render::render_resource::{AsBindGroup, RenderPipelineDescriptor, SpecializedMeshPipelineError},
+    shader::ShaderRef,
+};
+use bevy_panorbit_camera::PanOrbitCamera;
+
+use crate::{dimensions::{format_length, CurrentLeaf, ModelDimensions, TrackCurrentLeaf}, search::not_typing};
+
+pub struct ClippingPlugin;
+
+impl Plugin for ClippingPlugin {
+    fn build(&self, app: &mut App) {
+        embedded_asset!(app, "clipping.wgsl");
+        app.add_plugins(MaterialPlugin::<ClipMaterial>::default())
+            .init_resource::<Clipping>()
+            .add_systems(Update, (
+                switch_clip_axis.run_if(not_typing),
+                follow_leaf.after(TrackCurrentLeaf).run_if(resource_changed::<CurrentLeaf>),
+                swap_clip_materials.in_set(SwapClipMaterials),
+                update_clip_plane.run_if(resource_changed::<Clipping>),
+                (show_clip_panel, update_clip_panel).chain().run_if(resource_changed::<Clipping>),
+            ).chain());
+    }
+}
+
+/// The system giving the meshes of the leaf a [`ClipMaterial`], which the systems changing their
+/// [`StandardMaterial`] run before, so that no frame shows them unclipped.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SwapClipMaterials;
+
+/// A [`StandardMaterial`] cut by a plane.
+pub type ClipMaterial = ExtendedMaterial<StandardMaterial, ClipExtension>;
+
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub struct ClipExtension {
+    /// the normal of the plane in world space, with its distance from the origin along it, the
+    /// side the normal points to being cut away
+    #[uniform(100)]
+    plane: Vec4,
+    /// the linear color of the section
+    #[uniform(100)]
+    cap_color: Vec4,
+}
+
+impl MaterialExtension for ClipExtension {
+    fn fragment_shader() -> ShaderRef {
+        ShaderRef::Path(AssetPath::from_path_buf(embedded_path!("clipping.wgsl")).with_source("embedded"))
+    }
+
+    // the prepass and the shadows would still see the part cut away
+    fn enable_prepass() -> bool {
+        false
+    }
+
+    fn enable_shadows() -> bool {
+        false
+    }
+
+    fn specialize(
+        _pipeline: &MaterialExtensionPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialExtensionKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // the back faces are seen through the cut
+        descriptor.primitive.cull_mode = None;
+        Ok(())
+    }
+}
+
+/// The orientation of the clipping plane.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ClipAxis {
+    #[default]
+    Off,
+    X,
+    Y,
+    Z,
+    /// facing the camera when chosen, the part nearest to the camera being cut away
+    View,
+}
+
+impl ClipAxis {
+    const ALL: [ClipAxis; 5] = [ClipAxis::X, ClipAxis::Y, ClipAxis::Z, ClipAxis::View, ClipAxis::Off];
+
+    fn next(self) -> ClipAxis {
+        match self {
+            ClipAxis::Off => ClipAxis::X,
+            ClipAxis::X => ClipAxis::Y,
+            ClipAxis::Y => ClipAxis::Z,
+            ClipAxis::Z => ClipAxis::View,
+            ClipAxis::View => ClipAxis::Off,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ClipAxis::Off => "Off",
+            ClipAxis::X => "X",
+            ClipAxis::Y => "Y",
+            ClipAxis::Z => "Z",
+            ClipAxis::View => "View",
+        }
+    }
+
+    /// The normal of the plane in world space, where the Z axis of the files points up along Y,
+    /// or `None` for the view, which depends on the camera.
+    fn normal(self) -> Option<Vec3> {
+        match self {
+            ClipAxis::X => Some(Vec3::X),
+            ClipAxis::Y => Some(Vec3::NEG_Z),
+            ClipAxis::Z => Some(Vec3::Y),
+            ClipAxis::Off | ClipAxis::View => None,
+        }
+    }
+}
+
+/// The clipping plane of the leaf shown.
+#[derive(Resource, Default)]
+pub struct Clipping {
+    axis: ClipAxis,
+    normal: Vec3,
+    /// where the plane is along its normal, from -1 where the whole model is cut away to 1
+    /// where nothing is, through its center at 0
+    offset: f32,
+    /// the leaf entity being clipped
+    target: Option<Entity>,
+    /// the materials made for the meshes of the leaf, by the material they replace
+    materials: HashMap<AssetId<StandardMaterial>, Handle<ClipMaterial>>,
+}
+
+impl Clipping {
+    fn set_axis(&mut self, axis: ClipAxis, camera: Option<&GlobalTransform>) {
+        self.axis = axis;
+        self.offset = 0.;
+        self.normal = match (axis.normal(), camera) {
+            (Some(normal), _) => normal,
+            (None, Some(camera)) => *camera.back(),
+            (None, None) => Vec3::Z,
+        };
+    }
+
+    /// The plane for the shader, given the half size of the bounding box of the model, which is
+    /// centered on the origin.
+    fn plane(&self, half_size: Vec3) -> Vec4 {
+        // slightly beyond the model at the ends of the slider, to avoid flickering
+        let extent = half_size.dot(self.normal.abs()) * 1.001;
+        self.normal.extend(self.offset * extent)
+    }
+}
+
+/// Placed on the meshes rendered with a [`ClipMaterial`], to restore their own material.
+#[derive(Component)]
+pub struct Unclipped(pub Handle<StandardMaterial>);
+
+/// The color of the section, standing out from the usual white and light colors of the models.
+const CAP_COLOR: Srgba = FUCHSIA_500;
+/// How much of the slider the `[` and `]` keys move the plane by.
+const KEY_STEP: f32 = 0.02;
+const SLIDER_WIDTH: f32 = 240.;
+const KNOB_WIDTH: f32 = 10.;
+
+fn switch_clip_axis(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut clipping: ResMut<Clipping>,
+    camera: Query<&GlobalTransform, With<Camera3d>>,
+) {
+    if clipping.target.is_none() {
+        return;
+    }
+    if keys.just_pressed(KeyCode::KeyC) {
+        let axis = clipping.axis.next();
+        clipping.set_axis(axis, camera.single().ok());
+        console_log!("Clipping {axis:?}");
+    }
+    if clipping.axis == ClipAxis::Off {
+        return;
+    }
+    if keys.just_pressed(KeyCode::BracketLeft) {
+        clipping.offset = (clipping.offset - KEY_STEP).max(-1.);
+    }
+    if keys.just_pressed(KeyCode::BracketRight) {
+        clipping.offset = (clipping.offset + KEY_STEP).min(1.);
+    }
+}
+
+/// Stops clipping whenever another leaf is shown.
+fn follow_leaf(mut clipping: ResMut<Clipping>, current_leaf: Res<CurrentLeaf>) {
+    if clipping.target != current_leaf.0 {
+        clipping.target = current_leaf.0;
+        clipping.axis = ClipAxis::Off;
+        clipping.materials.clear();
+    } else {
+        // the model was turned, e.g. laid flat, so its bounding box changed
+        clipping.set_changed();
+    }
+}
+
+/// Renders the meshes of the leaf with a [`ClipMaterial`] while clipping, and with their own
+/// material otherwise. This runs every frame, since the meshes of glTF scenes might be spawned
+/// after the plane is chosen.
+#[allow(clippy::too_many_arguments)]
+fn swap_clip_materials(
+    mut commands: Commands,
+    mut clipping: ResMut<Clipping>,
+    mut clip_materials: ResMut<Assets<ClipMaterial>>,
+    standard_materials: Res<Assets<StandardMaterial>>,
+    descendants: Query<&Children>,
+    unclipped: Query<(Entity, &MeshMaterial3d<StandardMaterial>), With<Mesh3d>>,
+    clipped: Query<(Entity, &Unclipped)>,
+    dimensions: Query<&ModelDimensions>,
+) {
+    let target = clipping.target.filter(|_| clipping.axis != ClipAxis::Off);
+    let Some(target) = target else {
+        for (entity, material) in &clipped {
+            commands.entity(entity)
+                .insert(MeshMaterial3d(material.0.clone()))
+                .remove::<(Unclipped, MeshMaterial3d<ClipMaterial>)>();
+        }
+        return;
+    };
+    let Ok(dimensions) = dimensions.get(target) else { return; };
+
+    let half_size = dimensions.size_mm / dimensions.mm_per_world_unit / 2.;
+    let plane = clipping.plane(half_size);
+    for entity in descendants.iter_descendants(target) {
+        let Ok((entity, material)) = unclipped.get(entity) else { continue; };
+        let Some(base) = standard_materials.get(&material.0) else { continue; };
+        let clip_material = clipping.bypass_change_detection().materials.entry(material.0.id())
+            .or_insert_with(|| clip_materials.add(ClipMaterial {
+                base: base.clone(),
+                extension: ClipExtension { plane, cap_color: LinearRgba::from(CAP_COLOR).to_vec4() },
+            }))
+            .clone();
+        commands.entity(entity)
+            .insert((MeshMaterial3d(clip_material), Unclipped(material.0.clone())))
+            .remove::<MeshMaterial3d<StandardMaterial>>();
+    }
+}
+
+fn update_clip_plane(
+    clipping: Res<Clipping>,
+    mut clip_materials: ResMut<Assets<ClipMaterial>>,
+    dimensions: Query<&ModelDimensions>,
+) {
+    let Some(dimensions) = clipping.target.and_then(|target| dimensions.get(target).ok()) else { return; };
+    let plane = clipping.plane(dimensions.size_mm / dimensions.mm_per_world_unit / 2.);
+    for handle in clipping.materials.values() {
+        if let Some(material) = clip_materials.get_mut(handle) {
+            material.extension.plane = plane;
+        }
+    }
+}
+
+/// The panel to choose the axis and move the plane, shown while clipping.
+#[derive(Component)]
+struct ClipPanel;
+
+#[derive(Component)]
+struct ClipAxisButton(ClipAxis);
+
+#[derive(Component)]
+struct ClipKnob;
+
+#[derive(Component)]
+struct ClipText;
+
+/// Rebuilds the panel when the axis or the leaf change, but not while moving the plane, which
+/// would interrupt dragging the slider.
+fn show_clip_panel(
+    mut commands: Commands,
+    clipping: Res<Clipping>,
+    mut shown: Local<(ClipAxis, Option<Entity>)>,
+    panels: Query<Entity, With<ClipPanel>>,
+) {
+    if *shown == (clipping.axis, clipping.target) {
+        return;
+    }
+    *shown = (clipping.axis, clipping.target);
+    panels.iter().for_each(|entity| commands.entity(entity).despawn());
+    if clipping.axis == ClipAxis::Off || clipping.target.is_none() {
+        return;
+    }
+
+    commands.spawn((
+        ClipPanel,
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(12.),
+            width: Val::Percent(100.),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        Pickable::IGNORE,
+    )).with_children(|panel| {
+        panel.spawn((
+            Node {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(8.),
+                padding: UiRect::all(Val::Px(8.)),
+                ..default()
+            },
+            BackgroundColor(Color::BLACK.with_alpha(0.6)),
+        )).with_children(|panel| {
+            panel.spawn(Node { column_gap: Val::Px(12.), ..default() }).with_children(|row| {
+                row.spawn((
+                    Text::new("Section"),
+                    TextFont { font_size: 14., ..default() },
+                    TextColor(Color::WHITE),
+                    Pickable::IGNORE,
+                ));
+                for axis in ClipAxis::ALL {
+                    let color = if axis == clipping.axis { CYAN_300 } else { GRAY_400 };
+                    row.spawn((
+                        ClipAxisButton(axis),
+                        Text::new(axis.name()),
+                        TextFont { font_size: 14., ..default() },
+                        TextColor(Color::from(color)),
+                    )).observe(choose_clip_axis);
+                }
+            });
+            panel.spawn((
+                Node {
+                    width: Val::Px(SLIDER_WIDTH),
+                    height: Val::Px(8.),
+                    margin: UiRect::vertical(Val::Px(4.)),
+                    ..default()
+                },
+                BackgroundColor(Color::from(GRAY_700)),
+                children![(
+                    ClipKnob,
+                    Node {
+                        position_type: PositionType::Absolute,
+                        width: Val::Px(KNOB_WIDTH),
+                        height: Val::Px(16.),
+                        top: Val::Px(-4.),
+                        ..default()
+                    },
+                    BackgroundColor(Color::from(CAP_COLOR)),
+                    Pickable::IGNORE,
+                )],
+            ))
+                .observe(lock_camera_on::<Pointer<Press>>(true))
+                .observe(lock_camera_on::<Pointer<Click>>(false))
+                .observe(lock_camera_on::<Pointer<DragEnd>>(false))
+                .observe(drag_clip_slider);
+            panel.spawn((
+                ClipText,
+                Text::default(),
+                TextFont { font_size: 12., ..default() },
+                TextColor(Color::from(GRAY_400)),
+                Pickable::IGNORE,
+            ));
+        });
+    });
+}
+
+fn update_clip_panel(
+    clipping: Res<Clipping>,
+    dimensions: Query<&ModelDimensions>,
+    mut knobs: Query<&mut Node, With<ClipKnob>>,
+    mut texts: Query<&mut Text, With<ClipText>>,
+) {
+    let Some(dimensions) = clipping.target.and_then(|target| dimensions.get(target).ok()) else { return; };
+    for mut knob in &mut knobs {
+        knob.left = Val::Px((clipping.offset + 1.) / 2. * (SLIDER_WIDTH - KNOB_WIDTH));
+    }
+    let half_size = dimensions.size_mm / 2.;
+    let distance_mm = clipping.offset * half_size.dot(clipping.normal.abs());
+    let sign = if distance_mm < 0. { "-" } else { "+" };
+    for mut text in &mut texts {
+        text.0 = format!("{sign}{} from the center, drag or [ ] to move", format_length(distance_mm.abs()));
+    }
+}
+
+fn choose_clip_axis(
+    click: On<Pointer<Click>>,
+    buttons: Query<&ClipAxisButton>,
+    mut clipping: ResMut<Clipping>,
+    camera: Query<&GlobalTransform, With<Camera3d>>,
+) {
+    if let Ok(button) = buttons.get(click.event().event_target()) {
+        clipping.set_axis(button.0, camera.single().ok());
+    }
+}
+
+/// Returns an observer that stops the camera from orbiting while the slider is being dragged.
+fn lock_camera_on<E: EntityEvent>(locked: bool) -> impl Fn(On<E>, Query<&mut PanOrbitCamera>) {
+    move |_, mut cameras| {
+        cameras.iter_mut().for_each(|mut camera| camera.enabled = !locked);
+    }
+}
+
+fn drag_clip_slider(drag: On<Pointer<Drag>>, mut clipping: ResMut<Clipping>) {
+    let offset = clipping.offset + 2. * drag.delta.x / (SLIDER_WIDTH - KNOB_WIDTH);
+    clipping.offset = offset.clamp(-1., 1.);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{Vec3, Vec4};
+
+    use crate::clipping::{ClipAxis, Clipping};
+
+    #[test]
+    fn test_plane() {
+        let mut clipping = Clipping::default();
+        clipping.set_axis(ClipAxis::Y, None);
+        let half_size = Vec3::new(10., 20., 30.);
+        // the Y axis of the file is the depth of the world
+        assert_eq!(clipping.plane(half_size), Vec4::new(0., 0., -1., 0.));
+        clipping.offset = -0.5;
+        assert!((clipping.plane(half_size).w + 15.).abs() < 0.1);
+        clipping.set_axis(ClipAxis::Z, None);
+        clipping.offset = 1.;
+        assert!(clipping.plane(half_size).w > 20.);
+    }
+}
@@ -0,0 +1,44 @@
+// The standard PBR fragment shader, discarding what is beyond the clipping plane and drawing the
+// inside of the model, seen through the cut, with a flat color.
+
+#import bevy_pbr::{
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::{alpha_discard, apply_pbr_lighting, main_pass_post_lighting_processing},
+    pbr_types::STANDARD_MATERIAL_FLAGS_UNLIT_BIT,
+}
+
+struct Clipping {
+    // the normal of the plane in world space and its distance from the origin along it, the side
+    // the normal points to being cut away
+    plane: vec4<f32>,
+    cap_color: vec4<f32>,
+}
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(100)
+var<uniform> clipping: Clipping;
+
+@fragment
+fn fragment(
+    in: VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> FragmentOutput {
+    if dot(in.world_position.xyz, clipping.plane.xyz) > clipping.plane.w {
+        discard;
+    }
+
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+
+    var out: FragmentOutput;
+    if !is_front {
+        // the back faces are only visible through the cut, where they cap the section
+        out.color = clipping.cap_color;
+    } else if (pbr_input.material.flags & STANDARD_MATERIAL_FLAGS_UNLIT_BIT) == 0u {
+        out.color = apply_pbr_lighting(pbr_input);
+    } else {
+        out.color = pbr_input.material.base_color;
+    }
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+    return out;
+}
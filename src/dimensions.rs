@@ -0,0 +1,490 @@
+//! Fits meshes into the view without modifying them, keeping track of their real-world size,
+//! which is shown with a scale bar and the size of the bounding box when rendering a leaf.
+
+use bevy::{
+    camera::primitives::{Aabb, MeshAabb}, math::Affine3A, color::palettes::tailwind::GRAY_400,
+    ecs::query::QueryFilter, platform::collections::HashMap, prelude::*, scene::SceneInstanceReady,
+};
+use bevy_panorbit_camera::PanOrbitCamera;
+
+use crate::{loading::{LoadingState, VisualizationComponents}, GridItem, ShownNode};
+
+pub struct DimensionsPlugin;
+
+impl Plugin for DimensionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MeshBounds>()
+            .init_resource::<CurrentLeaf>()
+            .add_systems(Update, update_mesh_bounds)
+            .add_systems(Update, track_current_leaf.in_set(TrackCurrentLeaf))
+            .add_systems(OnEnter(LoadingState::Ready), fit_meshes)
+            .add_systems(Update, (fit_meshes_when_loaded, refit_models))
+            .add_observer(fit_scene)
+            .add_systems(Update, (spawn_scale_bar, despawn_orphan_scale_bars, update_scale_bar_size, update_scale_bar).chain());
+    }
+}
+
+/// Placed on a mesh or scene entity whose parent is a [`ShownNode`], or a pivot turning under one,
+/// to center the mesh on that node once loaded. If `normalize` is true, the mesh is also scaled to
+/// fit in a unit cube, otherwise it keeps its real size and the camera is moved to fit it instead.
+#[derive(Component)]
+pub struct FitMesh {
+    pub normalize: bool,
+}
+
+/// The transform of a mesh relative to its model, i.e. the entity with [`FitMesh`] it is under,
+/// which is only set on the meshes of glTF scenes. It is made of the local transforms in between,
+/// since global ones are not updated yet right after spawning.
+pub fn mesh_to_model<F: QueryFilter>(
+    mesh: Entity,
+    model: Entity,
+    nodes: &Query<(&Transform, &ChildOf, Option<&Mesh3d>), F>,
+) -> Affine3A {
+    let mut mesh_to_model = Affine3A::IDENTITY;
+    let mut current = mesh;
+    while current != model {
+        let Ok((transform, child_of, _)) = nodes.get(current) else { break; };
+        mesh_to_model = transform.compute_affine() * mesh_to_model;
+        current = child_of.parent();
+    }
+    mesh_to_model
+}
+
+/// Placed along with [`FitMesh`] on models spawned while the grid is already shown, e.g. when
+/// hovering a thumbnail, to fit them as soon as they are loaded. It is removed once fitted.
+#[derive(Component)]
+pub struct FitWhenLoaded;
+
+/// The real size of the model shown by a [`ShownNode`] entity.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct ModelDimensions {
+    /// the size of the bounding box along the world axes (X width, Y height, Z depth) in mm
+    pub size_mm: Vec3,
+    /// how many millimeters of the model correspond to one unit of the world
+    pub mm_per_world_unit: f32,
+}
+
+/// The leaf entity rendered on its own, once its model is fitted, which the tools working on the
+/// model follow. Besides when another leaf is shown, it is marked as changed when the model is
+/// turned, e.g. laid flat, since its dimensions change then.
+#[derive(Resource, Default)]
+pub struct CurrentLeaf(pub Option<Entity>);
+
+/// The system updating [`CurrentLeaf`], which the systems following it run after.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TrackCurrentLeaf;
+
+#[allow(clippy::type_complexity)]
+fn track_current_leaf(
+    mut current_leaf: ResMut<CurrentLeaf>,
+    leaves: Query<(Entity, Ref<ModelDimensions>), (With<ShownNode>, Without<GridItem>)>,
+) {
+    let leaf = leaves.iter().next();
+    let entity = leaf.as_ref().map(|(entity, _)| *entity);
+    if current_leaf.0 != entity {
+        current_leaf.0 = entity;
+    } else if leaf.is_some_and(|(_, dimensions)| dimensions.is_changed()) {
+        current_leaf.set_changed();
+    }
+}
+
+/// The bounding box of each loaded mesh, computed just once when the mesh is loaded, since the
+/// same mesh can be shown many times (e.g. when the same url is repeated in the manifest).
+#[derive(Resource, Default)]
+pub struct MeshBounds(HashMap<AssetId<Mesh>, Aabb>);
+
+impl MeshBounds {
+    pub fn get(&self, mesh: &Handle<Mesh>) -> Option<Aabb> {
+        self.0.get(&mesh.id()).copied()
+    }
+}
+
+fn update_mesh_bounds(
+    mut events: MessageReader<AssetEvent<Mesh>>,
+    meshes: Res<Assets<Mesh>>,
+    mut bounds: ResMut<MeshBounds>,
+) {
+    for event in events.read() {
+        match event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => {
+                match meshes.get(*id).and_then(|mesh| mesh.compute_aabb()) {
+                    Some(aabb) => bounds.0.insert(*id, aabb),
+                    None => bounds.0.remove(id),
+                };
+            },
+            AssetEvent::Removed { id } | AssetEvent::Unused { id } => {
+                bounds.0.remove(id);
+            },
+            AssetEvent::LoadedWithDependencies { .. } => {},
+        }
+    }
+}
+
+fn fit_meshes(
+    mut commands: Commands,
+    mut fit: Query<(&Mesh3d, &mut Transform, &FitMesh, &ChildOf)>,
+    roots: Query<(&ShownNode, &GlobalTransform)>,
+    parents: Query<&ChildOf>,
+    mesh_bounds: Res<MeshBounds>,
+    meshes: Res<Assets<Mesh>>,
+    mut camera: Query<(&mut PanOrbitCamera, &mut Projection)>,
+) {
+    for (mesh, mut transform, fit_mesh, child_of) in &mut fit {
+        // meshes that failed to load are skipped, since they get replaced by a placeholder
+        let Some(aabb) = mesh_bounds.get(&mesh.0)
+            .or_else(|| meshes.get(&mesh.0).and_then(|mesh| mesh.compute_aabb())) else { continue; };
+        let root = shown_root(child_of.parent(), &roots, &parents);
+        fit_model(&mut commands, aabb, &mut transform, fit_mesh, root, &roots, &mut camera);
+    }
+}
+
+fn fit_meshes_when_loaded(
+    mut commands: Commands,
+    mut fit: Query<(Entity, &Mesh3d, &mut Transform, &FitMesh, &ChildOf), With<FitWhenLoaded>>,
+    roots: Query<(&ShownNode, &GlobalTransform)>,
+    parents: Query<&ChildOf>,
+    mesh_bounds: Res<MeshBounds>,
+    mut camera: Query<(&mut PanOrbitCamera, &mut Projection)>,
+) {
+    for (entity, mesh, mut transform, fit_mesh, child_of) in &mut fit {
+        let Some(aabb) = mesh_bounds.get(&mesh.0) else { continue; };
+        let root = shown_root(child_of.parent(), &roots, &parents);
+        fit_model(&mut commands, aabb, &mut transform, fit_mesh, root, &roots, &mut camera);
+        commands.entity(entity).remove::<FitWhenLoaded>();
+    }
+}
+
+/// Fits a glTF scene once spawned, which might happen after the loading screen went away, using
+/// the bounding box of all the meshes it contains.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn fit_scene(
+    ready: On<SceneInstanceReady>,
+    mut commands: Commands,
+    mut fit: Query<(&mut Transform, &FitMesh, &ChildOf)>,
+    scene_nodes: Query<(&Transform, &ChildOf, Option<&Mesh3d>), Without<FitMesh>>,
+    children: Query<&Children>,
+    roots: Query<(&ShownNode, &GlobalTransform)>,
+    parents: Query<&ChildOf>,
+    mesh_bounds: Res<MeshBounds>,
+    meshes: Res<Assets<Mesh>>,
+    mut camera: Query<(&mut PanOrbitCamera, &mut Projection)>,
+) {
+    let scene = ready.event().event_target();
+    let Ok((mut transform, fit_mesh, child_of)) = fit.get_mut(scene) else { return; };
+    commands.entity(scene).remove::<FitWhenLoaded>();
+
+    // the scene might contain no meshes
+    let Some(aabb) = scene_aabb(scene, &scene_nodes, &children, &mesh_bounds, &meshes) else { return; };
+    let root = shown_root(child_of.parent(), &roots, &parents);
+    fit_model(&mut commands, aabb, &mut transform, fit_mesh, root, &roots, &mut camera);
+}
+
+/// Placed along with [`FitMesh`] on models whose rotation changed after they were fitted, e.g.
+/// when laid flat, to fit them again. It is removed once fitted.
+#[derive(Component)]
+pub struct Refit;
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn refit_models(
+    mut commands: Commands,
+    mut fit: Query<(Entity, Option<&Mesh3d>, &mut Transform, &FitMesh, &ChildOf), With<Refit>>,
+    scene_nodes: Query<(&Transform, &ChildOf, Option<&Mesh3d>), Without<FitMesh>>,
+    children: Query<&Children>,
+    roots: Query<(&ShownNode, &GlobalTransform)>,
+    parents: Query<&ChildOf>,
+    mesh_bounds: Res<MeshBounds>,
+    meshes: Res<Assets<Mesh>>,
+    mut camera: Query<(&mut PanOrbitCamera, &mut Projection)>,
+) {
+    for (entity, mesh, mut transform, fit_mesh, child_of) in &mut fit {
+        let aabb = match mesh {
+            Some(mesh) => mesh_bounds.get(&mesh.0),
+            None => scene_aabb(entity, &scene_nodes, &children, &mesh_bounds, &meshes),
+        };
+        let Some(aabb) = aabb else { continue; };
+        let root = shown_root(child_of.parent(), &roots, &parents);
+        fit_model(&mut commands, aabb, &mut transform, fit_mesh, root, &roots, &mut camera);
+        commands.entity(entity).remove::<Refit>();
+    }
+}
+
+/// The bounding box of all the meshes of a scene, in the coordinates of the scene.
+#[allow(clippy::type_complexity)]
+fn scene_aabb(
+    scene: Entity,
+    scene_nodes: &Query<(&Transform, &ChildOf, Option<&Mesh3d>), Without<FitMesh>>,
+    children: &Query<&Children>,
+    mesh_bounds: &MeshBounds,
+    meshes: &Assets<Mesh>,
+) -> Option<Aabb> {
+    let (mut min, mut max) = (Vec3::INFINITY, Vec3::NEG_INFINITY);
+    for entity in children.iter_descendants(scene) {
+        let Ok((_, _, Some(mesh))) = scene_nodes.get(entity) else { continue; };
+        let Some(aabb) = mesh_bounds.get(&mesh.0)
+            .or_else(|| meshes.get(&mesh.0).and_then(|mesh| mesh.compute_aabb())) else { continue; };
+
+        let mesh_to_scene = mesh_to_model(entity, scene, scene_nodes);
+        for corner in 0..8 {
+            let sign = Vec3::new(
+                if corner & 1 == 0 { -1. } else { 1. },
+                if corner & 2 == 0 { -1. } else { 1. },
+                if corner & 4 == 0 { -1. } else { 1. },
+            );
+            let point = mesh_to_scene.transform_point3(Vec3::from(aabb.center) + sign * Vec3::from(aabb.half_extents));
+            min = min.min(point);
+            max = max.max(point);
+        }
+    }
+    (!min.cmpgt(max).any()).then(|| Aabb::from_min_max(min, max))
+}
+
+/// The node a fitted model belongs to: its parent, or the parent of the pivot it turns on.
+fn shown_root(parent: Entity, roots: &Query<(&ShownNode, &GlobalTransform)>, parents: &Query<&ChildOf>) -> Entity {
+    if roots.contains(parent) { parent } else { parents.get(parent).map_or(parent, ChildOf::parent) }
+}
+
+/// Places a model with the given bounding box at the center of its root, and records its size.
+fn fit_model(
+    commands: &mut Commands,
+    aabb: Aabb,
+    transform: &mut Transform,
+    fit_mesh: &FitMesh,
+    root: Entity,
+    roots: &Query<(&ShownNode, &GlobalTransform)>,
+    camera: &mut Query<(&mut PanOrbitCamera, &mut Projection)>,
+) {
+    let Ok((shown_node, root_transform)) = roots.get(root) else { return; };
+    let center = Vec3::from(aabb.center);
+    let size = 2. * Vec3::from(aabb.half_extents);
+
+    // mesh coordinates are multiplied by `scale` and then by the scale of the root
+    let scale = if fit_mesh.normalize { 1. / size.max_element().max(f32::EPSILON) } else { 1. };
+    transform.scale = Vec3::splat(scale);
+    transform.translation = -(transform.rotation * (scale * center));
+
+    let mm_per_unit = shown_node.0.upgrade()
+        .map(|node| node.units().millimeters())
+        .unwrap_or(1.);
+    let root_scale = root_transform.to_scale_rotation_translation().0.max_element();
+    commands.entity(root).insert(ModelDimensions {
+        size_mm: (transform.rotation * size).abs() * mm_per_unit,
+        mm_per_world_unit: mm_per_unit / (scale * root_scale),
+    });
+
+    if !fit_mesh.normalize {
+        // move the camera so that the whole bounding sphere is in view
+        let radius = (size.length() / 2.).max(f32::EPSILON);
+        for (mut camera, mut projection) in camera.iter_mut() {
+            camera.target_focus = Vec3::ZERO;
+            camera.target_radius = 2.5 * radius;
+            camera.zoom_lower_limit = 0.1 * radius;
+            camera.zoom_upper_limit = Some(20. * radius);
+            if let Projection::Perspective(perspective) = projection.as_mut() {
+                perspective.near = 0.01 * radius;
+                perspective.far = 100. * radius;
+            }
+        }
+    }
+}
+
+/// The overlay showing the size of the leaf entity `target`.
+#[derive(Component)]
+pub struct ScaleBar {
+    pub target: Entity,
+}
+
+#[derive(Component)]
+struct ScaleBarLine;
+
+#[derive(Component)]
+struct ScaleBarText;
+
+/// The size of the bounding box, above the scale bar.
+#[derive(Component)]
+struct ScaleBarSize;
+
+#[allow(clippy::type_complexity)]
+fn spawn_scale_bar(
+    mut commands: Commands,
+    new_leaves: Query<(Entity, &ModelDimensions), (Added<ModelDimensions>, Without<GridItem>)>,
+) {
+    for (entity, dimensions) in &new_leaves {
+        commands.spawn((
+            ScaleBar { target: entity },
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(12.),
+                right: Val::Px(12.),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::End,
+                row_gap: Val::Px(4.),
+                ..default()
+            },
+            VisualizationComponents,
+            children![
+                (
+                    ScaleBarSize,
+                    Text::new(size_text(dimensions.size_mm)),
+                    TextFont { font_size: 14., ..default() },
+                    TextColor(Color::WHITE),
+                ),
+                (
+                    ScaleBarLine,
+                    Node {
+                        height: Val::Px(4.),
+                        ..default()
+                    },
+                    BackgroundColor(Color::WHITE),
+                ),
+                (
+                    ScaleBarText,
+                    Text::default(),
+                    TextFont { font_size: 12., ..default() },
+                    TextColor(Color::from(GRAY_400)),
+                ),
+            ],
+        ));
+    }
+}
+
+fn despawn_orphan_scale_bars(
+    mut commands: Commands,
+    scale_bars: Query<(Entity, &ScaleBar)>,
+    leaves: Query<(), With<ModelDimensions>>,
+) {
+    for (entity, scale_bar) in &scale_bars {
+        if !leaves.contains(scale_bar.target) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Updates the size of the bounding box when the model is turned, e.g. when laid flat.
+#[allow(clippy::type_complexity)]
+fn update_scale_bar_size(
+    scale_bars: Query<&ScaleBar>,
+    changed: Query<&ModelDimensions, (Changed<ModelDimensions>, Without<GridItem>)>,
+    mut text: Query<&mut Text, With<ScaleBarSize>>,
+) {
+    let Some(dimensions) = scale_bars.iter().find_map(|scale_bar| changed.get(scale_bar.target).ok()) else {
+        return;
+    };
+    text.iter_mut().for_each(|mut text| text.0 = size_text(dimensions.size_mm));
+}
+
+/// The width, depth and height of the model.
+fn size_text(size_mm: Vec3) -> String {
+    format!("{} × {} × {}", format_length(size_mm.x), format_length(size_mm.z), format_length(size_mm.y))
+}
+
+/// Keeps the scale bar as long as a round length on the model, measured where the camera is
+/// looking at, so that it follows zooming.
+fn update_scale_bar(
+    scale_bars: Query<&ScaleBar>,
+    leaves: Query<&ModelDimensions>,
+    camera: Query<(&Camera, &GlobalTransform, &PanOrbitCamera)>,
+    mut line: Query<&mut Node, With<ScaleBarLine>>,
+    mut text: Query<&mut Text, With<ScaleBarText>>,
+) {
+    let Some(dimensions) = scale_bars.iter().find_map(|scale_bar| leaves.get(scale_bar.target).ok()) else {
+        return;
+    };
+    let Ok((camera, camera_transform, pan_orbit)) = camera.single() else { return; };
+    let (Ok(start), Ok(end)) = (
+        camera.world_to_viewport(camera_transform, pan_orbit.focus),
+        camera.world_to_viewport(camera_transform, pan_orbit.focus + camera_transform.right() * 1.),
+    ) else { return; };
+
+    let pixels_per_mm = start.distance(end) / dimensions.mm_per_world_unit;
+    if pixels_per_mm <= 0. {
+        return;
+    }
+    let length_mm = round_length(120. / pixels_per_mm);
+    line.iter_mut().for_each(|mut node| node.width = Val::Px(length_mm * pixels_per_mm));
+    text.iter_mut().for_each(|mut text| text.0 = format_length(length_mm));
+}
+
+/// Rounds down to 1, 2 or 5 times a power of ten.
+fn round_length(length: f32) -> f32 {
+    let magnitude = 10f32.powf(length.log10().floor());
+    match length / magnitude {
+        ratio if ratio >= 5. => 5. * magnitude,
+        ratio if ratio >= 2. => 2. * magnitude,
+        _ => magnitude,
+    }
+}
+
+/// Formats a length in millimeters, switching to meters for big lengths.
+pub fn format_length(length_mm: f32) -> String {
+    match length_mm {
+        length if length >= 1000. => format!("{} m", round_to_significant(length / 1000.)),
+        length => format!("{} mm", round_to_significant(length)),
+    }
+}
+
+pub fn round_to_significant(value: f32) -> f32 {
+    let decimals = 2 - value.abs().log10().floor().clamp(-3., 2.) as i32;
+    let factor = 10f32.powi(decimals);
+    (value * factor).round() / factor
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Weak;
+
+    use bevy::prelude::*;
+
+    use crate::{
+        dimensions::{format_length, round_length, track_current_leaf, CurrentLeaf, ModelDimensions, TrackCurrentLeaf},
+        GridItem, ShownNode,
+    };
+
+    /// The leaves seen by a system following [`CurrentLeaf`], once per change.
+    #[derive(Resource, Default)]
+    struct FollowedLeaves(Vec<Option<Entity>>);
+
+    #[test]
+    fn test_track_current_leaf() {
+        let mut world = World::new();
+        world.init_resource::<CurrentLeaf>();
+        world.init_resource::<FollowedLeaves>();
+        let mut schedule = Schedule::default();
+        schedule.add_systems((
+            track_current_leaf.in_set(TrackCurrentLeaf),
+            (|current_leaf: Res<CurrentLeaf>, mut followed: ResMut<FollowedLeaves>| followed.0.push(current_leaf.0))
+                .after(TrackCurrentLeaf)
+                .run_if(resource_changed::<CurrentLeaf>),
+        ));
+        let dimensions = ModelDimensions { size_mm: Vec3::ONE, mm_per_world_unit: 1. };
+        schedule.run(&mut world);
+
+        // grid items are not followed, and neither are leaves until their model is fitted
+        world.spawn((ShownNode(Weak::new()), GridItem { child_index: 0 }, dimensions));
+        let leaf = world.spawn(ShownNode(Weak::new())).id();
+        schedule.run(&mut world);
+        world.entity_mut(leaf).insert(dimensions);
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        // turning the model changes its dimensions
+        world.get_mut::<ModelDimensions>(leaf).unwrap().size_mm = Vec3::new(1., 2., 3.);
+        schedule.run(&mut world);
+        world.despawn(leaf);
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<FollowedLeaves>().0, vec![None, Some(leaf), Some(leaf), None]);
+    }
+
+    #[test]
+    fn test_round_length() {
+        assert_eq!(round_length(120.), 100.);
+        assert_eq!(round_length(0.3), 0.2);
+        assert_eq!(round_length(7.), 5.);
+    }
+
+    #[test]
+    fn test_format_length() {
+        assert_eq!(format_length(48.12), "48.1 mm");
+        assert_eq!(format_length(123.45), "123 mm");
+        assert_eq!(format_length(0.5), "0.5 mm");
+        assert_eq!(format_length(2540.), "2.54 m");
+    }
+}
@@ -0,0 +1,133 @@
+//! Shows what went wrong in place of the visualization, instead of leaving a blank canvas.
+
+use bevy::{color::palettes::tailwind::{GRAY_900, RED_400}, prelude::*};
+
+use crate::{loading::{LoadingData, LoadingState}, MeshTreeRes};
+
+pub struct ErrorScreenPlugin;
+
+impl Plugin for ErrorScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(LoadingState::Ready), show_failed_assets)
+            .add_systems(OnEnter(LoadingState::Loading), clear_error_cards);
+    }
+}
+
+// Marker component for easier deletion of entities.
+#[derive(Component)]
+pub struct ErrorScreen;
+
+// Marker component for the list of assets that failed to load.
+#[derive(Component)]
+pub struct ErrorCards;
+
+/// Covers the whole window with `title` and a longer `message` explaining the error.
+pub fn show_error_screen(commands: &mut Commands, title: &str, message: &str) {
+    commands.spawn((
+        ErrorScreen,
+        Node {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            flex_direction: FlexDirection::Column,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            row_gap: Val::Px(12.),
+            padding: UiRect::all(Val::Px(24.)),
+            ..default()
+        },
+        BackgroundColor(Color::from(GRAY_900)),
+        GlobalZIndex(i32::MAX),
+        children![
+            (
+                Text::new(title),
+                TextFont { font_size: 28., ..default() },
+                TextColor(Color::from(RED_400)),
+            ),
+            (
+                Text::new(message),
+                TextFont { font_size: 16., ..default() },
+                TextColor(Color::WHITE),
+                TextLayout::new_with_justify(Justify::Center),
+            ),
+        ],
+    ));
+}
+
+/// Replaces the meshes that could not be loaded with a placeholder, so that the rest of the
+/// subtree can still be used, and lists the failures in a corner of the window.
+fn show_failed_assets(
+    mut commands: Commands,
+    loading_data: Res<LoadingData>,
+    mesh_tree: Option<Res<MeshTreeRes>>,
+    meshes: Query<(Entity, &Mesh3d)>,
+    scenes: Query<(Entity, &SceneRoot)>,
+    error_screen: Query<(), With<ErrorScreen>>,
+) {
+    let failed_assets = loading_data.failed_assets();
+    if failed_assets.is_empty() || !error_screen.is_empty() {
+        // nothing to show, or the error is already covering the whole window
+        return;
+    }
+
+    if let Some(mesh_tree) = mesh_tree {
+        for (entity, mesh) in &meshes {
+            if failed_assets.iter().any(|failed| failed.id == mesh.id().untyped()) {
+                commands.entity(entity).insert((
+                    Mesh3d(mesh_tree.placeholder_mesh.clone()),
+                    MeshMaterial3d(mesh_tree.error_matl.clone()),
+                ));
+            }
+        }
+        for (entity, scene) in &scenes {
+            if failed_assets.iter().any(|failed| failed.id == scene.id().untyped()) {
+                commands.entity(entity).remove::<SceneRoot>().insert((
+                    Mesh3d(mesh_tree.placeholder_mesh.clone()),
+                    MeshMaterial3d(mesh_tree.error_matl.clone()),
+                ));
+            }
+        }
+    }
+
+    commands.spawn((
+        ErrorCards,
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(12.),
+            bottom: Val::Px(12.),
+            max_width: Val::Px(420.),
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(8.),
+            ..default()
+        },
+    )).with_children(|cards| {
+        for failed in failed_assets {
+            cards.spawn((
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(4.),
+                    padding: UiRect::all(Val::Px(10.)),
+                    ..default()
+                },
+                BackgroundColor(Color::from(GRAY_900).with_alpha(0.9)),
+                children![
+                    (
+                        Text::new(format!("Could not load {}", failed.path)),
+                        TextFont { font_size: 14., ..default() },
+                        TextColor(Color::from(RED_400)),
+                    ),
+                    (
+                        Text::new(failed.reason.clone()),
+                        TextFont { font_size: 12., ..default() },
+                        TextColor(Color::WHITE),
+                    ),
+                ],
+            ));
+        }
+    });
+}
+
+fn clear_error_cards(mut commands: Commands, cards: Query<Entity, With<ErrorCards>>) {
+    for entity in &cards {
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,258 @@
+//! Loads meshes from STL, OBJ, PLY and 3MF files, keeping the colors they contain.
+//!
+//! All of them are handled by [`MeshFormatLoader`], which chooses the parser from the extension
+//! of the file, from the `format` field in the manifest, or from the content of the file, and
+//! records the size of the file. glTF files are instead loaded as scenes by Bevy, since they can
+//! contain materials and many nodes.
+
+mod obj;
+mod ply;
+mod three_mf;
+
+use std::sync::{Arc, Mutex};
+
+use bevy::{
+    asset::{io::{Reader, VecReader}, AssetLoader, AssetPath, LoadContext, RenderAssetUsages},
+    color::{LinearRgba, Srgba},
+    mesh::{Indices, PrimitiveTopology},
+    platform::collections::HashMap,
+    prelude::*,
+};
+use bevy_stl::{StlError, StlLoader};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub struct FormatsPlugin;
+
+impl Plugin for FormatsPlugin {
+    fn build(&self, app: &mut App) {
+        // the only loader of meshes, so it is also the one used for urls without a known
+        // extension, which it can recognize from their content
+        let file_sizes = FileSizes::default();
+        app.insert_resource(file_sizes.clone())
+            .register_asset_loader(MeshFormatLoader { file_sizes });
+    }
+}
+
+/// The size in bytes of each mesh file loaded, shared with the loader, which runs on another thread.
+#[derive(Resource, Clone, Default)]
+pub struct FileSizes(Arc<Mutex<HashMap<AssetPath<'static>, usize>>>);
+
+impl FileSizes {
+    pub fn get(&self, mesh: &Handle<Mesh>) -> Option<usize> {
+        let path = mesh.path()?;
+        self.0.lock().ok()?.get(path).copied()
+    }
+
+    fn insert(&self, path: &AssetPath<'static>, size: usize) {
+        if let Ok(mut sizes) = self.0.lock() {
+            sizes.insert(path.clone(), size);
+        }
+    }
+}
+
+/// The file formats meshes can be loaded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MeshFormat {
+    Stl,
+    Obj,
+    Ply,
+    #[serde(rename = "3mf")]
+    ThreeMf,
+    #[serde(alias = "glb")]
+    Gltf,
+}
+
+impl MeshFormat {
+    pub fn from_extension(extension: &str) -> Option<MeshFormat> {
+        match extension.to_ascii_lowercase().as_str() {
+            "stl" => Some(MeshFormat::Stl),
+            "obj" => Some(MeshFormat::Obj),
+            "ply" => Some(MeshFormat::Ply),
+            "3mf" => Some(MeshFormat::ThreeMf),
+            "gltf" | "glb" => Some(MeshFormat::Gltf),
+            _ => None,
+        }
+    }
+
+    /// The format suggested by the extension of the file in `url`, ignoring query and fragment.
+    pub fn from_url(url: &str) -> Option<MeshFormat> {
+        let path = url.split(['?', '#']).next().unwrap_or(url);
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        file_name.rsplit_once('.').and_then(|(_, extension)| MeshFormat::from_extension(extension))
+    }
+
+    /// Guesses the format from the first bytes of a file, falling back to binary STL, which has
+    /// no recognizable header.
+    pub fn detect(bytes: &[u8]) -> MeshFormat {
+        if bytes.starts_with(b"ply") {
+            MeshFormat::Ply
+        } else if bytes.starts_with(b"PK\x03\x04") {
+            MeshFormat::ThreeMf
+        } else if bytes.starts_with(b"solid") {
+            MeshFormat::Stl
+        } else if bytes.split(|&byte| byte == b'\n').take(1000).any(|line| line.starts_with(b"v ")) {
+            MeshFormat::Obj
+        } else {
+            MeshFormat::Stl
+        }
+    }
+}
+
+/// What a node is shown with: either a single mesh, or a glTF scene with its own materials.
+pub enum Model {
+    Mesh(Handle<Mesh>),
+    Scene(Handle<Scene>),
+}
+
+/// Begins loading the model at `url`. The `format` from the manifest is only needed when the url
+/// does not end with a known extension (e.g. `/download?id=42`), since the `AssetServer` chooses
+/// the loader from the extension. This is not possible for glTF files, whose urls need to end
+/// with `.gltf` or `.glb`.
+pub fn load_model(asset_server: &AssetServer, url: &str, format: Option<MeshFormat>) -> Model {
+    match (format, MeshFormat::from_url(url)) {
+        (_, Some(MeshFormat::Gltf)) => Model::Scene(asset_server.load(GltfAssetLabel::Scene(0).from_asset(url.to_string()))),
+        (Some(MeshFormat::Gltf), None) => {
+            console_log!("Cannot load {url} as glTF, its url does not end with .gltf or .glb");
+            Model::Mesh(asset_server.load(url.to_string()))
+        },
+        (Some(format), None) => Model::Mesh(asset_server.load_with_settings(url.to_string(), move |settings: &mut MeshFormatSettings| {
+            settings.format = Some(format);
+        })),
+        (Some(format), Some(extension_format)) if format != extension_format => {
+            console_log!("Ignoring format {format:?} of {url}, its extension says {extension_format:?}");
+            Model::Mesh(asset_server.load(url.to_string()))
+        },
+        _ => Model::Mesh(asset_server.load(url.to_string())),
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct MeshFormatSettings {
+    /// the format to parse the file as, instead of guessing it from its extension or content
+    pub format: Option<MeshFormat>,
+}
+
+#[derive(TypePath)]
+pub struct MeshFormatLoader {
+    file_sizes: FileSizes,
+}
+
+#[derive(Error, Debug)]
+pub enum MeshFormatError {
+    #[error("Failed to read mesh: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid STL: {0}")]
+    Stl(#[from] StlError),
+    #[error("Invalid OBJ: {0}")]
+    Obj(#[from] tobj::LoadError),
+    #[error("Invalid PLY: {0}")]
+    Ply(String),
+    #[error("Invalid 3MF: {0}")]
+    ThreeMf(String),
+    #[error("glTF files can only be loaded as scenes")]
+    NotAMesh,
+}
+
+impl AssetLoader for MeshFormatLoader {
+    type Asset = Mesh;
+    type Settings = MeshFormatSettings;
+    type Error = MeshFormatError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        settings: &MeshFormatSettings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        self.file_sizes.insert(load_context.path(), bytes.len());
+        let format = settings.format
+            .or_else(|| MeshFormat::from_url(&load_context.path().to_string()))
+            .unwrap_or_else(|| MeshFormat::detect(&bytes));
+
+        let triangles = match format {
+            MeshFormat::Stl => {
+                let mut reader = VecReader::new(bytes);
+                return Ok(StlLoader.load(&mut reader, &(), load_context).await?);
+            },
+            MeshFormat::Obj => obj::parse(&bytes)?,
+            MeshFormat::Ply => ply::parse(&bytes).map_err(MeshFormatError::Ply)?,
+            MeshFormat::ThreeMf => three_mf::parse(&bytes).map_err(MeshFormatError::ThreeMf)?,
+            MeshFormat::Gltf => return Err(MeshFormatError::NotAMesh),
+        };
+        Ok(triangles.into_mesh())
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["stl", "obj", "ply", "3mf"]
+    }
+}
+
+/// The triangles read from a mesh file, before being turned into a Bevy [`Mesh`].
+#[derive(Debug, Default)]
+pub struct Triangles {
+    pub positions: Vec<[f32; 3]>,
+    /// the linear color of each position, if the file has any
+    pub colors: Option<Vec<[f32; 4]>>,
+    /// three indices into `positions` for each triangle
+    pub indices: Vec<u32>,
+}
+
+impl Triangles {
+    /// Builds a mesh with one normal per triangle, like the one read from STL files, so that all
+    /// formats are shaded the same way. Vertex colors are multiplied by the color of the
+    /// material, so they show unchanged with the default white material.
+    pub fn into_mesh(self) -> Mesh {
+        let corner_count = self.indices.len() / 3 * 3;
+        let corners = &self.indices[..corner_count];
+        let positions: Vec<[f32; 3]> = corners.iter().map(|&index| self.positions[index as usize]).collect();
+        let normals: Vec<[f32; 3]> = positions.chunks_exact(3)
+            .flat_map(|triangle| {
+                let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(Vec3::from);
+                [(b - a).cross(c - a).normalize_or_zero().to_array(); 3]
+            })
+            .collect();
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0f32, 0.]; corner_count])
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+            .with_inserted_indices(Indices::U32((0..corner_count as u32).collect()));
+        if let Some(colors) = self.colors {
+            let colors: Vec<[f32; 4]> = corners.iter().map(|&index| colors[index as usize]).collect();
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        }
+        mesh
+    }
+}
+
+/// Converts a color as stored in files, i.e. in sRGB space, to the linear space of vertex colors.
+pub fn srgb_to_linear(color: [f32; 4]) -> [f32; 4] {
+    LinearRgba::from(Srgba::from_f32_array(color)).to_f32_array()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formats::MeshFormat;
+
+    #[test]
+    fn test_from_url() {
+        assert_eq!(MeshFormat::from_url("/models/part.3MF"), Some(MeshFormat::ThreeMf));
+        assert_eq!(MeshFormat::from_url("https://host/a.obj?version=2"), Some(MeshFormat::Obj));
+        assert_eq!(MeshFormat::from_url("https://host/get?file=a.ply"), None);
+        assert_eq!(MeshFormat::from_url("https://host.stl/download"), None);
+        assert_eq!(MeshFormat::from_url("/scenes/robot.glb"), Some(MeshFormat::Gltf));
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(MeshFormat::detect(b"ply\nformat ascii 1.0\n"), MeshFormat::Ply);
+        assert_eq!(MeshFormat::detect(b"PK\x03\x04rest"), MeshFormat::ThreeMf);
+        assert_eq!(MeshFormat::detect(b"solid cube\n"), MeshFormat::Stl);
+        assert_eq!(MeshFormat::detect(b"# comment\nv 0 0 0\n"), MeshFormat::Obj);
+        assert_eq!(MeshFormat::detect(&[0; 84]), MeshFormat::Stl);
+    }
+}
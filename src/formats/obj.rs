@@ -0,0 +1,51 @@
+//! Wavefront OBJ files, of which only the geometry and the vertex colors are used, since the
+//! materials are in separate MTL files.
+
+use std::io::Cursor;
+
+use crate::formats::{srgb_to_linear, MeshFormatError, Triangles};
+
+pub fn parse(bytes: &[u8]) -> Result<Triangles, MeshFormatError> {
+    let (models, _) = tobj::load_obj_buf(
+        &mut Cursor::new(bytes),
+        &tobj::GPU_LOAD_OPTIONS,
+        |_| Err(tobj::LoadError::OpenFileFailed),
+    )?;
+
+    // all the objects are merged into one mesh, with white vertices for the objects without
+    // colors if some other object has them
+    let has_colors = models.iter().any(|model| !model.mesh.vertex_color.is_empty());
+    let mut triangles = Triangles {
+        colors: has_colors.then(Vec::new),
+        ..Triangles::default()
+    };
+    for model in models {
+        let mesh = model.mesh;
+        let offset = triangles.positions.len() as u32;
+        let vertex_count = mesh.positions.len() / 3;
+        triangles.positions.extend(mesh.positions.chunks_exact(3).map(|p| [p[0], p[1], p[2]]));
+        if let Some(colors) = &mut triangles.colors {
+            if mesh.vertex_color.len() == mesh.positions.len() {
+                colors.extend(mesh.vertex_color.chunks_exact(3).map(|c| srgb_to_linear([c[0], c[1], c[2], 1.])));
+            } else {
+                colors.extend(std::iter::repeat_n([1.; 4], vertex_count));
+            }
+        }
+        triangles.indices.extend(mesh.indices.iter().map(|index| index + offset));
+    }
+    Ok(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formats::obj::parse;
+
+    #[test]
+    fn test_parse() {
+        let obj = b"o quad\nv 0 0 0 1 0 0\nv 1 0 0 1 0 0\nv 1 1 0 1 0 0\nv 0 1 0 1 0 0\nf 1 2 3 4\n";
+        let triangles = parse(obj).unwrap();
+        assert_eq!(triangles.positions.len(), 4);
+        assert_eq!(triangles.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(triangles.colors.unwrap()[0], [1., 0., 0., 1.]);
+    }
+}
@@ -0,0 +1,294 @@
+//! Polygon File Format, either ASCII or binary, as written by 3D scanners, which often store
+//! the color of each vertex.
+
+use crate::formats::{srgb_to_linear, Triangles};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Result<ScalarType, String> {
+        match name {
+            "char" | "int8" => Ok(ScalarType::I8),
+            "uchar" | "uint8" => Ok(ScalarType::U8),
+            "short" | "int16" => Ok(ScalarType::I16),
+            "ushort" | "uint16" => Ok(ScalarType::U16),
+            "int" | "int32" => Ok(ScalarType::I32),
+            "uint" | "uint32" => Ok(ScalarType::U32),
+            "float" | "float32" => Ok(ScalarType::F32),
+            "double" | "float64" => Ok(ScalarType::F64),
+            _ => Err(format!("unknown property type '{name}'")),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+
+    /// The factor bringing a color component of this type in the 0..1 range.
+    fn color_scale(&self) -> f32 {
+        match self {
+            ScalarType::U8 | ScalarType::I8 => 1. / 255.,
+            ScalarType::U16 | ScalarType::I16 => 1. / 65535.,
+            _ => 1.,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Property {
+    Scalar { name: String, scalar_type: ScalarType },
+    List { name: String, count_type: ScalarType, item_type: ScalarType },
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Reads the values of the properties one at a time, whatever the encoding.
+struct BodyReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    encoding: Encoding,
+}
+
+impl BodyReader<'_> {
+    fn read(&mut self, scalar_type: ScalarType) -> Result<f64, String> {
+        if self.encoding == Encoding::Ascii {
+            let rest = &self.data[self.position..];
+            let start = rest.iter().position(|byte| !byte.is_ascii_whitespace()).ok_or("unexpected end of file")?;
+            let length = rest[start..].iter().position(|byte| byte.is_ascii_whitespace()).unwrap_or(rest.len() - start);
+            self.position += start + length;
+            let token = std::str::from_utf8(&rest[start..start + length]).map_err(|_| "invalid number")?;
+            return token.parse().map_err(|_| format!("invalid number '{token}'"));
+        }
+
+        let size = scalar_type.size();
+        let bytes = self.data.get(self.position..self.position + size).ok_or("unexpected end of file")?;
+        self.position += size;
+        let mut buffer = [0u8; 8];
+        buffer[..size].copy_from_slice(bytes);
+        if self.encoding == Encoding::BinaryBigEndian {
+            buffer[..size].reverse();
+        }
+        Ok(match scalar_type {
+            ScalarType::I8 => i8::from_le_bytes([buffer[0]]) as f64,
+            ScalarType::U8 => buffer[0] as f64,
+            ScalarType::I16 => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            ScalarType::U16 => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            ScalarType::I32 => i32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+            ScalarType::U32 => u32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+            ScalarType::F32 => f32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+            ScalarType::F64 => f64::from_le_bytes(buffer),
+        })
+    }
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Triangles, String> {
+    let (encoding, elements, body_start) = parse_header(bytes)?;
+    let mut reader = BodyReader { data: bytes, position: body_start, encoding };
+    let mut triangles = Triangles::default();
+
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => read_vertices(&mut reader, element, &mut triangles)?,
+            "face" => read_faces(&mut reader, element, &mut triangles)?,
+            _ => for _ in 0..element.count {
+                for property in &element.properties {
+                    read_property(&mut reader, property)?;
+                }
+            },
+        }
+    }
+
+    let vertex_count = triangles.positions.len() as u32;
+    if let Some(index) = triangles.indices.iter().find(|&&index| index >= vertex_count) {
+        return Err(format!("face refers to vertex {index}, but there are only {vertex_count} vertices"));
+    }
+    Ok(triangles)
+}
+
+fn parse_header(bytes: &[u8]) -> Result<(Encoding, Vec<Element>, usize), String> {
+    const END_HEADER: &[u8] = b"end_header";
+    let end = bytes.windows(END_HEADER.len())
+        .position(|window| window == END_HEADER)
+        .ok_or("missing end_header")?;
+    let body_start = bytes[end..].iter().position(|&byte| byte == b'\n').map(|newline| end + newline + 1)
+        .unwrap_or(bytes.len());
+    let header = std::str::from_utf8(&bytes[..end]).map_err(|_| "header is not text")?;
+
+    let mut lines = header.lines();
+    if lines.next().map(str::trim) != Some("ply") {
+        return Err("missing 'ply' magic number".to_string());
+    }
+
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", "ascii", _] => encoding = Some(Encoding::Ascii),
+            ["format", "binary_little_endian", _] => encoding = Some(Encoding::BinaryLittleEndian),
+            ["format", "binary_big_endian", _] => encoding = Some(Encoding::BinaryBigEndian),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| format!("invalid count of '{name}'"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_type, item_type, name] => elements.last_mut()
+                .ok_or("property before any element")?
+                .properties.push(Property::List {
+                    name: name.to_string(),
+                    count_type: ScalarType::parse(count_type)?,
+                    item_type: ScalarType::parse(item_type)?,
+                }),
+            ["property", scalar_type, name] => elements.last_mut()
+                .ok_or("property before any element")?
+                .properties.push(Property::Scalar { name: name.to_string(), scalar_type: ScalarType::parse(scalar_type)? }),
+            ["comment", ..] | ["obj_info", ..] | [] => {},
+            _ => return Err(format!("unexpected header line '{line}'")),
+        }
+    }
+    Ok((encoding.ok_or("missing format")?, elements, body_start))
+}
+
+/// Reads a property, returning all its values, i.e. just one for scalars.
+fn read_property(reader: &mut BodyReader, property: &Property) -> Result<Vec<f64>, String> {
+    match property {
+        Property::Scalar { scalar_type, .. } => Ok(vec![reader.read(*scalar_type)?]),
+        Property::List { count_type, item_type, .. } => {
+            let count = reader.read(*count_type)? as usize;
+            (0..count).map(|_| reader.read(*item_type)).collect()
+        },
+    }
+}
+
+fn read_vertices(reader: &mut BodyReader, element: &Element, triangles: &mut Triangles) -> Result<(), String> {
+    let find = |names: &[&str]| element.properties.iter().position(|property| {
+        matches!(property, Property::Scalar { name, .. } if names.contains(&name.as_str()))
+    });
+    let color_scale = |index: usize| match &element.properties[index] {
+        Property::Scalar { scalar_type, .. } => scalar_type.color_scale(),
+        Property::List { .. } => 1.,
+    };
+    let (Some(x), Some(y), Some(z)) = (find(&["x"]), find(&["y"]), find(&["z"])) else {
+        return Err("vertices without x, y and z".to_string());
+    };
+    let red = find(&["red", "r", "diffuse_red"]);
+    let green = find(&["green", "g", "diffuse_green"]);
+    let blue = find(&["blue", "b", "diffuse_blue"]);
+    let alpha = find(&["alpha", "a"]);
+    let color_channels = match (red, green, blue) {
+        (Some(red), Some(green), Some(blue)) => {
+            triangles.colors = Some(Vec::new());
+            Some([red, green, blue])
+        },
+        _ => None,
+    };
+
+    let mut values = vec![0.; element.properties.len()];
+    for _ in 0..element.count {
+        for (value, property) in values.iter_mut().zip(&element.properties) {
+            // lists in vertices are not used, only their first value is kept
+            *value = read_property(reader, property)?.first().copied().unwrap_or_default();
+        }
+        triangles.positions.push([values[x] as f32, values[y] as f32, values[z] as f32]);
+        if let (Some(channels), Some(colors)) = (color_channels, &mut triangles.colors) {
+            let [r, g, b] = channels.map(|index| values[index] as f32 * color_scale(index));
+            let a = alpha.map_or(1., |index| values[index] as f32 * color_scale(index));
+            colors.push(srgb_to_linear([r, g, b, a]));
+        }
+    }
+    Ok(())
+}
+
+fn read_faces(reader: &mut BodyReader, element: &Element, triangles: &mut Triangles) -> Result<(), String> {
+    let indices_property = element.properties.iter().position(|property| {
+        matches!(property, Property::List { name, .. } if name == "vertex_indices" || name == "vertex_index")
+    }).ok_or("faces without vertex_indices")?;
+
+    for _ in 0..element.count {
+        for (index, property) in element.properties.iter().enumerate() {
+            let values = read_property(reader, property)?;
+            if index == indices_property {
+                // polygons are split into a fan of triangles
+                for i in 2..values.len() {
+                    triangles.indices.extend([values[0], values[i - 1], values[i]].map(|value| value as u32));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formats::ply::parse;
+
+    #[test]
+    fn test_parse_ascii() {
+        let ply = b"ply\nformat ascii 1.0\ncomment made by hand\nelement vertex 4\n\
+            property float x\nproperty float y\nproperty float z\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+            0 0 0 255 0 0\n1 0 0 255 0 0\n1 1 0 0 0 255\n0 1 0 0 0 255\n4 0 1 2 3\n";
+        let triangles = parse(ply).unwrap();
+        assert_eq!(triangles.positions[2], [1., 1., 0.]);
+        assert_eq!(triangles.indices, vec![0, 1, 2, 0, 2, 3]);
+        let colors = triangles.colors.unwrap();
+        assert_eq!(colors[0], [1., 0., 0., 1.]);
+        assert_eq!(colors[3], [0., 0., 1., 1.]);
+    }
+
+    #[test]
+    fn test_parse_binary() {
+        let mut ply = b"ply\r\nformat binary_big_endian 1.0\r\nelement vertex 3\r\n\
+            property float x\r\nproperty float y\r\nproperty float z\r\n\
+            element face 1\r\nproperty list uchar uint vertex_index\r\nend_header\r\n".to_vec();
+        for vertex in [[0f32, 0., 0.], [2., 0., 0.], [0., 3., 0.]] {
+            vertex.iter().for_each(|value| ply.extend(value.to_be_bytes()));
+        }
+        ply.push(3);
+        [2u32, 1, 0].iter().for_each(|index| ply.extend(index.to_be_bytes()));
+
+        let triangles = parse(&ply).unwrap();
+        assert_eq!(triangles.positions, vec![[0., 0., 0.], [2., 0., 0.], [0., 3., 0.]]);
+        assert_eq!(triangles.indices, vec![2, 1, 0]);
+        assert!(triangles.colors.is_none());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nend_header\n0\n").is_err());
+        assert!(parse(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\n\
+            property float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0\n3 0 1 2\n").is_err());
+        // a header claiming far more colored vertices than the body holds must fail, not abort
+        assert!(parse(b"ply\nformat binary_little_endian 1.0\nelement vertex 4000000000000\nproperty float x\n\
+            property float y\nproperty float z\nproperty uchar red\nproperty uchar green\nproperty uchar blue\n\
+            end_header\n").is_err());
+    }
+}
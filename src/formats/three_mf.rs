@@ -0,0 +1,276 @@
+//! 3D Manufacturing Format, a zip archive with the model in XML, as written by modern slicers.
+//! Colors can be assigned to whole objects or to single triangles, through base materials or
+//! color groups, while textures are not supported.
+
+use std::{collections::HashMap, io::{Cursor, Read}};
+
+use bevy::{color::{ColorToComponents, Srgba}, math::Affine3A, prelude::Vec3};
+use roxmltree::{Document, Node};
+use zip::ZipArchive;
+
+use crate::formats::{srgb_to_linear, Triangles};
+
+/// Components referring to other objects nested deeper than this are rejected, in case of cycles.
+const MAX_COMPONENT_DEPTH: usize = 16;
+
+const DEFAULT_MODEL_PATH: &str = "3D/3dmodel.model";
+
+pub fn parse(bytes: &[u8]) -> Result<Triangles, String> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(|error| error.to_string())?;
+    let model_path = read_file(&mut archive, "_rels/.rels").ok()
+        .and_then(|rels| find_model_path(&rels))
+        .unwrap_or_else(|| DEFAULT_MODEL_PATH.to_string());
+    let model = read_file(&mut archive, &model_path)?;
+    parse_model(&model)
+}
+
+fn read_file(archive: &mut ZipArchive<Cursor<&[u8]>>, path: &str) -> Result<String, String> {
+    let mut file = archive.by_name(path.trim_start_matches('/'))
+        .map_err(|error| format!("cannot open '{path}': {error}"))?;
+    let mut content = String::new();
+    file.read_to_string(&mut content).map_err(|error| format!("cannot read '{path}': {error}"))?;
+    Ok(content)
+}
+
+/// The path of the model in the archive, as listed in the package relationships.
+fn find_model_path(rels: &str) -> Option<String> {
+    let document = Document::parse(rels).ok()?;
+    document.descendants()
+        .filter(|node| node.has_tag_name("Relationship"))
+        .find(|node| node.attribute("Type").is_some_and(|kind| kind.ends_with("/3dmodel")))
+        .and_then(|node| node.attribute("Target"))
+        .map(str::to_string)
+}
+
+/// The triangles of a mesh object, with positions already in millimeters.
+struct MeshObject {
+    positions: Vec<Vec3>,
+    /// the corners of each triangle, with their color if any
+    triangles: Vec<[(u32, Option<[f32; 4]>); 3]>,
+}
+
+enum Object {
+    Mesh(MeshObject),
+    Components(Vec<(String, Affine3A)>),
+}
+
+fn parse_model(xml: &str) -> Result<Triangles, String> {
+    let document = Document::parse(xml).map_err(|error| error.to_string())?;
+    let model = document.root_element();
+    let unit_scale = match model.attribute("unit").unwrap_or("millimeter") {
+        "micron" => 0.001,
+        "millimeter" => 1.,
+        "centimeter" => 10.,
+        "inch" => 25.4,
+        "foot" => 304.8,
+        "meter" => 1000.,
+        unit => return Err(format!("unknown unit '{unit}'")),
+    };
+
+    let resources = model.children().find(|node| node.has_tag_name("resources")).ok_or("missing resources")?;
+    // the colors of each property group, by id of the group
+    let mut property_groups: HashMap<&str, Vec<[f32; 4]>> = HashMap::new();
+    for group in resources.children().filter(Node::is_element) {
+        let (color_tag, color_attribute) = match group.tag_name().name() {
+            "basematerials" => ("base", "displaycolor"),
+            "colorgroup" => ("color", "color"),
+            _ => continue,
+        };
+        let colors = group.children()
+            .filter(|node| node.has_tag_name(color_tag))
+            .map(|node| node.attribute(color_attribute).map_or(Ok([1.; 4]), parse_color))
+            .collect::<Result<_, _>>()?;
+        property_groups.insert(group.attribute("id").unwrap_or_default(), colors);
+    }
+    let color = |group: Option<&str>, index: Option<&str>| -> Option<[f32; 4]> {
+        let colors = property_groups.get(group?)?;
+        colors.get(index?.parse::<usize>().ok()?).copied()
+    };
+
+    let mut objects = HashMap::new();
+    for object in resources.children().filter(|node| node.has_tag_name("object")) {
+        let id = object.attribute("id").ok_or("object without id")?;
+        if let Some(mesh) = object.children().find(|node| node.has_tag_name("mesh")) {
+            let vertices = mesh.children().find(|node| node.has_tag_name("vertices")).ok_or("mesh without vertices")?;
+            let positions = vertices.children()
+                .filter(|node| node.has_tag_name("vertex"))
+                .map(|vertex| {
+                    let [x, y, z] = ["x", "y", "z"].map(|axis| float_attribute(vertex, axis));
+                    Ok(Vec3::new(x?, y?, z?) * unit_scale)
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+
+            // the color of the object is used for the triangles without one
+            let object_group = object.attribute("pid");
+            let object_index = object.attribute("pindex");
+            let mut triangles = Vec::new();
+            for triangle in mesh.descendants().filter(|node| node.has_tag_name("triangle")) {
+                let group = triangle.attribute("pid").or(object_group);
+                let first_index = triangle.attribute("p1").or(object_index);
+                let mut corners = [(0, None); 3];
+                for (i, corner) in corners.iter_mut().enumerate() {
+                    let vertex: u32 = triangle.attribute(["v1", "v2", "v3"][i])
+                        .and_then(|value| value.parse().ok())
+                        .ok_or("triangle without vertices")?;
+                    if vertex as usize >= positions.len() {
+                        return Err(format!("triangle refers to vertex {vertex}, but there are only {} vertices", positions.len()));
+                    }
+                    // p2 and p3 give different colors to the corners, defaulting to p1
+                    let index = triangle.attribute(["p1", "p2", "p3"][i]).or(first_index);
+                    *corner = (vertex, color(group, index));
+                }
+                triangles.push(corners);
+            }
+            objects.insert(id, Object::Mesh(MeshObject { positions, triangles }));
+        } else if let Some(components) = object.children().find(|node| node.has_tag_name("components")) {
+            let components = components.children()
+                .filter(|node| node.has_tag_name("component"))
+                .map(|component| Ok((
+                    component.attribute("objectid").ok_or("component without objectid")?.to_string(),
+                    parse_transform(component.attribute("transform"), unit_scale)?,
+                )))
+                .collect::<Result<_, String>>()?;
+            objects.insert(id, Object::Components(components));
+        }
+    }
+
+    let build = model.children().find(|node| node.has_tag_name("build")).ok_or("missing build")?;
+    let mut triangles = Triangles::default();
+    let mut colors = Vec::new();
+    for item in build.children().filter(|node| node.has_tag_name("item")) {
+        let object_id = item.attribute("objectid").ok_or("build item without objectid")?;
+        let transform = parse_transform(item.attribute("transform"), unit_scale)?;
+        add_object(&objects, object_id, transform, 0, &mut triangles, &mut colors)?;
+    }
+
+    // files without any color are left without vertex colors, so that they get the color from
+    // the manifest like the other formats
+    if colors.iter().any(Option::is_some) {
+        triangles.colors = Some(colors.into_iter().map(|color| color.unwrap_or([1.; 4])).collect());
+    }
+    Ok(triangles)
+}
+
+/// Adds the triangles of the object, and of the objects it is made of, to `triangles`, each
+/// with its own three vertices so that every triangle can have its own color.
+fn add_object(
+    objects: &HashMap<&str, Object>,
+    id: &str,
+    transform: Affine3A,
+    depth: usize,
+    triangles: &mut Triangles,
+    colors: &mut Vec<Option<[f32; 4]>>,
+) -> Result<(), String> {
+    if depth > MAX_COMPONENT_DEPTH {
+        return Err(format!("components nested deeper than {MAX_COMPONENT_DEPTH} levels"));
+    }
+    match objects.get(id).ok_or_else(|| format!("unknown object {id}"))? {
+        Object::Mesh(mesh) => for corners in &mesh.triangles {
+            for (vertex, color) in corners {
+                triangles.indices.push(triangles.positions.len() as u32);
+                triangles.positions.push(transform.transform_point3(mesh.positions[*vertex as usize]).to_array());
+                colors.push(*color);
+            }
+        },
+        Object::Components(components) => for (object_id, component_transform) in components {
+            add_object(objects, object_id, transform * *component_transform, depth + 1, triangles, colors)?;
+        },
+    }
+    Ok(())
+}
+
+fn float_attribute(node: Node, name: &str) -> Result<f32, String> {
+    let value = node.attribute(name).ok_or_else(|| format!("missing attribute '{name}'"))?;
+    value.parse().map_err(|_| format!("invalid number '{value}'"))
+}
+
+/// Parses a 3MF transform, made of 12 numbers in row-major order, the last 3 being the
+/// translation, which is converted to millimeters.
+fn parse_transform(transform: Option<&str>, unit_scale: f32) -> Result<Affine3A, String> {
+    let Some(transform) = transform else {
+        return Ok(Affine3A::IDENTITY);
+    };
+    let values = transform.split_whitespace()
+        .map(|value| value.parse().map_err(|_| format!("invalid number '{value}' in transform")))
+        .collect::<Result<Vec<f32>, String>>()?;
+    let mut values: [f32; 12] = values.try_into().map_err(|_| format!("invalid transform '{transform}'"))?;
+    values[9..].iter_mut().for_each(|value| *value *= unit_scale);
+    Ok(Affine3A::from_cols_array(&values))
+}
+
+/// Parses a `#RRGGBB` or `#RRGGBBAA` color.
+fn parse_color(color: &str) -> Result<[f32; 4], String> {
+    Srgba::hex(color)
+        .map(|color| srgb_to_linear(color.to_f32_array()))
+        .map_err(|_| format!("invalid color '{color}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use crate::formats::three_mf::{parse, parse_model};
+
+    const MODEL: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+        <model unit="centimeter" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02"
+            xmlns:m="http://schemas.microsoft.com/3dmanufacturing/material/2015/02">
+          <resources>
+            <basematerials id="1">
+              <base name="red" displaycolor="#FF0000" />
+              <base name="blue" displaycolor="#0000FFFF" />
+            </basematerials>
+            <m:colorgroup id="2">
+              <m:color color="#00FF00" />
+            </m:colorgroup>
+            <object id="3" type="model" pid="1" pindex="0">
+              <mesh>
+                <vertices>
+                  <vertex x="0" y="0" z="0" />
+                  <vertex x="1" y="0" z="0" />
+                  <vertex x="0" y="1" z="0" />
+                </vertices>
+                <triangles>
+                  <triangle v1="0" v2="1" v3="2" />
+                  <triangle v1="0" v2="2" v3="1" pid="2" p1="0" />
+                </triangles>
+              </mesh>
+            </object>
+            <object id="4" type="model">
+              <components>
+                <component objectid="3" transform="1 0 0 0 1 0 0 0 1 0 0 1" />
+              </components>
+            </object>
+          </resources>
+          <build>
+            <item objectid="4" transform="2 0 0 0 2 0 0 0 2 1 0 0" />
+          </build>
+        </model>"##;
+
+    #[test]
+    fn test_parse_model() {
+        let triangles = parse_model(MODEL).unwrap();
+        assert_eq!(triangles.indices, (0..6).collect::<Vec<_>>());
+        // scaled by 2, moved up by 1cm before and right by 1cm after, and converted to mm
+        assert_eq!(triangles.positions[0], [10., 0., 20.]);
+        assert_eq!(triangles.positions[1], [30., 0., 20.]);
+        let colors = triangles.colors.unwrap();
+        assert_eq!(colors[0], [1., 0., 0., 1.]);
+        assert_eq!(colors[3], [0., 1., 0., 1.]);
+    }
+
+    #[test]
+    fn test_parse_archive() {
+        let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+        archive.start_file("_rels/.rels", SimpleFileOptions::default()).unwrap();
+        archive.write_all(br#"<Relationships><Relationship Target="/3D/part.model" Id="rel0"
+            Type="http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel" /></Relationships>"#).unwrap();
+        archive.start_file("3D/part.model", SimpleFileOptions::default()).unwrap();
+        archive.write_all(MODEL.as_bytes()).unwrap();
+        let bytes = archive.finish().unwrap().into_inner();
+
+        assert_eq!(parse(&bytes).unwrap().positions.len(), 6);
+        assert!(parse(b"PK\x03\x04 not really a zip").is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! Reflects the node being shown in the url fragment (e.g. `#node=2/0`), so that the back button
+//! of the browser moves through the tree, and links can point straight at a nested node.
+
+use std::sync::Arc;
+
+use bevy::prelude::*;
+
+use crate::{
+    bind, manifest::{fragment_parameters, ManifestSource, PendingManifest}, MeshTreeRes, OneShotSystemsRes,
+};
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (
+            receive_popped_fragment,
+            follow_node_path.run_if(resource_exists::<PendingNodePath>.and(not(resource_exists::<PendingManifest>))),
+            push_node_path.run_if(resource_changed::<MeshTreeRes>.and(not(resource_exists::<PendingNodePath>))),
+        ).chain().run_if(resource_exists::<MeshTreeRes>.and(resource_exists::<NodeHistory>)));
+    }
+}
+
+/// What the url fragment currently points at.
+#[derive(Resource)]
+pub struct NodeHistory {
+    source: ManifestSource,
+    shown_path: Vec<usize>,
+}
+
+impl NodeHistory {
+    pub fn new(source: ManifestSource, shown_path: Vec<usize>) -> NodeHistory {
+        NodeHistory { source, shown_path }
+    }
+}
+
+/// The path that still needs to be followed from the current node to reach the node the url
+/// points at, which might need to wait for the manifests of lazy nodes to be downloaded.
+#[derive(Resource)]
+pub struct PendingNodePath {
+    target: Vec<usize>,
+    remaining: Vec<usize>,
+    waiting_for_manifest: bool,
+}
+
+impl PendingNodePath {
+    pub fn new(target: Vec<usize>) -> PendingNodePath {
+        PendingNodePath { remaining: target.clone(), target, waiting_for_manifest: false }
+    }
+}
+
+/// The path of the node in the url fragment, made of the indices of the children to follow from
+/// the root, e.g. `node=2/0`. The root has an empty path.
+pub fn node_path_from_fragment(fragment: &str) -> Vec<usize> {
+    fragment_parameters(fragment).unwrap_or_default().into_iter()
+        .find(|(key, _)| *key == "node")
+        .map(|(_, value)| value.split('/')
+            .filter(|index| !index.is_empty())
+            .map_while(|index| index.parse().ok())
+            .collect())
+        .unwrap_or_default()
+}
+
+/// Replaces the node path in the url fragment, keeping the other parameters.
+pub fn fragment_with_node_path(fragment: &str, path: &[usize]) -> String {
+    let fragment = fragment.trim_start_matches('#').trim();
+    let mut parameters: Vec<String> = match fragment_parameters(fragment) {
+        // the other parameters are kept as they are, without decoding them
+        Some(_) => fragment.split('&')
+            .filter(|parameter| parameter.split_once('=').is_some_and(|(key, _)| key != "node"))
+            .map(str::to_string)
+            .collect(),
+        None if fragment.is_empty() => Vec::new(),
+        // a bare manifest url becomes a parameter, so that the node can follow it
+        None => match ManifestSource::from_fragment(fragment) {
+            ManifestSource::Manifest(url) => vec![format!("manifest={}", url.replace('&', "%26"))],
+            ManifestSource::Mesh(_) => return fragment.to_string(),
+        },
+    };
+    if !path.is_empty() {
+        let path: Vec<String> = path.iter().map(usize::to_string).collect();
+        parameters.push(format!("node={}", path.join("/")));
+    }
+    parameters.join("&")
+}
+
+/// Moves to the node of the history entry the user went back or forward to.
+fn receive_popped_fragment(
+    mut commands: Commands,
+    mut mesh_tree: ResMut<MeshTreeRes>,
+    mut history: ResMut<NodeHistory>,
+) {
+    let Some(fragment) = bind::take_popped_fragment() else { return; };
+    if ManifestSource::from_fragment(&fragment) != history.source {
+        // the user edited the url to show something else entirely
+        bind::reload_page();
+        return;
+    }
+    let Some(root) = mesh_tree.root.clone() else { return; };
+
+    let path = node_path_from_fragment(&fragment);
+    console_log!("Moving to node {path:?} from the browser history");
+    mesh_tree.current = Arc::downgrade(&root);
+    history.shown_path = path.clone();
+    commands.insert_resource(PendingNodePath::new(path));
+}
+
+/// Descends from the current node towards the node the url points at, loading the manifests of
+/// lazy nodes along the way, and then shows it. The nodes along the way are not shown in between,
+/// since receiving their manifest leaves that to this system while there is a path to follow.
+fn follow_node_path(
+    mut commands: Commands,
+    mut pending: ResMut<PendingNodePath>,
+    mut mesh_tree: ResMut<MeshTreeRes>,
+    mut history: ResMut<NodeHistory>,
+    one_shot_systems: Res<OneShotSystemsRes>,
+) {
+    // wait for the root manifest
+    let Some(mut node) = mesh_tree.current.upgrade() else { return; };
+
+    while let Some(&index) = pending.remaining.first() {
+        if node.pending_manifest().is_some() {
+            if pending.waiting_for_manifest {
+                console_log!("Could not load the children of the node at {:?}", node.path());
+                break;
+            }
+            // showing the node loads its manifest, then we can go on from there
+            pending.waiting_for_manifest = true;
+            mesh_tree.current = Arc::downgrade(&node);
+            commands.run_system(one_shot_systems.update_current_sys);
+            return;
+        }
+        pending.waiting_for_manifest = false;
+
+        let Some(child) = node.children().get(index).cloned() else {
+            console_log!("There is no node at {:?}", pending.target);
+            break;
+        };
+        node = child;
+        pending.remaining.remove(0);
+    }
+
+    mesh_tree.current = Arc::downgrade(&node);
+    commands.remove_resource::<PendingNodePath>();
+    commands.run_system(one_shot_systems.update_current_sys);
+
+    // if the path was not valid, the url points at the closest node that exists instead
+    history.shown_path = node.path();
+    if history.shown_path != pending.target {
+        bind::replace_url_fragment(&fragment_with_node_path(&bind::get_url_fragment(), &history.shown_path));
+    }
+}
+
+/// Adds an entry to the browser history whenever the user moves to another node.
+fn push_node_path(mesh_tree: Res<MeshTreeRes>, mut history: ResMut<NodeHistory>) {
+    let Some(current) = mesh_tree.current.upgrade() else { return; };
+    let path = current.path();
+    let is_root = mesh_tree.root.as_ref().is_some_and(|root| Arc::ptr_eq(root, &current));
+    if path.is_empty() && !is_root {
+        // listings like search results are not part of the tree, so they have no path
+        return;
+    }
+    if path != history.shown_path {
+        bind::push_url_fragment(&fragment_with_node_path(&bind::get_url_fragment(), &path));
+        history.shown_path = path;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::history::{fragment_with_node_path, node_path_from_fragment};
+
+    #[test]
+    fn test_node_path_from_fragment() {
+        assert_eq!(node_path_from_fragment(""), Vec::<usize>::new());
+        assert_eq!(node_path_from_fragment("#node=2/0/13"), vec![2, 0, 13]);
+        assert_eq!(node_path_from_fragment("manifest=/a/tree.json&node=1"), vec![1]);
+        assert_eq!(node_path_from_fragment("node=1/x/2"), vec![1]);
+        assert_eq!(node_path_from_fragment("/get?node=1"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_fragment_with_node_path() {
+        assert_eq!(fragment_with_node_path("", &[2, 0]), "node=2/0");
+        assert_eq!(fragment_with_node_path("#node=2/0", &[]), "");
+        assert_eq!(fragment_with_node_path("manifest=/t.json&node=1", &[3]), "manifest=/t.json&node=3");
+        assert_eq!(fragment_with_node_path("/catalog/tree.json", &[1]), "manifest=/catalog/tree.json&node=1");
+        assert_eq!(fragment_with_node_path("http://host/a.stl", &[]), "http://host/a.stl");
+        assert_eq!(
+            fragment_with_node_path("manifest=/get%3Fa=1%26b=2&node=1", &[2]),
+            "manifest=/get%3Fa=1%26b=2&node=2"
+        );
+        assert_eq!(fragment_with_node_path("/get?a=1&b=tree.json", &[1]), "manifest=/get?a=1%26b=tree.json&node=1");
+    }
+}
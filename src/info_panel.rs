@@ -0,0 +1,90 @@
+//! Shows the metadata from the manifest next to the mesh being rendered on its own.
+
+use bevy::{color::palettes::tailwind::GRAY_400, prelude::*};
+
+use crate::{loading::VisualizationComponents, meshes_tree::MeshTreeNode, GridItem, ShownNode};
+
+pub struct InfoPanelPlugin;
+
+impl Plugin for InfoPanelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (spawn_info_panel, despawn_orphan_info_panels));
+    }
+}
+
+/// The panel describing the leaf entity `target`.
+#[derive(Component)]
+pub struct InfoPanel {
+    pub target: Entity,
+}
+
+#[allow(clippy::type_complexity)]
+fn spawn_info_panel(
+    mut commands: Commands,
+    new_leaves: Query<(Entity, &ShownNode), (Added<ShownNode>, Without<GridItem>)>,
+) {
+    for (entity, shown_node) in &new_leaves {
+        let Some(node) = shown_node.0.upgrade() else { continue; };
+        commands.spawn((
+            InfoPanel { target: entity },
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(12.),
+                right: Val::Px(12.),
+                max_width: Val::Px(320.),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.),
+                padding: UiRect::all(Val::Px(10.)),
+                ..default()
+            },
+            BackgroundColor(Color::BLACK.with_alpha(0.6)),
+            VisualizationComponents,
+            Visibility::Hidden,
+        )).with_children(|panel| {
+            panel.spawn((
+                Text::new(node.display_name()),
+                TextFont { font_size: 20., ..default() },
+                TextColor(Color::WHITE),
+            ));
+            for line in info_lines(&node) {
+                panel.spawn((
+                    Text::new(line),
+                    TextFont { font_size: 13., ..default() },
+                    TextColor(Color::from(GRAY_400)),
+                ));
+            }
+        });
+    }
+}
+
+/// The lines of text to show below the title, skipping the missing fields.
+fn info_lines(node: &MeshTreeNode) -> Vec<String> {
+    let metadata = &node.metadata;
+    let mut lines = Vec::new();
+    if let Some(author) = &metadata.author {
+        lines.push(format!("by {author}"));
+    }
+    if let Some(description) = &metadata.description {
+        lines.push(description.clone());
+    }
+    if let Some(license) = &metadata.license {
+        lines.push(format!("License: {license}"));
+    }
+    if !metadata.tags.is_empty() {
+        lines.push(format!("Tags: {}", metadata.tags.join(", ")));
+    }
+    lines.push(format!("Units: {}", node.units().symbol()));
+    lines
+}
+
+fn despawn_orphan_info_panels(
+    mut commands: Commands,
+    panels: Query<(Entity, &InfoPanel)>,
+    leaves: Query<(), With<ShownNode>>,
+) {
+    for (entity, panel) in &panels {
+        if !leaves.contains(panel.target) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,650 @@
+//! Checks each loaded mesh for the problems of broken exports: holes, i.e. edges of a single
+//! triangle, edges shared by more than two triangles, triangles wound the wrong way, degenerate
+//! and duplicate triangles, and triangles crossing each other. The checks run once per mesh, in
+//! a task pausing now and then.
+//!
+//! When rendering a leaf, the problems found are listed in the info panel and highlighted on the
+//! model, which pressing I hides and shows again. When rendering a subtree, a badge above each
+//! item sums up the problems of its mesh. Items shown with a thumbnail get theirs only once their
+//! model is loaded, i.e. hovered, since not downloading all the meshes is the point of thumbnails.
+
+use std::collections::HashSet;
+
+use bevy::{
+    color::palettes::tailwind::{AMBER_400, EMERALD_600, GRAY_400, ORANGE_500, PINK_500, PURPLE_500, RED_500, SKY_500},
+    mesh::{Indices, PrimitiveTopology},
+    platform::collections::HashMap,
+    prelude::*,
+    tasks::{block_on, poll_once, AsyncComputeTaskPool, Task},
+};
+
+use crate::{
+    bvh::Bvh, dimensions::ModelDimensions, info_panel::InfoPanel, pause::{pause, TRIANGLES_PER_PAUSE},
+    render_modes::{LoadedLook, Overlay}, search::not_typing, thumbnails::Thumbnail, GridItem, MeshTreeRes, ShownNode,
+};
+
+pub struct IntegrityPlugin;
+
+impl Plugin for IntegrityPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MeshIntegrity>()
+            .add_systems(Update, (
+                forget_changed_meshes,
+                toggle_highlights.run_if(not_typing),
+                start_checks,
+                finish_checks,
+                highlight_leaf,
+                show_integrity_section,
+                (spawn_grid_badges, despawn_orphan_badges, place_grid_badges).chain(),
+            ).chain());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Problem {
+    /// an edge of a single triangle, bordering a hole
+    OpenEdge,
+    /// an edge shared by more than two triangles
+    NonManifoldEdge,
+    /// an edge between two triangles wound in opposite ways, i.e. one of them is flipped
+    FlippedEdge,
+    /// a triangle with no area
+    Degenerate,
+    /// a triangle with the same corners as another one
+    Duplicate,
+    /// a triangle with an edge crossing another triangle
+    Intersecting,
+}
+
+impl Problem {
+    const ALL: [Problem; 6] = [
+        Problem::OpenEdge, Problem::NonManifoldEdge, Problem::FlippedEdge, Problem::Degenerate, Problem::Duplicate, Problem::Intersecting,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Problem::OpenEdge => "Open edges",
+            Problem::NonManifoldEdge => "Non-manifold edges",
+            Problem::FlippedEdge => "Edges between flipped triangles",
+            Problem::Degenerate => "Degenerate triangles",
+            Problem::Duplicate => "Duplicate triangles",
+            Problem::Intersecting => "Self-intersecting triangles",
+        }
+    }
+
+    /// The name in the badges of the grid, which have little room.
+    fn short_name(self) -> &'static str {
+        match self {
+            Problem::OpenEdge => "holes",
+            Problem::NonManifoldEdge => "non-manifold",
+            Problem::FlippedEdge => "flipped",
+            Problem::Degenerate => "degenerate",
+            Problem::Duplicate => "duplicates",
+            Problem::Intersecting => "self-intersecting",
+        }
+    }
+
+    /// The color of the highlights and of the line listing them.
+    fn color(self) -> Color {
+        match self {
+            Problem::OpenEdge => Color::from(RED_500),
+            Problem::NonManifoldEdge => Color::from(PURPLE_500),
+            Problem::FlippedEdge => Color::from(AMBER_400),
+            Problem::Degenerate => Color::from(SKY_500),
+            Problem::Duplicate => Color::from(ORANGE_500),
+            Problem::Intersecting => Color::from(PINK_500),
+        }
+    }
+}
+
+/// How many edges or triangles of a mesh have each problem.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct IntegrityReport {
+    counts: [usize; Problem::ALL.len()],
+}
+
+impl IntegrityReport {
+    fn count(&self, problem: Problem) -> usize {
+        self.counts[problem as usize]
+    }
+
+    fn add(&mut self, problem: Problem) {
+        self.counts[problem as usize] += 1;
+    }
+
+    /// The problems found, with how many times.
+    fn problems(&self) -> impl Iterator<Item = (Problem, usize)> + '_ {
+        Problem::ALL.into_iter().map(|problem| (problem, self.count(problem))).filter(|(_, count)| *count > 0)
+    }
+
+    fn merge(&mut self, other: &IntegrityReport) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts) {
+            *count += other;
+        }
+    }
+}
+
+/// The edges and triangles to highlight, in the coordinates of the mesh.
+#[derive(Default)]
+struct Highlights {
+    edges: Vec<([Vec3; 2], Problem)>,
+    triangles: Vec<([Vec3; 3], Problem)>,
+}
+
+/// How many triangles use an edge, and how many of them go along it from its lower vertex.
+struct EdgeUse {
+    ends: [Vec3; 2],
+    triangles: usize,
+    forward: usize,
+}
+
+/// Finds the problems of a triangle mesh. Its corners are merged by position first, since files
+/// like STL repeat them for each triangle.
+async fn find_problems(positions: &[Vec3], indices: &[u32]) -> (IntegrityReport, Highlights) {
+    let mut vertices: HashMap<[u32; 3], u32> = HashMap::new();
+    let vertex_ids: Vec<u32> = positions.iter()
+        .map(|position| {
+            let next_id = vertices.len() as u32;
+            // adding zero turns -0 into 0, so that both have the same bits
+            *vertices.entry(position.to_array().map(|coordinate| (coordinate + 0.).to_bits())).or_insert(next_id)
+        })
+        .collect();
+
+    let mut report = IntegrityReport::default();
+    let mut highlights = Highlights::default();
+    let mut faces = HashSet::new();
+    let mut edges: HashMap<(u32, u32), EdgeUse> = HashMap::new();
+    // the triangles that are neither degenerate nor duplicate, with the ids of their corners
+    let mut kept: Vec<([u32; 3], [Vec3; 3])> = Vec::new();
+    for (index, triangle) in indices.chunks_exact(3).enumerate() {
+        if index > 0 && index.is_multiple_of(TRIANGLES_PER_PAUSE) {
+            pause().await;
+        }
+        if triangle.iter().any(|index| *index as usize >= positions.len()) {
+            continue;
+        }
+        let ids = [0, 1, 2].map(|corner| vertex_ids[triangle[corner] as usize]);
+        let corners = [0, 1, 2].map(|corner| positions[triangle[corner] as usize]);
+
+        let (side1, side2) = (corners[1] - corners[0], corners[2] - corners[0]);
+        let collinear = side1.cross(side2).length() <= f32::EPSILON * side1.length() * side2.length();
+        if ids[0] == ids[1] || ids[1] == ids[2] || ids[2] == ids[0] || collinear {
+            report.add(Problem::Degenerate);
+            highlights.edges.extend([0, 1, 2].map(|corner| ([corners[corner], corners[(corner + 1) % 3]], Problem::Degenerate)));
+            continue;
+        }
+        let mut sorted_ids = ids;
+        sorted_ids.sort_unstable();
+        if !faces.insert(sorted_ids) {
+            report.add(Problem::Duplicate);
+            highlights.triangles.push((corners, Problem::Duplicate));
+            continue;
+        }
+
+        for corner in 0..3 {
+            let next = (corner + 1) % 3;
+            let (start, end) = (ids[corner], ids[next]);
+            let edge = edges.entry((start.min(end), start.max(end)))
+                .or_insert(EdgeUse { ends: [corners[corner], corners[next]], triangles: 0, forward: 0 });
+            edge.triangles += 1;
+            edge.forward += usize::from(start < end);
+        }
+        kept.push((ids, corners));
+    }
+
+    for edge in edges.values() {
+        let problem = match edge.triangles {
+            1 => Problem::OpenEdge,
+            // two triangles wound the same way go along their shared edge in opposite directions
+            2 if edge.forward == 1 => continue,
+            2 => Problem::FlippedEdge,
+            _ => Problem::NonManifoldEdge,
+        };
+        report.add(problem);
+        highlights.edges.push((edge.ends, problem));
+    }
+
+    // the edges of the triangles are cast against the triangles not sharing any corner with them
+    let triangles: Vec<[Vec3; 3]> = kept.iter().map(|(_, corners)| *corners).collect();
+    let bvh = Bvh::new(&triangles);
+    for (index, (ids, corners)) in kept.iter().enumerate() {
+        if index > 0 && index.is_multiple_of(TRIANGLES_PER_PAUSE) {
+            pause().await;
+        }
+        let apart = |other: usize| !kept[other].0.iter().any(|id| ids.contains(id));
+        let crossing = (0..3).any(|corner| {
+            let edge = corners[(corner + 1) % 3] - corners[corner];
+            bvh.any_hit(&triangles, corners[corner], edge.normalize(), edge.length(), apart)
+        });
+        if crossing {
+            report.add(Problem::Intersecting);
+            highlights.triangles.push((*corners, Problem::Intersecting));
+        }
+    }
+    (report, highlights)
+}
+
+/// The meshes drawing the highlighted edges and triangles with the color of their problem.
+fn highlight_meshes(highlights: &Highlights) -> Vec<Mesh> {
+    let mut meshes = Vec::new();
+    let color = |problem: Problem| LinearRgba::from(problem.color()).to_f32_array();
+    if !highlights.edges.is_empty() {
+        let positions: Vec<[f32; 3]> = highlights.edges.iter().flat_map(|(ends, _)| ends.map(|end| end.to_array())).collect();
+        let colors: Vec<[f32; 4]> = highlights.edges.iter().flat_map(|(_, problem)| [color(*problem); 2]).collect();
+        let normals = vec![[0., 1., 0.]; positions.len()];
+        meshes.push(Mesh::new(PrimitiveTopology::LineList, default())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors));
+    }
+    if !highlights.triangles.is_empty() {
+        let positions: Vec<[f32; 3]> = highlights.triangles.iter()
+            .flat_map(|(corners, _)| corners.map(|corner| corner.to_array()))
+            .collect();
+        let colors: Vec<[f32; 4]> = highlights.triangles.iter().flat_map(|(_, problem)| [color(*problem); 3]).collect();
+        let normals = vec![[0., 1., 0.]; positions.len()];
+        let count = positions.len() as u32;
+        meshes.push(Mesh::new(PrimitiveTopology::TriangleList, default())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+            .with_inserted_indices(Indices::U32((0..count).collect())));
+    }
+    meshes
+}
+
+/// A mesh once checked, with the meshes highlighting its problems.
+struct CheckedMesh {
+    report: IntegrityReport,
+    highlights: Vec<Handle<Mesh>>,
+}
+
+/// The problems of each loaded mesh, checked just once since the same mesh can be shown many
+/// times, as for [`crate::dimensions::MeshBounds`].
+#[derive(Resource, Default)]
+pub struct MeshIntegrity {
+    checked: HashMap<AssetId<Mesh>, CheckedMesh>,
+    tasks: HashMap<AssetId<Mesh>, Task<(IntegrityReport, Vec<Mesh>)>>,
+    /// whether the problems of the leaf are not highlighted
+    hidden: bool,
+    /// unlit, showing the colors of the highlights
+    highlight_matl: Option<Handle<StandardMaterial>>,
+}
+
+impl MeshIntegrity {
+    /// The problems of all the `meshes`, once they are all checked.
+    fn report_of(&self, meshes: impl IntoIterator<Item = AssetId<Mesh>>) -> Option<IntegrityReport> {
+        let mut report = IntegrityReport::default();
+        let mut any = false;
+        for mesh in meshes {
+            report.merge(&self.checked.get(&mesh)?.report);
+            any = true;
+        }
+        any.then_some(report)
+    }
+}
+
+/// The meshes of a shown node, i.e. the ones it was loaded with, skipping the placeholder of the
+/// nodes without a mesh, the thumbnails and the overlays. Items shown with a thumbnail thus have
+/// none until their model is requested.
+#[allow(clippy::type_complexity)]
+fn node_meshes(
+    node: Entity,
+    descendants: &Query<&Children>,
+    mesh_entities: &Query<(&Mesh3d, Option<&LoadedLook>), (Without<Overlay>, Without<Thumbnail>)>,
+    mesh_tree: &MeshTreeRes,
+) -> Vec<AssetId<Mesh>> {
+    descendants.iter_descendants(node)
+        .filter_map(|entity| mesh_entities.get(entity).ok())
+        .map(|(mesh, loaded_look)| loaded_look.map_or(mesh.0.id(), |loaded_look| loaded_look.mesh.id()))
+        .filter(|mesh| *mesh != mesh_tree.placeholder_mesh.id())
+        .collect()
+}
+
+fn forget_changed_meshes(mut events: MessageReader<AssetEvent<Mesh>>, mut integrity: ResMut<MeshIntegrity>) {
+    for event in events.read() {
+        if let AssetEvent::Modified { id } | AssetEvent::Removed { id } | AssetEvent::Unused { id } = event {
+            integrity.checked.remove(id);
+            integrity.tasks.remove(id);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn toggle_highlights(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut integrity: ResMut<MeshIntegrity>,
+    leaves: Query<(), (With<ShownNode>, With<ModelDimensions>, Without<GridItem>)>,
+) {
+    if keys.just_pressed(KeyCode::KeyI) && !leaves.is_empty() {
+        integrity.hidden = !integrity.hidden;
+        console_log!("Integrity highlights {}", if integrity.hidden { "hidden" } else { "shown" });
+    }
+}
+
+/// Starts checking the meshes of the shown nodes as soon as they are loaded.
+#[allow(clippy::type_complexity)]
+fn start_checks(
+    mut integrity: ResMut<MeshIntegrity>,
+    nodes: Query<Entity, With<ShownNode>>,
+    descendants: Query<&Children>,
+    mesh_entities: Query<(&Mesh3d, Option<&LoadedLook>), (Without<Overlay>, Without<Thumbnail>)>,
+    meshes: Res<Assets<Mesh>>,
+    mesh_tree: Res<MeshTreeRes>,
+) {
+    for node in &nodes {
+        for id in node_meshes(node, &descendants, &mesh_entities, &mesh_tree) {
+            if integrity.checked.contains_key(&id) || integrity.tasks.contains_key(&id) {
+                continue;
+            }
+            let Some(mesh) = meshes.get(id) else { continue; };
+            let positions = mesh.attribute(Mesh::ATTRIBUTE_POSITION).and_then(|positions| positions.as_float3());
+            let (PrimitiveTopology::TriangleList, Some(positions)) = (mesh.primitive_topology(), positions) else {
+                // there is nothing to check in meshes made of lines or points
+                integrity.checked.insert(id, CheckedMesh { report: IntegrityReport::default(), highlights: Vec::new() });
+                continue;
+            };
+            let positions: Vec<Vec3> = positions.iter().map(|position| Vec3::from(*position)).collect();
+            let indices: Vec<u32> = match mesh.indices() {
+                Some(indices) => indices.iter().map(|index| index as u32).collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            let task = AsyncComputeTaskPool::get().spawn(async move {
+                let (report, highlights) = find_problems(&positions, &indices).await;
+                (report, highlight_meshes(&highlights))
+            });
+            integrity.tasks.insert(id, task);
+        }
+    }
+}
+
+fn finish_checks(mut integrity: ResMut<MeshIntegrity>, mut meshes: ResMut<Assets<Mesh>>) {
+    let mut done = Vec::new();
+    for (id, task) in integrity.tasks.iter_mut() {
+        if let Some((report, highlights)) = block_on(poll_once(task)) {
+            done.push((*id, report, highlights));
+        }
+    }
+    for (id, report, highlights) in done {
+        console_log!("Integrity of mesh {id}: {report:?}");
+        integrity.tasks.remove(&id);
+        let highlights = highlights.into_iter().map(|highlight| meshes.add(highlight)).collect();
+        integrity.checked.insert(id, CheckedMesh { report, highlights });
+    }
+}
+
+/// The highlights of the problems of a mesh entity of the leaf, as its child.
+#[derive(Component)]
+#[require(Overlay)]
+struct IntegrityOverlay;
+
+/// Draws the highlights of the problems over the mesh entities of the leaf, unless hidden.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn highlight_leaf(
+    mut commands: Commands,
+    mut integrity: ResMut<MeshIntegrity>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    leaves: Query<Entity, (With<ShownNode>, With<ModelDimensions>, Without<GridItem>)>,
+    descendants: Query<&Children>,
+    mesh_entities: Query<(Entity, &Mesh3d, Option<&LoadedLook>), Without<Overlay>>,
+    overlays: Query<Entity, With<IntegrityOverlay>>,
+) {
+    if integrity.hidden {
+        overlays.iter().for_each(|overlay| commands.entity(overlay).despawn());
+        return;
+    }
+    for leaf in &leaves {
+        for (entity, mesh, loaded_look) in descendants.iter_descendants(leaf).filter_map(|entity| mesh_entities.get(entity).ok()) {
+            let highlighted = descendants.get(entity).is_ok_and(|children| children.iter().any(|child| overlays.contains(child)));
+            let mesh = loaded_look.map_or(mesh.0.id(), |loaded_look| loaded_look.mesh.id());
+            let Some(checked) = integrity.checked.get(&mesh).filter(|_| !highlighted) else { continue; };
+            let highlights = checked.highlights.clone();
+            // drawn in front of the faces of the model they highlight
+            let material = integrity.highlight_matl
+                .get_or_insert_with(|| materials.add(StandardMaterial {
+                    base_color: Color::WHITE,
+                    unlit: true,
+                    cull_mode: None,
+                    depth_bias: 1000.,
+                    ..default()
+                }))
+                .clone();
+            for highlight in highlights {
+                commands.spawn((IntegrityOverlay, ChildOf(entity), Mesh3d(highlight), MeshMaterial3d(material.clone()), Pickable::IGNORE));
+            }
+        }
+    }
+}
+
+/// The part of the info panel listing the problems of the leaf entity `target`.
+#[derive(Component)]
+struct IntegritySection {
+    target: Entity,
+}
+
+/// The lines of text describing the problems found, with their color.
+fn report_lines(report: &IntegrityReport) -> Vec<(String, Color)> {
+    if report.problems().next().is_none() {
+        return vec![("Mesh check: no problems found".to_string(), Color::from(EMERALD_600))];
+    }
+    let mut lines = vec![("Mesh check, I to hide the highlights:".to_string(), Color::from(GRAY_400))];
+    lines.extend(report.problems().map(|(problem, count)| (format!("{}: {count}", problem.name()), problem.color())));
+    lines
+}
+
+/// Lists the problems of the leaf in its info panel once all its meshes are checked.
+#[allow(clippy::type_complexity)]
+fn show_integrity_section(
+    mut commands: Commands,
+    integrity: Res<MeshIntegrity>,
+    panels: Query<(Entity, &InfoPanel)>,
+    sections: Query<&IntegritySection>,
+    descendants: Query<&Children>,
+    mesh_entities: Query<(&Mesh3d, Option<&LoadedLook>), (Without<Overlay>, Without<Thumbnail>)>,
+    mesh_tree: Res<MeshTreeRes>,
+) {
+    for (panel, InfoPanel { target }) in &panels {
+        if sections.iter().any(|section| section.target == *target) {
+            continue;
+        }
+        let Some(report) = integrity.report_of(node_meshes(*target, &descendants, &mesh_entities, &mesh_tree)) else { continue; };
+        commands.entity(panel).with_children(|panel| {
+            panel.spawn((
+                IntegritySection { target: *target },
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(4.),
+                    margin: UiRect::top(Val::Px(6.)),
+                    ..default()
+                },
+            )).with_children(|section| {
+                for (line, color) in report_lines(&report) {
+                    section.spawn((Text::new(line), TextFont { font_size: 13., ..default() }, TextColor(color)));
+                }
+            });
+        });
+    }
+}
+
+/// A badge summing up the problems of the grid item entity `target`.
+#[derive(Component)]
+struct GridBadge {
+    target: Entity,
+}
+
+/// The text of the badge of an item and its background color.
+fn badge(report: &IntegrityReport) -> (String, Color) {
+    let problems: Vec<&str> = report.problems().map(|(problem, _)| problem.short_name()).collect();
+    if problems.is_empty() {
+        ("OK".to_string(), Color::from(EMERALD_600))
+    } else {
+        (problems.join(", "), Color::from(RED_500))
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn spawn_grid_badges(
+    mut commands: Commands,
+    integrity: Res<MeshIntegrity>,
+    items: Query<Entity, With<GridItem>>,
+    badges: Query<&GridBadge>,
+    descendants: Query<&Children>,
+    mesh_entities: Query<(&Mesh3d, Option<&LoadedLook>), (Without<Overlay>, Without<Thumbnail>)>,
+    mesh_tree: Res<MeshTreeRes>,
+) {
+    for item in &items {
+        if badges.iter().any(|badge| badge.target == item) {
+            continue;
+        }
+        let Some(report) = integrity.report_of(node_meshes(item, &descendants, &mesh_entities, &mesh_tree)) else { continue; };
+        let (text, color) = badge(&report);
+        commands.spawn((
+            GridBadge { target: item },
+            Node {
+                position_type: PositionType::Absolute,
+                padding: UiRect::axes(Val::Px(6.), Val::Px(2.)),
+                border_radius: BorderRadius::all(Val::Px(4.)),
+                ..default()
+            },
+            BackgroundColor(color.with_alpha(0.8)),
+            // like the labels, the badge can overlap the mesh, which should still be clickable
+            Pickable::IGNORE,
+            children![(Text::new(text), TextFont { font_size: 11., ..default() }, TextColor(Color::WHITE), Pickable::IGNORE)],
+        ));
+    }
+}
+
+fn despawn_orphan_badges(
+    mut commands: Commands,
+    badges: Query<(Entity, &GridBadge)>,
+    items: Query<(), With<GridItem>>,
+) {
+    for (entity, badge) in &badges {
+        if !items.contains(badge.target) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Keeps each badge in the top left corner of the grid cell of its item, as for the labels.
+fn place_grid_badges(
+    mut badges: Query<(&GridBadge, &mut Node)>,
+    items: Query<&GlobalTransform, With<GridItem>>,
+    camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+) {
+    let Ok((camera, camera_transform)) = camera.single() else { return; };
+    for (badge, mut node) in &mut badges {
+        let Ok(item_transform) = items.get(badge.target) else { continue; };
+        let (scale, _, center) = item_transform.to_scale_rotation_translation();
+        let half_cell = scale.max_element() / 2.;
+        let Ok(corner) = camera.world_to_viewport(camera_transform, center + Vec3::new(-half_cell, half_cell, 0.)) else { continue; };
+        node.left = Val::Px(corner.x);
+        node.top = Val::Px(corner.y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Weak};
+
+    use bevy::{
+        ecs::system::RunSystemOnce,
+        math::Vec3,
+        prelude::*,
+        tasks::block_on,
+    };
+
+    use crate::{
+        integrity::{badge, find_problems, node_meshes, IntegrityReport, Problem},
+        render_modes::{LoadedLook, Overlay},
+        thumbnails::Thumbnail,
+        MeshTreeRes,
+    };
+
+    fn cube() -> (Vec<Vec3>, Vec<u32>) {
+        let mesh = Cuboid::from_length(2.).mesh().build();
+        let positions = mesh.attribute(Mesh::ATTRIBUTE_POSITION).unwrap().as_float3().unwrap().iter().map(|p| Vec3::from(*p)).collect();
+        (positions, mesh.indices().unwrap().iter().map(|index| index as u32).collect())
+    }
+
+    fn problems(positions: &[Vec3], indices: &[u32]) -> Vec<(Problem, usize)> {
+        block_on(find_problems(positions, indices)).0.problems().collect()
+    }
+
+    #[test]
+    fn test_find_problems() {
+        let (mut positions, indices) = cube();
+        let (report, highlights) = block_on(find_problems(&positions, &indices));
+        assert_eq!(report, IntegrityReport::default());
+        assert!(highlights.edges.is_empty() && highlights.triangles.is_empty());
+        assert_eq!(badge(&report).0, "OK");
+
+        // a missing triangle leaves a hole
+        assert_eq!(problems(&positions, &indices[3..]), vec![(Problem::OpenEdge, 3)]);
+
+        let mut flipped = indices.clone();
+        flipped.swap(0, 1);
+        assert_eq!(problems(&positions, &flipped), vec![(Problem::FlippedEdge, 3)]);
+
+        let mut repeated = indices.clone();
+        repeated.extend_from_slice(&indices[..3]);
+        repeated.extend_from_slice(&[indices[0], indices[0], indices[1]]);
+        assert_eq!(problems(&positions, &repeated), vec![(Problem::Degenerate, 1), (Problem::Duplicate, 1)]);
+
+        // a fin on an edge of the cube, and a triangle going through it
+        let fin = positions.len() as u32;
+        positions.extend([Vec3::new(5., 5., 5.), Vec3::new(0., -5., -5.), Vec3::new(0., 5., -5.), Vec3::new(0., 0., 10.)]);
+        let mut extra = indices.clone();
+        extra.extend_from_slice(&[indices[0], indices[1], fin]);
+        let (report, highlights) = block_on(find_problems(&positions, &extra));
+        assert_eq!((report.count(Problem::NonManifoldEdge), report.count(Problem::OpenEdge)), (1, 2));
+        assert_eq!(highlights.edges.len(), 3);
+        extra.truncate(indices.len());
+        extra.extend_from_slice(&[fin + 1, fin + 2, fin + 3]);
+        let (report, _) = block_on(find_problems(&positions, &extra));
+        assert!(report.count(Problem::Intersecting) > 0);
+        assert_eq!(report.count(Problem::OpenEdge), 3);
+        assert_eq!(badge(&report).0, "holes, self-intersecting");
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn item_meshes(
+        In(item): In<Entity>,
+        descendants: Query<&Children>,
+        mesh_entities: Query<(&Mesh3d, Option<&LoadedLook>), (Without<Overlay>, Without<Thumbnail>)>,
+        mesh_tree: Res<MeshTreeRes>,
+    ) -> Vec<AssetId<Mesh>> {
+        node_meshes(item, &descendants, &mesh_entities, &mesh_tree)
+    }
+
+    #[test]
+    fn test_node_meshes() {
+        let mut world = World::new();
+        let mut meshes = Assets::<Mesh>::default();
+        let placeholder = meshes.add(Cuboid::default());
+        let (model, quad) = (meshes.add(Cuboid::default()), meshes.add(Rectangle::default()));
+        world.insert_resource(MeshTreeRes {
+            root: None,
+            current: Weak::new(),
+            white_matl: default(),
+            hover_matl: default(),
+            pressed_matl: default(),
+            error_matl: default(),
+            color_matls: HashMap::new(),
+            placeholder_mesh: placeholder.clone(),
+        });
+
+        // the image of a thumbnail is not a mesh of the node, so its badge waits for the model
+        let item = world.spawn_empty().id();
+        world.spawn((ChildOf(item), Mesh3d(quad), Thumbnail { image: default(), material: default() }));
+        assert!(world.run_system_once_with(item_meshes, item).unwrap().is_empty());
+
+        let pivot = world.spawn(ChildOf(item)).id();
+        world.spawn((ChildOf(pivot), Mesh3d(model.clone())));
+        assert_eq!(world.run_system_once_with(item_meshes, item).unwrap(), vec![model.id()]);
+
+        let placeholder_item = world.spawn_empty().id();
+        world.spawn((ChildOf(placeholder_item), Mesh3d(placeholder)));
+        assert!(world.run_system_once_with(item_meshes, placeholder_item).unwrap().is_empty());
+    }
+}
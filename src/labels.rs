@@ -0,0 +1,79 @@
+//! Shows the name of each item under its mesh when rendering a subtree.
+
+use bevy::prelude::*;
+
+use crate::{loading::VisualizationComponents, GridItem, ShownNode};
+
+pub struct LabelsPlugin;
+
+impl Plugin for LabelsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (spawn_grid_labels, despawn_orphan_labels, place_grid_labels).chain());
+    }
+}
+
+/// A text label attached to the grid item entity `target`.
+#[derive(Component)]
+pub struct GridLabel {
+    pub target: Entity,
+}
+
+fn spawn_grid_labels(
+    mut commands: Commands,
+    new_items: Query<(Entity, &ShownNode), Added<GridItem>>,
+) {
+    for (entity, shown_node) in &new_items {
+        let Some(node) = shown_node.0.upgrade() else { continue; };
+        commands.spawn((
+            GridLabel { target: entity },
+            Text::new(node.display_name()),
+            TextFont { font_size: 14., ..default() },
+            TextColor(Color::WHITE),
+            TextLayout::new(Justify::Center, LineBreak::WordOrCharacter),
+            Node {
+                position_type: PositionType::Absolute,
+                overflow: Overflow::clip(),
+                ..default()
+            },
+            // the label can overlap its mesh, which should still be clickable
+            Pickable::IGNORE,
+            VisualizationComponents,
+            Visibility::Hidden,
+        ));
+    }
+}
+
+fn despawn_orphan_labels(
+    mut commands: Commands,
+    labels: Query<(Entity, &GridLabel)>,
+    items: Query<(), With<GridItem>>,
+) {
+    for (entity, label) in &labels {
+        if !items.contains(label.target) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Keeps each label right below its mesh, by projecting the bottom corners of the grid cell
+/// onto the screen, so that labels follow the layout whenever it changes.
+fn place_grid_labels(
+    mut labels: Query<(&GridLabel, &mut Node)>,
+    items: Query<&GlobalTransform, With<GridItem>>,
+    camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+) {
+    let Ok((camera, camera_transform)) = camera.single() else { return; };
+    for (label, mut node) in &mut labels {
+        let Ok(item_transform) = items.get(label.target) else { continue; };
+        let (scale, _, center) = item_transform.to_scale_rotation_translation();
+        let half_cell = scale.max_element() / 2.;
+        let (Ok(left), Ok(right)) = (
+            camera.world_to_viewport(camera_transform, center + Vec3::new(-half_cell, -half_cell, 0.)),
+            camera.world_to_viewport(camera_transform, center + Vec3::new(half_cell, -half_cell, 0.)),
+        ) else { continue; };
+
+        node.left = Val::Px(left.x);
+        node.top = Val::Px(left.y);
+        node.width = Val::Px(right.x - left.x);
+    }
+}
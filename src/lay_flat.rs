@@ -0,0 +1,270 @@
+//! Lays models flat on the largest face of their convex hull, i.e. on the side they would rest
+//! on most steadily, instead of assuming an up axis. This is done once loaded for the nodes
+//! whose manifest doesn't give the up axis, and for the leaf shown when pressing L.
+//!
+//! Pressing F and then clicking a face of the model rendered on its own puts that face down.
+
+use bevy::{
+    mesh::PrimitiveTopology,
+    picking::{mesh_picking::ray_cast::{MeshRayCast, MeshRayCastSettings}, pointer::PointerButton},
+    prelude::*,
+    tasks::{block_on, poll_once, AsyncComputeTaskPool, Task},
+};
+use stlviewer_hull::{face_down_rotation, unique_points, Hull};
+
+use crate::{
+    dimensions::{mesh_to_model, CurrentLeaf, FitMesh, ModelDimensions, Refit, TrackCurrentLeaf}, measure::DRAG_THRESHOLD,
+    meshes_tree::UpAxis, pause::pause, render_modes::Overlay, search::not_typing, GridItem, ShownNode,
+};
+
+pub struct LayFlatPlugin;
+
+impl Plugin for LayFlatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FaceDown>()
+            .add_systems(Update, (
+                lay_flat_new_models,
+                switch_lay_flat.run_if(not_typing),
+                follow_leaf.after(TrackCurrentLeaf).run_if(resource_changed::<CurrentLeaf>),
+                start_lay_flat_tasks,
+                finish_lay_flat_tasks,
+                put_face_down,
+                show_face_down_hint.run_if(resource_changed::<FaceDown>),
+            ).chain());
+    }
+}
+
+/// Placed on a model, i.e. an entity with [`FitMesh`], to lay it flat as soon as its meshes are
+/// loaded.
+#[derive(Component)]
+pub struct LayFlat;
+
+/// The outward normal of the face to put down, in the coordinates of the model, being computed.
+#[derive(Component)]
+struct LayFlatTask(Task<Option<Vec3>>);
+
+/// Whether the next click on the leaf shown puts the clicked face down.
+#[derive(Resource, Default)]
+struct FaceDown {
+    armed: bool,
+    /// the leaf entity shown
+    target: Option<Entity>,
+}
+
+#[derive(Component)]
+struct FaceDownHint;
+
+/// How many points are added to a hull between two pauses, each going through the faces it sees.
+const POINTS_PER_PAUSE: usize = 2000;
+
+/// The outward normal of the largest face of the convex hull of the points, which is the side
+/// to put down. The points repeated by files like STL are merged first.
+async fn lay_flat_normal(points: &[Vec3]) -> Option<Vec3> {
+    let mut hull = Hull::start(unique_points(points))?;
+    let mut added: usize = 0;
+    while hull.add_next_point() {
+        added += 1;
+        if added.is_multiple_of(POINTS_PER_PAUSE) {
+            pause().await;
+        }
+    }
+    hull.largest_face()
+}
+
+/// Lays flat the models of the nodes whose manifest doesn't give the up axis.
+fn lay_flat_new_models(
+    mut commands: Commands,
+    new_models: Query<Entity, Added<FitMesh>>,
+    nodes: Query<&ShownNode>,
+    parents: Query<&ChildOf>,
+) {
+    for model in &new_models {
+        // thumbnail models turn on a pivot under their node
+        let Some(node) = parents.iter_ancestors(model).find_map(|entity| nodes.get(entity).ok())
+            .and_then(|shown_node| shown_node.0.upgrade()) else { continue; };
+        if node.up_axis() == UpAxis::Auto {
+            commands.entity(model).insert(LayFlat);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn switch_lay_flat(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut face_down: ResMut<FaceDown>,
+    leaves: Query<&Children, (With<ShownNode>, With<ModelDimensions>, Without<GridItem>)>,
+    models: Query<(), With<FitMesh>>,
+) {
+    let Some(children) = leaves.iter().next() else { return; };
+    if keys.just_pressed(KeyCode::KeyL) {
+        console_log!("Laying the model flat");
+        for model in children.iter().filter(|child| models.contains(*child)) {
+            commands.entity(model).insert(LayFlat);
+        }
+    }
+    if keys.just_pressed(KeyCode::KeyF) {
+        face_down.armed = !face_down.armed;
+    }
+}
+
+fn follow_leaf(mut face_down: ResMut<FaceDown>, current_leaf: Res<CurrentLeaf>) {
+    if face_down.target != current_leaf.0 {
+        face_down.target = current_leaf.0;
+        face_down.armed = false;
+    }
+}
+
+/// Starts looking for the largest face of the models to lay flat, once their meshes are loaded.
+#[allow(clippy::type_complexity)]
+fn start_lay_flat_tasks(
+    mut commands: Commands,
+    models: Query<Entity, With<LayFlat>>,
+    descendants: Query<&Children>,
+    nodes: Query<(&Transform, &ChildOf, Option<&Mesh3d>), Without<Overlay>>,
+    meshes: Res<Assets<Mesh>>,
+) {
+    for model in &models {
+        let mut points = Vec::new();
+        let mut loaded = true;
+        for entity in [model].into_iter().chain(descendants.iter_descendants(model)) {
+            let Ok((_, _, Some(mesh))) = nodes.get(entity) else { continue; };
+            let Some(mesh) = meshes.get(&mesh.0) else {
+                loaded = false;
+                break;
+            };
+            if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+                continue;
+            }
+            let Some(positions) = mesh.attribute(Mesh::ATTRIBUTE_POSITION).and_then(|positions| positions.as_float3()) else { continue; };
+            let mesh_to_model = mesh_to_model(entity, model, &nodes);
+            points.extend(positions.iter().map(|position| mesh_to_model.transform_point3(Vec3::from(*position))));
+        }
+        // scenes get their meshes once spawned
+        if !loaded || points.is_empty() {
+            continue;
+        }
+        let task = AsyncComputeTaskPool::get().spawn(async move { lay_flat_normal(&points).await });
+        commands.entity(model).remove::<LayFlat>().insert(LayFlatTask(task));
+    }
+}
+
+/// Turns the models once their largest face is found, to be fitted again.
+fn finish_lay_flat_tasks(mut commands: Commands, mut tasks: Query<(Entity, &mut LayFlatTask, &mut Transform)>) {
+    for (model, mut task, mut transform) in &mut tasks {
+        let Some(normal) = block_on(poll_once(&mut task.0)) else { continue; };
+        commands.entity(model).remove::<LayFlatTask>();
+        // flat models have no hull, and keep the up axis they were shown with
+        let Some(normal) = normal else { continue; };
+        transform.rotation = face_down_rotation(normal);
+        commands.entity(model).insert(Refit);
+    }
+}
+
+/// Puts down the face clicked on the leaf while armed. The ray is cast again, since the hit
+/// reported by the click lacks the triangle.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn put_face_down(
+    mut commands: Commands,
+    mut presses: MessageReader<Pointer<Press>>,
+    mut clicks: MessageReader<Pointer<Click>>,
+    mut pressed_at: Local<Option<Vec2>>,
+    mut face_down: ResMut<FaceDown>,
+    mut ray_cast: MeshRayCast,
+    camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    parents: Query<&ChildOf>,
+    models: Query<&GlobalTransform, With<FitMesh>>,
+    overlays: Query<(), With<Overlay>>,
+) {
+    for press in presses.read() {
+        if press.button == PointerButton::Primary {
+            *pressed_at = Some(press.pointer_location.position);
+        }
+    }
+    for click in clicks.read() {
+        let position = click.pointer_location.position;
+        let dragged = pressed_at.take().is_none_or(|start| start.distance(position) > DRAG_THRESHOLD);
+        if click.button != PointerButton::Primary || dragged || !face_down.armed {
+            continue;
+        }
+        let Some(target) = face_down.target else { continue; };
+        let in_model = |entity: Entity| parents.iter_ancestors(entity).any(|parent| parent == target) && !overlays.contains(entity);
+        if !in_model(click.entity) {
+            continue;
+        }
+
+        let Ok((camera, camera_transform)) = camera.single() else { continue; };
+        let Ok(ray) = camera.viewport_to_world(camera_transform, position) else { continue; };
+        let settings = MeshRayCastSettings::default().with_filter(&in_model);
+        let Some((entity, hit)) = ray_cast.cast_ray(ray, &settings).first() else { continue; };
+        let Some([a, b, c]) = hit.triangle else { continue; };
+        let Some((model, model_transform)) = [*entity].into_iter().chain(parents.iter_ancestors(*entity))
+            .find_map(|ancestor| models.get(ancestor).ok().map(|transform| (ancestor, transform))) else { continue; };
+
+        // the side of the face clicked is the one looking at the camera
+        let mut normal = (b - a).cross(c - a).normalize_or_zero();
+        if normal == Vec3::ZERO {
+            continue;
+        }
+        if normal.dot(*ray.direction) > 0. {
+            normal = -normal;
+        }
+        let (_, rotation, _) = model_transform.to_scale_rotation_translation();
+        let rotation = face_down_rotation(rotation.inverse() * normal);
+        commands.entity(model).insert((Transform::from_rotation(rotation), Refit));
+        face_down.armed = false;
+        console_log!("Putting down the face with normal {normal}");
+    }
+}
+
+fn show_face_down_hint(
+    mut commands: Commands,
+    face_down: Res<FaceDown>,
+    hints: Query<Entity, With<FaceDownHint>>,
+) {
+    hints.iter().for_each(|entity| commands.entity(entity).despawn());
+    if !face_down.armed || face_down.target.is_none() {
+        return;
+    }
+    commands.spawn((
+        FaceDownHint,
+        Text::new("Click a face of the model to put it down, F to cancel, L to lay it flat"),
+        TextFont { font_size: 14., ..default() },
+        TextColor(Color::WHITE),
+        Node {
+            position_type: PositionType::Absolute,
+            // above the hint of the measurements
+            bottom: Val::Px(72.),
+            left: Val::Px(12.),
+            padding: UiRect::all(Val::Px(8.)),
+            ..default()
+        },
+        BackgroundColor(Color::BLACK.with_alpha(0.6)),
+        Pickable::IGNORE,
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{math::{Quat, Vec3}, tasks::block_on};
+
+    use stlviewer_hull::face_down_rotation;
+
+    use crate::lay_flat::lay_flat_normal;
+
+    #[test]
+    fn test_lay_flat() {
+        // a pyramid rests on its base, whatever way it was modeled
+        let pyramid = [
+            Vec3::new(-5., -5., 0.), Vec3::new(5., -5., 0.), Vec3::new(5., 5., 0.), Vec3::new(-5., 5., 0.), Vec3::new(0., 0., 3.),
+        ];
+        let turned = Quat::from_rotation_y(1.);
+        let normal = block_on(lay_flat_normal(&pyramid.map(|point| turned * point))).unwrap();
+        assert!(normal.distance(turned * Vec3::NEG_Z) < 1e-5);
+        assert!((face_down_rotation(normal) * turned * Vec3::Z).distance(Vec3::Y) < 1e-5);
+
+        // Z-up models lying on their bottom get the usual rotation
+        let rotation = face_down_rotation(Vec3::NEG_Z);
+        assert!(rotation.abs_diff_eq(Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2), 1e-5));
+    }
+}
@@ -1,6 +1,10 @@
 //! Shows how to create a loading screen that waits for assets to load and render.
 
-use bevy::{camera::primitives::MeshAabb, math::Vec3A, platform::collections::HashMap, prelude::*};
+use bevy::{
+    asset::{LoadState, RecursiveDependencyLoadState, UntypedAssetId},
+    color::palettes::tailwind::{CYAN_300, GRAY_400, GRAY_700},
+    prelude::*,
+};
 use pipelines_ready::*;
 
 // The way we'll go about doing this in this example is to
@@ -31,10 +35,10 @@ impl Plugin for LoadingScreenPlugin {
             )
             .add_systems(
                 OnEnter(LoadingState::Ready),
-                (clear_loading_screen, resize_meshes),
+                (clear_loading_screen, reset_progress),
             ).add_systems(
                 OnEnter(LoadingState::Loading),
-                load_loading_screen,
+                (load_loading_screen, clear_failed_assets),
             );
     }
 }
@@ -49,6 +53,51 @@ fn load_loading_screen(loading_data: Res<LoadingData>, mut commands: Commands) {
         },
     ));
     commands.spawn((LoadingScreen, Camera2d));
+
+    // progress indicator, placed below the logo
+    commands.spawn((
+        LoadingScreen,
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.),
+            top: Val::Percent(65.),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            row_gap: Val::Px(6.),
+            ..default()
+        },
+        children![
+            (
+                ProgressText,
+                Text::default(),
+                TextFont { font_size: 16., ..default() },
+                TextColor(Color::WHITE),
+            ),
+            (
+                Node {
+                    width: Val::Px(300.),
+                    height: Val::Px(8.),
+                    ..default()
+                },
+                BackgroundColor(Color::from(GRAY_700)),
+                children![(
+                    ProgressBar,
+                    Node {
+                        width: Val::Percent(0.),
+                        height: Val::Percent(100.),
+                        ..default()
+                    },
+                    BackgroundColor(Color::from(CYAN_300)),
+                )],
+            ),
+            (
+                ProgressBytesText,
+                Text::default(),
+                TextFont { font_size: 13., ..default() },
+                TextColor(Color::from(GRAY_400)),
+            ),
+        ],
+    ));
 }
 
 fn clear_loading_screen(
@@ -83,6 +132,11 @@ pub struct LoadingData {
     img: Handle<Image>,
     // This will hold the currently unloaded/loading assets.
     loading_assets: Vec<UntypedHandle>,
+    // How many assets were loaded (or failed to) since the last time everything was ready,
+    // used to show the progress together with the length of `loading_assets`.
+    finished_assets_count: usize,
+    // The assets that could not be loaded, since the last time the loading screen was shown.
+    failed_assets: Vec<FailedAsset>,
     // Number of frames that everything needs to be ready for.
     // This is to prevent going into the fully loaded state in instances
     // where there might be a some frames between certain loading/pipelines action.
@@ -95,6 +149,22 @@ impl LoadingData {
     pub fn add_asset<C: Asset>(&mut self, asset: &Handle<C>) {
         self.loading_assets.push(asset.clone().untyped());
     }
+
+    pub fn remove_asset<C: Asset>(&mut self, asset: &Handle<C>) {
+        self.loading_assets.retain(|loading| loading.id() != asset.id().untyped());
+    }
+
+    pub fn failed_assets(&self) -> &[FailedAsset] {
+        &self.failed_assets
+    }
+}
+
+// An asset that failed to load, either by itself or because of one of its dependencies.
+#[derive(Debug, Clone)]
+pub struct FailedAsset {
+    pub id: UntypedAssetId,
+    pub path: String,
+    pub reason: String,
 }
 
 impl LoadingData {
@@ -102,6 +172,8 @@ impl LoadingData {
         Self {
             img: Handle::default(),
             loading_assets: Vec::new(),
+            finished_assets_count: 0,
+            failed_assets: Vec::new(),
             confirmation_frames_target,
             confirmation_frames_count: 0,
         }
@@ -139,20 +211,33 @@ fn update_loading_data(
         loading_data.confirmation_frames_count = 0;
 
         // Go through each asset and verify their load states.
-        // Any assets that are loaded are then added to the pop list for later removal.
+        // Any assets that are loaded or failed are then added to the pop list for later removal.
         let mut pop_list: Vec<usize> = Vec::new();
+        let mut failed_list: Vec<FailedAsset> = Vec::new();
         for (index, asset) in loading_data.loading_assets.iter().enumerate() {
-            if let Some(state) = asset_server.get_load_states(asset) {
-                if state.2.is_loaded() {
+            let Some(state) = asset_server.get_load_states(asset) else {
+                continue;
+            };
+            match state {
+                (LoadState::Failed(error), _, _) | (_, _, RecursiveDependencyLoadState::Failed(error)) => {
                     pop_list.push(index);
-                }
+                    failed_list.push(FailedAsset {
+                        id: asset.id(),
+                        path: asset.path().map(|path| path.to_string()).unwrap_or_default(),
+                        reason: error.to_string(),
+                    });
+                },
+                (_, _, recursive_state) if recursive_state.is_loaded() => pop_list.push(index),
+                _ => {},
             }
         }
 
-        // Remove all loaded assets from the loading_assets list.
+        // Remove all loaded and failed assets from the loading_assets list.
+        loading_data.finished_assets_count += pop_list.len();
         for i in pop_list.into_iter().rev() {
             loading_data.loading_assets.remove(i);
         }
+        loading_data.failed_assets.extend(failed_list);
 
         // If there are no more assets being monitored, and pipelines
         // are compiled, then start counting confirmation frames.
@@ -166,18 +251,80 @@ fn update_loading_data(
     }
 }
 
+// Forgets about the failures of the previous visualization.
+fn clear_failed_assets(mut loading_data: ResMut<LoadingData>) {
+    loading_data.failed_assets.clear();
+}
+
+// Starts counting the progress from zero for the next loading screen.
+fn reset_progress(mut loading_data: ResMut<LoadingData>) {
+    loading_data.finished_assets_count = 0;
+    crate::bind::reset_download_progress();
+}
+
 // Marker tag for loading screen components.
 #[derive(Component)]
 struct LoadingScreen;
 
+// Marker tags for the parts of the progress indicator.
+#[derive(Component)]
+struct ProgressText;
+#[derive(Component)]
+struct ProgressBytesText;
+#[derive(Component)]
+struct ProgressBar;
+
 // Determines when to show the loading screen
 fn update_loading_screen(
     mut image: Query<&mut Transform, (With<LoadingScreen>, With<Sprite>)>,
+    mut text: Query<&mut Text, (With<ProgressText>, Without<ProgressBytesText>)>,
+    mut bytes_text: Query<&mut Text, (With<ProgressBytesText>, Without<ProgressText>)>,
+    mut bar: Query<&mut Node, With<ProgressBar>>,
+    loading_data: Res<LoadingData>,
+    pipelines_ready: Res<PipelinesReady>,
     timer: Res<Time>,
 ) {
     image
         .iter_mut()
         .for_each(|mut x| x.rotate_z(-timer.delta_secs()));
+
+    let finished = loading_data.finished_assets_count;
+    let total = finished + loading_data.loading_assets.len();
+    let downloaded_bytes = crate::bind::get_downloaded_bytes();
+    // zero if unknown, i.e. if some server did not send the Content-Length
+    let total_bytes = crate::bind::get_total_bytes();
+
+    // pipelines are compiled as the last phase, once all assets are there
+    let (message, progress) = if total > finished {
+        let progress = if total_bytes > 0. {
+            downloaded_bytes / total_bytes
+        } else {
+            finished as f64 / total as f64
+        };
+        (format!("Loaded {finished} of {total} files"), progress)
+    } else if !pipelines_ready.0 {
+        ("Compiling shaders".to_string(), 1.)
+    } else {
+        ("Almost ready".to_string(), 1.)
+    };
+
+    let bytes_message = match (downloaded_bytes, total_bytes) {
+        (downloaded, _) if downloaded <= 0. => String::new(),
+        (downloaded, 0.) => format_bytes(downloaded),
+        (downloaded, total) => format!("{} of {}", format_bytes(downloaded.min(total)), format_bytes(total)),
+    };
+
+    text.iter_mut().for_each(|mut text| text.0 = message.clone());
+    bytes_text.iter_mut().for_each(|mut text| text.0 = bytes_message.clone());
+    bar.iter_mut().for_each(|mut node| node.width = Val::Percent(100. * progress.clamp(0., 1.) as f32));
+}
+
+pub fn format_bytes(bytes: f64) -> String {
+    match bytes {
+        bytes if bytes >= 1e6 => format!("{:.1} MB", bytes / 1e6),
+        bytes if bytes >= 1e3 => format!("{:.1} kB", bytes / 1e3),
+        bytes => format!("{bytes} B"),
+    }
 }
 
 mod pipelines_ready {
@@ -211,36 +358,14 @@ mod pipelines_ready {
     }
 }
 
-fn resize_meshes(mut commands: Commands, mut q: Query<(Entity, &Mesh3d)>, mut meshes: ResMut<Assets<Mesh>>,){
-    //for each entity with an associated mesh
-    let mut to_update = HashMap::new();
-    for (_, mesh) in &mut q{
-        if to_update.contains_key(&mesh.0){
-            continue;
-        }
-        // get the mesh throught the handle
-        meshes.get_mut(&mesh.0).and_then(|m|{
-            
-            // computing axis_aligned_bounding_box
-            let bounding = m.compute_aabb()?;
-            let center: Vec3 = bounding.center.into();
-            m.translate_by(-center);
-
-            let mut bounding = m.compute_aabb()?;
-            // get the max dimension
-            let elem = bounding.half_extents.max_element();
-            // rescale the mesh, and update the entity
-            m.scale_by(Vec3::splat(0.5/elem));
-            
-            bounding.half_extents*=0.5/elem;
-            bounding.center=Vec3A::splat(0.0);
-            to_update.insert(mesh.0.clone(), bounding);
-            Some(())
-        });
-    }
-    for (entity, mesh) in &mut q{
-        // is necessary to update the bounding boxes by hand
-        commands.entity(entity).insert(*to_update.get(&mesh.0).unwrap());
-    }
+#[cfg(test)]
+mod tests {
+    use crate::loading::format_bytes;
 
-}
\ No newline at end of file
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512.), "512 B");
+        assert_eq!(format_bytes(2_500.), "2.5 kB");
+        assert_eq!(format_bytes(50_123_456.), "50.1 MB");
+    }
+}
@@ -1,24 +1,57 @@
 mod loading;
 #[macro_use]
 mod bind;
+mod breadcrumb;
+mod bvh;
+mod clipping;
+mod dimensions;
+mod error_screen;
+mod formats;
+mod history;
+mod info_panel;
+mod integrity;
+mod labels;
+mod lay_flat;
+mod manifest;
+mod measure;
+mod mesh_stats;
 mod meshes_tree;
+mod navigation;
+mod pagination;
+mod pause;
+mod printability;
+mod render_modes;
 mod rotating;
+mod search;
+mod thumbnails;
+mod tooltip;
 
-use std::{iter::zip, sync::{Arc, Weak}};
+use std::{collections::HashMap, iter::zip, sync::{Arc, Weak}};
 
 use bevy::{
-    asset::AssetMetaCheck, color::palettes::tailwind::{CYAN_300, YELLOW_300}, diagnostic::LogDiagnosticsPlugin, ecs::system::SystemId, prelude::*, window::{PresentMode, WindowResized}
+    asset::AssetMetaCheck, color::palettes::tailwind::{CYAN_300, RED_400, YELLOW_300}, diagnostic::LogDiagnosticsPlugin, ecs::system::SystemId, prelude::*, window::{PresentMode, WindowResized}
 };
 //use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_panorbit_camera::{PanOrbitCamera, PanOrbitCameraPlugin};
+use dimensions::FitMesh;
+use formats::{load_model, Model};
+use history::{node_path_from_fragment, NodeHistory, PendingNodePath};
 use loading::{unload_current_visualization, LoadingData, LoadingState, VisualizationComponents};
+use manifest::{ManifestSource, MeshTreeManifest, PendingManifest};
+use mesh_stats::FilamentDensity;
 use meshes_tree::MeshTreeNode;
+use navigation::GridHighlight;
+use pagination::Pagination;
+use printability::Printability;
+use render_modes::RenderModes;
 use rotating::{rotate, Rotate};
+use thumbnails::ThumbnailAssets;
 
 #[derive(Resource, Component)]
 pub struct MeshTreeRes {
-    // not meant to be used, just to keep a strong reference to the root
-    _root: Arc<MeshTreeNode>,
+    // keeps a strong reference to the root, which is None until the manifest
+    // has been downloaded
+    root: Option<Arc<MeshTreeNode>>,
 
     // the current node of the tree to render (which might be a leave with
     // just one mesh or a menu with multiple meshes to select from)
@@ -28,6 +61,30 @@ pub struct MeshTreeRes {
     white_matl: Handle<StandardMaterial>,
     hover_matl: Handle<StandardMaterial>,
     pressed_matl: Handle<StandardMaterial>,
+    error_matl: Handle<StandardMaterial>,
+    // materials for the colors specified in the manifest, shared by all nodes with the same
+    // color so that the meshes can be drawn together
+    color_matls: HashMap<[u8; 4], Handle<StandardMaterial>>,
+
+    // shown in place of nodes without a mesh of their own
+    placeholder_mesh: Handle<Mesh>,
+}
+
+impl MeshTreeRes {
+    pub fn set_root(&mut self, root: Arc<MeshTreeNode>) {
+        self.current = Arc::downgrade(&root);
+        self.root = Some(root);
+    }
+
+    /// The material to render `node` with, i.e. the one with the color from the manifest, if any.
+    fn material_for(&mut self, node: &MeshTreeNode, materials: &mut Assets<StandardMaterial>) -> Handle<StandardMaterial> {
+        let Some(color) = node.metadata.color else {
+            return self.white_matl.clone();
+        };
+        self.color_matls.entry(color.to_srgba().to_u8_array())
+            .or_insert_with(|| materials.add(color))
+            .clone()
+    }
 }
 
 #[derive(Resource)]
@@ -46,6 +103,16 @@ impl FromWorld for OneShotSystemsRes {
 #[derive(Component)]
 pub struct BackButton;
 
+/// The tree node rendered by an entity, whose child is the entity with the actual mesh.
+#[derive(Component)]
+pub struct ShownNode(pub Weak<MeshTreeNode>);
+
+/// Marks the meshes laid out in a grid, i.e. the children of the current node to select from.
+#[derive(Component)]
+pub struct GridItem {
+    pub child_index: usize,
+}
+
 fn main() {
     //def.set(plugin)
     let window = WindowPlugin {
@@ -65,10 +132,30 @@ fn main() {
     App::new()
         // .add_plugins(WebAssetPlugin::default())
         .add_plugins(DefaultPlugins.set(asset).set(window))
-        .add_plugins(bevy_stl::StlPlugin)
+        .add_plugins(formats::FormatsPlugin)
         .add_plugins(LogDiagnosticsPlugin::default())
         .add_plugins(PanOrbitCameraPlugin)
         .add_plugins(loading::LoadingScreenPlugin)
+        .add_plugins(manifest::ManifestPlugin)
+        .add_plugins(history::HistoryPlugin)
+        .add_plugins(error_screen::ErrorScreenPlugin)
+        .add_plugins(labels::LabelsPlugin)
+        .add_plugins(thumbnails::ThumbnailsPlugin)
+        .add_plugins(tooltip::TooltipPlugin)
+        .add_plugins(info_panel::InfoPanelPlugin)
+        .add_plugins(pause::PausePlugin)
+        .add_plugins(mesh_stats::MeshStatsPlugin)
+        .add_plugins(integrity::IntegrityPlugin)
+        .add_plugins(dimensions::DimensionsPlugin)
+        .add_plugins(lay_flat::LayFlatPlugin)
+        .add_plugins(measure::MeasurePlugin)
+        .add_plugins(clipping::ClippingPlugin)
+        .add_plugins(render_modes::RenderModesPlugin)
+        .add_plugins(printability::PrintabilityPlugin)
+        .add_plugins(navigation::NavigationPlugin)
+        .add_plugins(pagination::PaginationPlugin)
+        .add_plugins(breadcrumb::BreadcrumbPlugin)
+        .add_plugins(search::SearchPlugin)
         .add_plugins(MeshPickingPlugin)
         //.add_plugins(WorldInspectorPlugin::new())
         //.add_plugins(FrameTimeDiagnosticsPlugin)
@@ -83,8 +170,10 @@ fn main() {
 fn setup(
     mut commands: Commands,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
     one_shot_systems: ResMut<OneShotSystemsRes>,
     asset_server: ResMut<AssetServer>,
+    mut loading_data: ResMut<LoadingData>,
 ) {
     // light
     let light = commands.spawn((
@@ -119,41 +208,54 @@ fn setup(
         Transform::default(),
         BackButton,
         Visibility::Hidden,
-    ));
+    )).observe(navigation::open_parent_on::<Pointer<Click>>);
 
     // materials
     let white_matl = materials.add(Color::WHITE);
     let hover_matl = materials.add(Color::from(CYAN_300));
     let pressed_matl = materials.add(Color::from(YELLOW_300));
+    let error_matl = materials.add(Color::from(RED_400));
+
+    // setup the main resource, the tree will be filled in once known
+    let mut mesh_tree = MeshTreeRes {
+        root: None,
+        current: Weak::new(),
+        white_matl,
+        hover_matl,
+        pressed_matl,
+        error_matl,
+        color_matls: HashMap::new(),
+        placeholder_mesh: meshes.add(Cuboid::from_length(1.)),
+    };
 
-    // load tree of meshes to navigate through
-    let mesh_tree_root = MeshTreeNode::from_json(r#"{
-        "url": "useless",
-        "children": [
-            { "url": "/benchy.stl" },
-            { "url": "/mendocino.stl" },
-            { "url": "/benchy.stl" },
-            { "url": "/mendocino.stl" },
-            { "url": "/benchy.stl" },
-            { "url": "/mendocino.stl" }
-        ]
-    }"#);
-    console_log!("Meshes: {mesh_tree_root:?}");
-    let initial_mesh_node = Arc::downgrade(&mesh_tree_root);
-
-    // setup the main resource
-    commands.insert_resource(
-        MeshTreeRes {
-            _root: mesh_tree_root,
-            current: initial_mesh_node,
-            white_matl,
-            hover_matl,
-            pressed_matl,
-        }
-    );
-
-    // show the initial entities
-    commands.run_system(one_shot_systems.update_current_sys);
+    // load tree of meshes to navigate through, as specified in the url fragment, which might
+    // also point at a nested node to open straight away
+    let fragment = bind::get_url_fragment();
+    let source = ManifestSource::from_fragment(&fragment);
+    let node_path = node_path_from_fragment(&fragment);
+    commands.insert_resource(NodeHistory::new(source.clone(), node_path.clone()));
+    commands.insert_resource(Pagination::from_fragment(&fragment));
+    commands.insert_resource(FilamentDensity::from_fragment(&fragment));
+    commands.insert_resource(RenderModes::from_fragment(&fragment));
+    commands.insert_resource(Printability::from_fragment(&fragment));
+    match source {
+        ManifestSource::Manifest(url) => {
+            console_log!("Loading manifest {url}");
+            let manifest: Handle<MeshTreeManifest> = asset_server.load(url);
+            loading_data.add_asset(&manifest);
+            commands.insert_resource(PendingManifest { handle: manifest, target: None });
+            if !node_path.is_empty() {
+                commands.insert_resource(PendingNodePath::new(node_path));
+            }
+            commands.insert_resource(mesh_tree);
+        },
+        ManifestSource::Mesh(url) => {
+            mesh_tree.set_root(MeshTreeNode::leaf(url));
+            commands.insert_resource(mesh_tree);
+            // show the initial entities
+            commands.run_system(one_shot_systems.update_current_sys);
+        },
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -161,16 +263,21 @@ fn update_current_sys(
     mut commands: Commands,
     asset_server: ResMut<AssetServer>,
     mut loading_data: ResMut<LoadingData>,
-    mesh_tree: Res<MeshTreeRes>,
-    current_meshes: Query<Entity, With<Mesh3d>>,
-    mut camera_pan_orbit: Query<&mut PanOrbitCamera, With<Camera3d>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut mesh_tree: ResMut<MeshTreeRes>,
+    current_meshes: Query<Entity, With<ShownNode>>,
+    mut camera_pan_orbit: Query<(&mut PanOrbitCamera, &mut Projection), With<Camera3d>>,
     mut back_button: Query<(&mut Visibility, &mut Transform), With<BackButton>>,
     window: Query<&Window>,
+    mut grid_highlight: ResMut<GridHighlight>,
+    mut pagination: ResMut<Pagination>,
+    thumbnail_assets: Res<ThumbnailAssets>,
 ) {
     console_log!("update_current_sys called");
 
     // despawn all current meshes
     current_meshes.iter().for_each(|entity| commands.entity(entity).despawn());
+    *grid_highlight = GridHighlight::default();
 
     // obtain some objects
     let Some(mesh_tree_node) = mesh_tree.current.upgrade() else {
@@ -178,7 +285,7 @@ fn update_current_sys(
         console_log!("update_current_sys: something went wrong, nothing to do");
         return;
     };
-    let mut camera_pan_orbit = camera_pan_orbit.single_mut().unwrap();
+    let (mut camera_pan_orbit, mut camera_projection) = camera_pan_orbit.single_mut().unwrap();
     let mut back_button = back_button.single_mut().unwrap();
     let window = window.single().unwrap();
 
@@ -195,82 +302,144 @@ fn update_current_sys(
         *back_button.0 = Visibility::Visible;
     }
 
+    // the children of this node are listed in another manifest, which needs to be loaded first
+    if let Some(manifest_url) = mesh_tree_node.pending_manifest() {
+        console_log!("Loading manifest {manifest_url}");
+        let manifest: Handle<MeshTreeManifest> = asset_server.load(manifest_url.to_string());
+        loading_data.add_asset(&manifest);
+        commands.insert_resource(PendingManifest { handle: manifest, target: Some(Arc::downgrade(&mesh_tree_node)) });
+        return;
+    }
+
     match get_render_mode(&mesh_tree_node) {
-        MeshRenderMode::Leaf { url } => {
+        MeshRenderMode::Leaf { node } => {
             // we need to render a single item and let the user move the camera
+            pagination.clear();
             camera_pan_orbit.enabled = true;
             camera_pan_orbit.target_radius = 1.5;
             camera_pan_orbit.target_yaw = 0.5;
             camera_pan_orbit.target_pitch = 0.5;
 
-            let model = asset_server.load(url);
-            loading_data.add_asset(&model);
+            // the mesh keeps its real size, the camera will be fitted to it once loaded
+            let model = load_model(&asset_server, &node.url, node.format());
+            let material = mesh_tree.material_for(&node, &mut materials);
             commands.spawn((
-                Mesh3d(model),
-                MeshMaterial3d(mesh_tree.white_matl.clone()),
-                Transform::from_rotation(Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2)),
+                Transform::default(),
                 VisualizationComponents,
                 Visibility::Hidden,
-            ));
+                ShownNode(Arc::downgrade(&node)),
+            )).with_children(|parent| {
+                let mut model_entity = parent.spawn((
+                    Transform::from_rotation(node.up_axis().rotation()),
+                    FitMesh { normalize: false },
+                ));
+                match model {
+                    Model::Mesh(mesh) => {
+                        loading_data.add_asset(&mesh);
+                        model_entity.insert((Mesh3d(mesh), MeshMaterial3d(material)));
+                    },
+                    Model::Scene(scene) => {
+                        loading_data.add_asset(&scene);
+                        model_entity.insert(SceneRoot(scene));
+                    },
+                }
+            });
         },
 
-        MeshRenderMode::Subtree { urls } => {
+        MeshRenderMode::Subtree { children } => {
             // we need to render multiple rotating items but the camera should stay still
             camera_pan_orbit.enabled = false;
             camera_pan_orbit.target_radius = 1.5;
             camera_pan_orbit.target_yaw = 0.0;
             camera_pan_orbit.target_pitch = 0.0;
-
-            let (positions, scale) = generate_positions(urls.len(), window.height(), window.width());
+            // undo the fitting to the size of the last leaf
+            camera_pan_orbit.target_focus = Vec3::ZERO;
+            camera_pan_orbit.zoom_lower_limit = 0.05;
+            camera_pan_orbit.zoom_upper_limit = None;
+            *camera_projection = Projection::Perspective(PerspectiveProjection::default());
+
+            // only a page of the children is shown
+            let page = pagination.show(&mesh_tree_node);
+            let (positions, scale, columns) = generate_positions(page.len(), window.height(), window.width());
+            *grid_highlight = GridHighlight::new(page.start, page.len(), columns);
             console_log!("positions {positions:?}, scale {scale}");
-            for ((child_index, url), (h, w)) in zip(urls, positions) {
-                let model = asset_server.load(url);
-                loading_data.add_asset(&model);
-                commands.spawn((
-                    Mesh3d(model),
-                    MeshMaterial3d(mesh_tree.white_matl.clone()),
-                    Transform::from_rotation(Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2))
-                        .with_scale(Vec3::splat(scale))
+            for ((child_index, child), (h, w)) in zip(children.iter().enumerate().skip(page.start).take(page.len()), positions) {
+                let mut item = commands.spawn((
+                    Transform::from_scale(Vec3::splat(scale))
                         .with_translation(Vec3 { x: w, y: h, z: 0.0 }),
                     VisualizationComponents,
                     Visibility::Hidden,
-                    Rotate,
-                ))
-                    .observe(update_material_on::<Pointer<Over>>(mesh_tree.hover_matl.clone()))
-                    .observe(update_material_on::<Pointer<Out>>(mesh_tree.white_matl.clone()))
-                    .observe(update_material_on::<Pointer<Press>>(mesh_tree.pressed_matl.clone()))
-                    .observe(child_child_as_current_on::<Pointer<Release>>(child_index));
+                    ShownNode(Arc::downgrade(child)),
+                    GridItem { child_index },
+                ));
+                if let Some(thumbnail) = &child.metadata.thumbnail {
+                    // the model is downloaded only when hovering the thumbnail
+                    let image = asset_server.load(thumbnail.clone());
+                    loading_data.add_asset(&image);
+                    thumbnails::spawn_thumbnail(&mut item, image, &thumbnail_assets, &mut materials, child_index);
+                    continue;
+                }
+
+                // nodes whose children were not loaded yet might have no mesh to show, while
+                // repeated urls share the same handle, and are thus drawn as instances of one mesh
+                let model = match child.preview() {
+                    Some(preview) => load_model(&asset_server, &preview.url, preview.format()),
+                    None => Model::Mesh(mesh_tree.placeholder_mesh.clone()),
+                };
+                let material = mesh_tree.material_for(child, &mut materials);
+                // the mesh will be scaled to fit in a unit cube once loaded
+                item.insert(Rotate).with_children(|parent| {
+                    let mut model_entity = parent.spawn((
+                        Transform::from_rotation(child.up_axis().rotation()),
+                        FitMesh { normalize: true },
+                    ));
+                    match model {
+                        Model::Mesh(mesh) => {
+                            loading_data.add_asset(&mesh);
+                            model_entity.insert((Mesh3d(mesh), MeshMaterial3d(material.clone())))
+                                .observe(update_material_on::<Pointer<Over>>(mesh_tree.hover_matl.clone()))
+                                .observe(update_material_on::<Pointer<Out>>(material))
+                                .observe(update_material_on::<Pointer<Press>>(mesh_tree.pressed_matl.clone()));
+                        },
+                        Model::Scene(scene) => {
+                            // pointer events on the meshes of the scene bubble up to its root
+                            loading_data.add_asset(&scene);
+                            model_entity.insert(SceneRoot(scene))
+                                .observe(update_scene_material_on::<Pointer<Over>>(Some(mesh_tree.hover_matl.clone())))
+                                .observe(update_scene_material_on::<Pointer<Out>>(None))
+                                .observe(update_scene_material_on::<Pointer<Press>>(Some(mesh_tree.pressed_matl.clone())));
+                        },
+                    }
+                    model_entity.observe(child_child_as_current_on::<Pointer<Release>>(child_index));
+                });
             }
+            // the nearby pages are loaded after the shown one, so that they are ready when turning the page
+            pagination.prefetch(&asset_server, &mesh_tree_node);
         },
     }
 }
 
 enum MeshRenderMode {
-    Leaf { url: String },
-    Subtree { urls: Vec<(usize, String)> },
+    Leaf { node: Arc<MeshTreeNode> },
+    Subtree { children: Vec<Arc<MeshTreeNode>> },
 }
 
 fn get_render_mode(mesh_tree_node: &Arc<MeshTreeNode>) -> MeshRenderMode {
     console_log!("get_render_mode {mesh_tree_node:?}");
 
-    if mesh_tree_node.children.is_empty() {
-        return MeshRenderMode::Leaf { url: mesh_tree_node.url.clone() };
+    if mesh_tree_node.is_leaf() {
+        return MeshRenderMode::Leaf { node: mesh_tree_node.clone() };
     }
 
-    if mesh_tree_node.children.len() == 1 {
-        if let Some(child) = mesh_tree_node.children.first() {
-            if child.children.is_empty() {
-                return MeshRenderMode::Leaf { url: child.url.clone() }
+    if mesh_tree_node.children().len() == 1 {
+        if let Some(child) = mesh_tree_node.children().first() {
+            if child.is_leaf() {
+                return MeshRenderMode::Leaf { node: child.clone() }
             }
         }
     }
 
-    MeshRenderMode::Subtree {
-        urls: mesh_tree_node.children.iter()
-            .enumerate()
-            .map(|(index, child)| (index, child.url.clone()))
-            .collect()
-    }
+    MeshRenderMode::Subtree { children: mesh_tree_node.children().to_vec() }
 }
 
 fn update_window_size(
@@ -288,7 +457,9 @@ fn update_window_size(
     }
 }
 
-fn generate_positions(mesh_count: usize, window_height: f32, window_width: f32) -> (Vec<(f32, f32)>, f32) {
+/// Lays out `mesh_count` meshes in a grid filling the window, returning the position of each
+/// mesh (row by row), their scale and the number of columns.
+fn generate_positions(mesh_count: usize, window_height: f32, window_width: f32) -> (Vec<(f32, f32)>, f32, usize) {
     let ratio = window_width / window_height;
     let height = (mesh_count as f32 / ratio).sqrt();
     let width = (mesh_count as f32 * ratio).sqrt();
@@ -317,7 +488,7 @@ fn generate_positions(mesh_count: usize, window_height: f32, window_width: f32)
     positions.truncate(mesh_count);
 
     let scale = f32::min(viewport_height / (height as f32), viewport_width / (width as f32))*0.9;
-    (positions, scale)
+    (positions, scale, width)
 }
 
 /// Returns an observer that updates the entity's material to the one specified.
@@ -334,13 +505,50 @@ fn update_material_on<E : EntityEvent>(
     }
 }
 
+/// The material a mesh of a glTF scene was loaded with, while it is replaced by another one.
+#[derive(Component)]
+struct SceneMaterial(Handle<StandardMaterial>);
+
+type SceneMaterialQuery<'w, 's> = Query<'w, 's, (&'static mut MeshMaterial3d<StandardMaterial>, Option<&'static SceneMaterial>)>;
+
+/// Returns an observer that updates the material of all the meshes in a scene to the one
+/// specified, or back to their own material if `None`.
+fn update_scene_material_on<E : EntityEvent>(
+    new_material: Option<Handle<StandardMaterial>>,
+) -> impl Fn(On<E>, Commands, Query<&Children>, SceneMaterialQuery) {
+    move |trigger, mut commands, children, mut query| {
+        set_scene_material(&mut commands, trigger.event().event_target(), new_material.as_ref(), &children, &mut query);
+    }
+}
+
+fn set_scene_material(
+    commands: &mut Commands,
+    scene: Entity,
+    new_material: Option<&Handle<StandardMaterial>>,
+    children: &Query<&Children>,
+    query: &mut SceneMaterialQuery,
+) {
+    for entity in children.iter_descendants(scene) {
+        let Ok((mut material, scene_material)) = query.get_mut(entity) else { continue; };
+        match (new_material, scene_material) {
+            (Some(new_material), Some(_)) => material.0 = new_material.clone(),
+            (Some(new_material), None) => {
+                commands.entity(entity).insert(SceneMaterial(material.0.clone()));
+                material.0 = new_material.clone();
+            },
+            (None, Some(scene_material)) => {
+                material.0 = scene_material.0.clone();
+                commands.entity(entity).remove::<SceneMaterial>();
+            },
+            (None, None) => {},
+        }
+    }
+}
+
 fn child_child_as_current_on<E : EntityEvent>(
     child_index: usize
 ) -> impl Fn(On<E>, Commands, ResMut<MeshTreeRes>, ResMut<OneShotSystemsRes>) {
     move |_, mut commands, mut mesh_tree, one_shot_systems| {
-        let Some(current) = mesh_tree.current.upgrade() else { return; };
-        let Some(child) = current.children.get(child_index) else { return; };
-        mesh_tree.current = Arc::downgrade(child);
-        commands.run_system(one_shot_systems.update_current_sys);
+        navigation::open_child(&mut commands, &mut mesh_tree, &one_shot_systems, child_index);
     }
 }
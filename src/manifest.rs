@@ -0,0 +1,243 @@
+//! Loads the tree of meshes to show, as specified in the url fragment of the page.
+
+use std::{borrow::Cow, sync::{Arc, Weak}};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext, LoadState},
+    prelude::*,
+};
+use thiserror::Error;
+
+use crate::{
+    error_screen::show_error_screen, history::PendingNodePath, loading::LoadingData,
+    meshes_tree::{MeshTreeError, MeshTreeNode, MeshTreeWarning}, MeshTreeRes, OneShotSystemsRes,
+};
+
+/// The manifest used when the url fragment is empty.
+pub const DEFAULT_MANIFEST_URL: &str = "/tree.json";
+
+pub struct ManifestPlugin;
+
+impl Plugin for ManifestPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<MeshTreeManifest>()
+            .init_asset_loader::<MeshTreeManifestLoader>()
+            .add_systems(Update, receive_manifest.run_if(resource_exists::<PendingManifest>));
+    }
+}
+
+/// Where to take the meshes to show from, obtained from the url fragment, which can be:
+/// - empty, to load [`DEFAULT_MANIFEST_URL`]
+/// - `manifest=<url>`, to load the mesh tree manifest at `<url>`
+/// - any other url, to show just that mesh (e.g. `#http://localhost:8080/benchy.stl`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestSource {
+    Manifest(String),
+    Mesh(String),
+}
+
+impl ManifestSource {
+    pub fn from_fragment(fragment: &str) -> ManifestSource {
+        let fragment = fragment.trim_start_matches('#').trim();
+        if fragment.is_empty() {
+            return ManifestSource::Manifest(DEFAULT_MANIFEST_URL.to_string());
+        }
+
+        let Some(parameters) = fragment_parameters(fragment) else {
+            return if fragment.ends_with(".json") {
+                ManifestSource::Manifest(fragment.to_string())
+            } else {
+                ManifestSource::Mesh(fragment.to_string())
+            };
+        };
+
+        parameters.into_iter()
+            .find_map(|(key, value)| match key {
+                "manifest" => Some(ManifestSource::Manifest(value.to_string())),
+                "mesh" => Some(ManifestSource::Mesh(value.to_string())),
+                _ => None,
+            })
+            .unwrap_or_else(|| ManifestSource::Manifest(DEFAULT_MANIFEST_URL.to_string()))
+    }
+}
+
+/// Splits the url fragment into its `key=value` parameters, or returns `None` if the fragment is a
+/// bare url. The fragment is made of parameters only if it starts with a plain identifier
+/// followed by `=`, since a bare url might contain `=` too.
+///
+/// The values are percent-decoded. Since parameters are separated by `&`, a `&` in a value (e.g.
+/// in the query of a `manifest=` url) must be encoded as `%26`.
+pub fn fragment_parameters(fragment: &str) -> Option<Vec<(&str, Cow<'_, str>)>> {
+    let fragment = fragment.trim_start_matches('#').trim();
+    let is_parameter_list = fragment.split_once('=').is_some_and(|(key, _)| {
+        !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    });
+    is_parameter_list.then(|| fragment.split('&')
+        .filter_map(|parameter| parameter.split_once('='))
+        .map(|(key, value)| (key, percent_decode(value)))
+        .collect())
+}
+
+/// Decodes the `%XX` escapes of `value`, keeping the `%` signs that do not start one as they are.
+fn percent_decode(value: &str) -> Cow<'_, str> {
+    if !value.contains('%') {
+        return Cow::Borrowed(value);
+    }
+    let hex_digit = |byte: u8| (byte as char).to_digit(16).map(|digit| digit as u8);
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%').then(|| bytes.get(i + 1..i + 3)).flatten()
+            .and_then(|hex| Some(hex_digit(hex[0])? << 4 | hex_digit(hex[1])?));
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    Cow::Owned(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+/// A mesh tree, as loaded from a JSON manifest through the `AssetServer`.
+#[derive(Asset, TypePath, Debug)]
+pub struct MeshTreeManifest {
+    pub root: Arc<MeshTreeNode>,
+    pub warnings: Vec<MeshTreeWarning>,
+}
+
+#[derive(Default, TypePath)]
+pub struct MeshTreeManifestLoader;
+
+#[derive(Error, Debug)]
+pub enum MeshTreeManifestError {
+    #[error("Failed to read manifest: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid manifest: {0}")]
+    Tree(#[from] MeshTreeError),
+}
+
+impl AssetLoader for MeshTreeManifestLoader {
+    type Asset = MeshTreeManifest;
+    type Settings = ();
+    type Error = MeshTreeManifestError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let manifest_url = load_context.path().to_string();
+        let (root, warnings) = MeshTreeNode::from_json(&bytes, &manifest_url)?;
+        Ok(MeshTreeManifest { root, warnings })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["json"]
+    }
+}
+
+/// The manifest that is being downloaded, which will provide the children of `target`, or
+/// become the root of [`MeshTreeRes`] if there is no target.
+#[derive(Resource)]
+pub struct PendingManifest {
+    pub handle: Handle<MeshTreeManifest>,
+    pub target: Option<Weak<MeshTreeNode>>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn receive_manifest(
+    mut commands: Commands,
+    pending: Res<PendingManifest>,
+    manifests: Res<Assets<MeshTreeManifest>>,
+    asset_server: Res<AssetServer>,
+    mut loading_data: ResMut<LoadingData>,
+    mut mesh_tree: ResMut<MeshTreeRes>,
+    one_shot_systems: Res<OneShotSystemsRes>,
+    pending_node_path: Option<Res<PendingNodePath>>,
+) {
+    if let Some(manifest) = manifests.get(&pending.handle) {
+        console_log!("Meshes: {:?}", manifest.root);
+        for warning in &manifest.warnings {
+            console_log!("Manifest warning: {warning}");
+        }
+        match &pending.target {
+            None => mesh_tree.set_root(manifest.root.clone()),
+            Some(target) => if let Some(target) = target.upgrade() {
+                target.load_children(&manifest.root);
+            },
+        }
+        commands.remove_resource::<PendingManifest>();
+        // while following the node path of the url, only the node at its end gets shown
+        if pending_node_path.is_none() {
+            commands.run_system(one_shot_systems.update_current_sys);
+        }
+
+    } else if let LoadState::Failed(error) = asset_server.load_state(&pending.handle) {
+        console_log!("Could not load manifest: {error}");
+        // stop waiting for the manifest, so that the loading screen goes away
+        loading_data.remove_asset(&pending.handle);
+        commands.remove_resource::<PendingManifest>();
+        // without the root there is nothing to show, while failed sub-manifests are just
+        // listed along with the other assets that failed to load
+        if pending.target.is_none() {
+            show_error_screen(&mut commands, "Could not load the list of models", &error.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::manifest::{fragment_parameters, ManifestSource, DEFAULT_MANIFEST_URL};
+
+    #[test]
+    fn test_from_fragment() {
+        assert_eq!(ManifestSource::from_fragment(""), ManifestSource::Manifest(DEFAULT_MANIFEST_URL.to_string()));
+        assert_eq!(
+            ManifestSource::from_fragment("#manifest=https://host/tree.json"),
+            ManifestSource::Manifest("https://host/tree.json".to_string())
+        );
+        assert_eq!(
+            ManifestSource::from_fragment("http://localhost:8080/benchy.stl"),
+            ManifestSource::Mesh("http://localhost:8080/benchy.stl".to_string())
+        );
+        assert_eq!(
+            ManifestSource::from_fragment("https://host/get?file=a.stl"),
+            ManifestSource::Mesh("https://host/get?file=a.stl".to_string())
+        );
+        assert_eq!(
+            ManifestSource::from_fragment("/catalog/tree.json"),
+            ManifestSource::Manifest("/catalog/tree.json".to_string())
+        );
+        assert_eq!(ManifestSource::from_fragment("node=1/2"), ManifestSource::Manifest(DEFAULT_MANIFEST_URL.to_string()));
+        assert_eq!(
+            ManifestSource::from_fragment("manifest=https://host/tree.json?a=1%26b=2&node=1"),
+            ManifestSource::Manifest("https://host/tree.json?a=1&b=2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_fragment_parameters() {
+        assert_eq!(fragment_parameters("/tree.json"), None);
+        assert_eq!(
+            fragment_parameters("#manifest=/my%20models/tree.json&density=1.1").unwrap(),
+            vec![("manifest", "/my models/tree.json".into()), ("density", "1.1".into())]
+        );
+        // a literal `&` ends the value
+        assert_eq!(
+            fragment_parameters("manifest=/tree.json?a=1&b=2").unwrap(),
+            vec![("manifest", "/tree.json?a=1".into()), ("b", "2".into())]
+        );
+        // invalid escapes are kept as they are
+        assert_eq!(fragment_parameters("mesh=/100%.stl%2").unwrap(), vec![("mesh", "/100%.stl%2".into())]);
+        assert_eq!(fragment_parameters("mesh=/%C3%A9.stl").unwrap(), vec![("mesh", "/é.stl".into())]);
+    }
+}
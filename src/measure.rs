@@ -0,0 +1,340 @@
+//! Measures the model rendered on its own: pressing M switches between measuring the distance
+//! between two points clicked on its surface, the angle between three points, and not measuring.
+//! Points clicked near a corner or an edge of the model snap to it, and lengths are given in the
+//! real units of the model.
+
+use bevy::{
+    color::palettes::tailwind::{GRAY_400, ORANGE_400},
+    picking::{mesh_picking::ray_cast::{MeshRayCast, MeshRayCastSettings}, pointer::PointerButton},
+    prelude::*,
+};
+
+use crate::{dimensions::{format_length, CurrentLeaf, ModelDimensions, TrackCurrentLeaf}, search::not_typing};
+
+pub struct MeasurePlugin;
+
+impl Plugin for MeasurePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Measure>()
+            .init_resource::<MeasureAssets>()
+            .add_systems(Update, (
+                switch_measure_mode.run_if(not_typing),
+                follow_leaf.after(TrackCurrentLeaf).run_if(resource_changed::<CurrentLeaf>),
+                pick_points,
+                show_measurement.run_if(resource_changed::<Measure>),
+                place_measure_labels,
+            ).chain());
+    }
+}
+
+/// What clicking on the model measures.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MeasureMode {
+    #[default]
+    Off,
+    Distance,
+    Angle,
+}
+
+impl MeasureMode {
+    fn next(self) -> MeasureMode {
+        match self {
+            MeasureMode::Off => MeasureMode::Distance,
+            MeasureMode::Distance => MeasureMode::Angle,
+            MeasureMode::Angle => MeasureMode::Off,
+        }
+    }
+
+    /// How many points make a measurement.
+    fn point_count(self) -> usize {
+        match self {
+            MeasureMode::Off => 0,
+            MeasureMode::Distance => 2,
+            MeasureMode::Angle => 3,
+        }
+    }
+}
+
+/// The measurement being made on the leaf shown.
+#[derive(Resource, Default)]
+pub struct Measure {
+    mode: MeasureMode,
+    /// the leaf entity being measured, i.e. the [`ShownNode`] whose model is clicked
+    target: Option<Entity>,
+    /// the points clicked so far, in world coordinates, which are the coordinates of the model
+    /// since leaves are not scaled
+    points: Vec<Vec3>,
+}
+
+/// The meshes and material drawing the measurements.
+#[derive(Resource)]
+struct MeasureAssets {
+    /// a sphere of radius 1, placed on each point
+    marker: Handle<Mesh>,
+    /// a cylinder of radius 1 and height 1 along Y, joining the points
+    line: Handle<Mesh>,
+    matl: Handle<StandardMaterial>,
+}
+
+impl FromWorld for MeasureAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let marker = meshes.add(Sphere::new(1.));
+        let line = meshes.add(Cylinder::new(1., 1.));
+        let matl = world.resource_mut::<Assets<StandardMaterial>>().add(StandardMaterial {
+            base_color: Color::from(ORANGE_400),
+            unlit: true,
+            ..default()
+        });
+        MeasureAssets { marker, line, matl }
+    }
+}
+
+/// Marks the entities showing the measurement, which are respawned whenever it changes.
+#[derive(Component)]
+struct MeasureMarker;
+
+/// The text with the measured value, kept next to the point `anchor` of the model.
+#[derive(Component)]
+struct MeasureLabel {
+    anchor: Vec3,
+}
+
+/// How far the pointer can move between pressing and releasing the button to still count as a
+/// click on the model, rather than as dragging the camera around.
+pub const DRAG_THRESHOLD: f32 = 4.;
+/// How close on screen, in pixels, a point must be to a corner or an edge to snap to it.
+const VERTEX_SNAP: f32 = 12.;
+const EDGE_SNAP: f32 = 8.;
+/// The radius of the markers, relative to the diagonal of the bounding box of the model.
+const MARKER_SIZE: f32 = 0.006;
+
+fn switch_measure_mode(keys: Res<ButtonInput<KeyCode>>, mut measure: ResMut<Measure>) {
+    if keys.just_pressed(KeyCode::KeyM) {
+        measure.mode = measure.mode.next();
+        measure.points.clear();
+        console_log!("Measure mode {:?}", measure.mode);
+    }
+    if keys.just_pressed(KeyCode::Delete) && !measure.points.is_empty() {
+        measure.points.clear();
+    }
+}
+
+/// Starts over whenever another leaf is shown, or the model is turned, e.g. laid flat.
+fn follow_leaf(mut measure: ResMut<Measure>, current_leaf: Res<CurrentLeaf>) {
+    measure.target = current_leaf.0;
+    measure.points.clear();
+}
+
+/// Adds a point where the model is clicked. The ray is cast again, since the hit reported by the
+/// click lacks the triangle needed for snapping.
+#[allow(clippy::too_many_arguments)]
+fn pick_points(
+    mut presses: MessageReader<Pointer<Press>>,
+    mut clicks: MessageReader<Pointer<Click>>,
+    mut pressed_at: Local<Option<Vec2>>,
+    mut measure: ResMut<Measure>,
+    mut ray_cast: MeshRayCast,
+    camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    parents: Query<&ChildOf>,
+) {
+    for press in presses.read() {
+        if press.button == PointerButton::Primary {
+            *pressed_at = Some(press.pointer_location.position);
+        }
+    }
+    for click in clicks.read() {
+        let position = click.pointer_location.position;
+        let dragged = pressed_at.take().is_none_or(|start| start.distance(position) > DRAG_THRESHOLD);
+        if click.button != PointerButton::Primary || dragged || measure.mode == MeasureMode::Off {
+            continue;
+        }
+        let Some(target) = measure.target else { continue; };
+        let in_model = |entity: Entity| entity == target || parents.iter_ancestors(entity).any(|parent| parent == target);
+        if !in_model(click.entity) {
+            continue;
+        }
+
+        let Ok((camera, camera_transform)) = camera.single() else { continue; };
+        let Ok(ray) = camera.viewport_to_world(camera_transform, position) else { continue; };
+        let settings = MeshRayCastSettings::default().with_filter(&in_model);
+        let Some((_, hit)) = ray_cast.cast_ray(ray, &settings).first() else { continue; };
+        let to_screen = |point: Vec3| camera.world_to_viewport(camera_transform, point).ok();
+        let point = match hit.triangle {
+            Some(triangle) => snap(hit.point, triangle, to_screen),
+            None => hit.point,
+        };
+
+        // a complete measurement is replaced by a new one
+        if measure.points.len() >= measure.mode.point_count() {
+            measure.points.clear();
+        }
+        measure.points.push(point);
+    }
+}
+
+/// Moves `point`, which lies on `triangle`, to the closest corner of the triangle if it is close
+/// enough on screen, or else to the closest point of its edges.
+fn snap(point: Vec3, triangle: [Vec3; 3], to_screen: impl Fn(Vec3) -> Option<Vec2>) -> Vec3 {
+    let Some(on_screen) = to_screen(point) else { return point; };
+    let closest = |candidates: [Vec3; 3]| {
+        candidates.into_iter()
+            .map(|candidate| (candidate, to_screen(candidate).map_or(f32::INFINITY, |other| other.distance(on_screen))))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap()
+    };
+
+    let (vertex, distance) = closest(triangle);
+    if distance <= VERTEX_SNAP {
+        return vertex;
+    }
+    let edges = [0, 1, 2].map(|index| closest_on_segment(point, triangle[index], triangle[(index + 1) % 3]));
+    let (edge_point, distance) = closest(edges);
+    if distance <= EDGE_SNAP {
+        return edge_point;
+    }
+    point
+}
+
+fn closest_on_segment(point: Vec3, start: Vec3, end: Vec3) -> Vec3 {
+    let direction = end - start;
+    let t = (point - start).dot(direction) / direction.length_squared().max(f32::EPSILON);
+    start + direction * t.clamp(0., 1.)
+}
+
+/// The measured value once all the points have been clicked, with the point to show it at.
+fn measurement(mode: MeasureMode, points: &[Vec3], mm_per_world_unit: f32) -> Option<(String, Vec3)> {
+    match (mode, points) {
+        (MeasureMode::Distance, [start, end]) => {
+            Some((format_length(start.distance(*end) * mm_per_world_unit), start.midpoint(*end)))
+        },
+        (MeasureMode::Angle, [first, vertex, last]) => {
+            let angle = (*first - *vertex).angle_between(*last - *vertex).to_degrees();
+            Some((format!("{angle:.1}°"), *vertex))
+        },
+        _ => None,
+    }
+}
+
+fn show_measurement(
+    mut commands: Commands,
+    measure: Res<Measure>,
+    measure_assets: Res<MeasureAssets>,
+    leaves: Query<&ModelDimensions>,
+    markers: Query<Entity, With<MeasureMarker>>,
+) {
+    markers.iter().for_each(|entity| commands.entity(entity).despawn());
+    let Some(dimensions) = measure.target.and_then(|target| leaves.get(target).ok()) else { return; };
+    let hint = match measure.mode {
+        MeasureMode::Off => return,
+        MeasureMode::Distance => "Measuring distances: click two points",
+        MeasureMode::Angle => "Measuring angles: click three points, the angle is at the second one",
+    };
+
+    commands.spawn((
+        MeasureMarker,
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(12.),
+            left: Val::Px(12.),
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(2.),
+            padding: UiRect::all(Val::Px(8.)),
+            ..default()
+        },
+        BackgroundColor(Color::BLACK.with_alpha(0.6)),
+        Pickable::IGNORE,
+        children![
+            (
+                Text::new(hint),
+                TextFont { font_size: 14., ..default() },
+                TextColor(Color::WHITE),
+            ),
+            (
+                Text::new("M to switch mode, Delete to clear"),
+                TextFont { font_size: 12., ..default() },
+                TextColor(Color::from(GRAY_400)),
+            ),
+        ],
+    ));
+
+    // the markers keep the same size relative to the model, whatever its units
+    let radius = MARKER_SIZE * dimensions.size_mm.length() / dimensions.mm_per_world_unit;
+    for point in &measure.points {
+        commands.spawn((
+            MeasureMarker,
+            Mesh3d(measure_assets.marker.clone()),
+            MeshMaterial3d(measure_assets.matl.clone()),
+            Transform::from_translation(*point).with_scale(Vec3::splat(radius)),
+            Pickable::IGNORE,
+        ));
+    }
+    for segment in measure.points.windows(2) {
+        let (start, end) = (segment[0], segment[1]);
+        commands.spawn((
+            MeasureMarker,
+            Mesh3d(measure_assets.line.clone()),
+            MeshMaterial3d(measure_assets.matl.clone()),
+            Transform::from_translation(start.midpoint(end))
+                .with_rotation(Quat::from_rotation_arc(Vec3::Y, (end - start).normalize_or(Vec3::Y)))
+                .with_scale(Vec3::new(radius / 3., start.distance(end), radius / 3.)),
+            Pickable::IGNORE,
+        ));
+    }
+
+    let Some((text, anchor)) = measurement(measure.mode, &measure.points, dimensions.mm_per_world_unit) else { return; };
+    console_log!("Measured {text}");
+    commands.spawn((
+        MeasureMarker,
+        MeasureLabel { anchor },
+        Text::new(text),
+        TextFont { font_size: 16., ..default() },
+        TextColor(Color::WHITE),
+        Node {
+            position_type: PositionType::Absolute,
+            padding: UiRect::axes(Val::Px(6.), Val::Px(2.)),
+            ..default()
+        },
+        BackgroundColor(Color::BLACK.with_alpha(0.75)),
+        Pickable::IGNORE,
+    ));
+}
+
+/// Keeps the labels next to their point while the camera moves.
+fn place_measure_labels(
+    mut labels: Query<(&MeasureLabel, &mut Node)>,
+    camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+) {
+    let Ok((camera, camera_transform)) = camera.single() else { return; };
+    for (label, mut node) in &mut labels {
+        let Ok(position) = camera.world_to_viewport(camera_transform, label.anchor) else { continue; };
+        node.left = Val::Px(position.x + 8.);
+        node.top = Val::Px(position.y + 8.);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{Vec2, Vec3, Vec3Swizzles};
+
+    use crate::measure::{measurement, snap, MeasureMode};
+
+    #[test]
+    fn test_snap() {
+        // seen from above, one unit of the model being one pixel
+        let triangle = [Vec3::ZERO, Vec3::new(100., 0., 0.), Vec3::new(0., 100., 0.)];
+        let to_screen = |point: Vec3| Some(point.xy());
+        assert_eq!(snap(Vec3::new(95., 3., 0.), triangle, to_screen), Vec3::new(100., 0., 0.));
+        assert_eq!(snap(Vec3::new(50., 5., 0.), triangle, to_screen), Vec3::new(50., 0., 0.));
+        assert_eq!(snap(Vec3::new(30., 30., 0.), triangle, to_screen), Vec3::new(30., 30., 0.));
+        assert_eq!(snap(Vec3::new(30., 30., 0.), triangle, |_: Vec3| None::<Vec2>), Vec3::new(30., 30., 0.));
+    }
+
+    #[test]
+    fn test_measurement() {
+        let points = [Vec3::new(1., 0., 0.), Vec3::ZERO, Vec3::new(0., 2., 0.)];
+        assert_eq!(measurement(MeasureMode::Distance, &points[..1], 1.), None);
+        assert_eq!(measurement(MeasureMode::Distance, &points[1..], 25.4), Some(("50.8 mm".to_string(), Vec3::new(0., 1., 0.))));
+        assert_eq!(measurement(MeasureMode::Angle, &points, 1.), Some(("90.0°".to_string(), Vec3::ZERO)));
+    }
+}
@@ -0,0 +1,300 @@
+//! Adds statistics about the model to the info panel of a leaf: triangle and vertex counts,
+//! volume, surface area, bounding box and center of mass in the units of the file, and the mass
+//! of the printed model. They are computed in a task, pausing now and then since big meshes take
+//! a while, so that the viewer keeps running meanwhile.
+//!
+//! The density of the filament defaults to the one of PLA, and can be set in g/cm³ in the url
+//! fragment, e.g. `#manifest=/tree.json&density=1.04`.
+
+use std::collections::HashSet;
+
+use bevy::{
+    color::palettes::tailwind::GRAY_400,
+    math::Affine3A,
+    mesh::PrimitiveTopology,
+    prelude::*,
+    tasks::{block_on, poll_once, AsyncComputeTaskPool, Task},
+};
+
+use crate::{
+    dimensions::{mesh_to_model, round_to_significant, FitMesh, ModelDimensions}, info_panel::InfoPanel, manifest::fragment_parameters,
+    meshes_tree::Units, pause::{pause, TRIANGLES_PER_PAUSE}, GridItem, ShownNode,
+};
+
+pub struct MeshStatsPlugin;
+
+impl Plugin for MeshStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (start_stats_tasks, finish_stats_tasks).chain().run_if(resource_exists::<FilamentDensity>));
+    }
+}
+
+/// The density of PLA, the most common filament.
+const DEFAULT_DENSITY: f32 = 1.24;
+
+/// The density of the filament in g/cm³, used to estimate the mass of the printed models.
+#[derive(Resource)]
+pub struct FilamentDensity(pub f32);
+
+impl FilamentDensity {
+    /// Reads the `density` parameter of the url fragment, if any.
+    pub fn from_fragment(fragment: &str) -> FilamentDensity {
+        let density = fragment_parameters(fragment).unwrap_or_default().into_iter()
+            .find(|(key, _)| *key == "density")
+            .and_then(|(_, value)| value.parse().ok())
+            .filter(|density: &f32| *density > 0.)
+            .unwrap_or(DEFAULT_DENSITY);
+        FilamentDensity(density)
+    }
+}
+
+/// A mesh of the model, with its transform relative to the model, so that the statistics are
+/// given in the coordinates of the file.
+struct MeshPart {
+    positions: Vec<Vec3>,
+    indices: Vec<u32>,
+    transform: Affine3A,
+}
+
+impl MeshPart {
+    fn new(mesh: &Mesh, transform: Affine3A) -> Option<MeshPart> {
+        if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+            return None;
+        }
+        let positions: Vec<Vec3> = mesh.attribute(Mesh::ATTRIBUTE_POSITION)?
+            .as_float3()?
+            .iter()
+            .map(|position| Vec3::from(*position))
+            .collect();
+        let indices = match mesh.indices() {
+            Some(indices) => indices.iter().map(|index| index as u32).collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        Some(MeshPart { positions, indices, transform })
+    }
+}
+
+/// The statistics of a model, in the units of its file.
+#[derive(Debug, Clone, Copy)]
+struct MeshStats {
+    triangles: usize,
+    /// the distinct positions, since files like STL repeat them for each triangle
+    vertices: usize,
+    /// negative when the triangles are wound the wrong way, i.e. the model is inside out
+    volume: f32,
+    area: f32,
+    min: Vec3,
+    max: Vec3,
+    /// the centroid of the volume, assuming the model is closed and solid
+    center_of_mass: Option<Vec3>,
+}
+
+/// Sums the signed volumes of the tetrahedra made by each triangle with the origin, which add
+/// up to the volume of closed meshes.
+async fn compute_stats(parts: &[MeshPart]) -> MeshStats {
+    let mut stats = MeshStats {
+        triangles: 0,
+        vertices: 0,
+        volume: 0.,
+        area: 0.,
+        min: Vec3::INFINITY,
+        max: Vec3::NEG_INFINITY,
+        center_of_mass: None,
+    };
+    let mut vertices = HashSet::new();
+    let mut moment = Vec3::ZERO;
+    for part in parts {
+        let positions: Vec<Vec3> = part.positions.iter().map(|position| part.transform.transform_point3(*position)).collect();
+        for chunk in positions.chunks(TRIANGLES_PER_PAUSE) {
+            for position in chunk {
+                vertices.insert(position.to_array().map(f32::to_bits));
+                stats.min = stats.min.min(*position);
+                stats.max = stats.max.max(*position);
+            }
+            pause().await;
+        }
+        for chunk in part.indices.chunks(3 * TRIANGLES_PER_PAUSE) {
+            for triangle in chunk.chunks_exact(3) {
+                let [Some(a), Some(b), Some(c)] = [0, 1, 2].map(|corner| positions.get(triangle[corner] as usize)) else {
+                    continue;
+                };
+                let volume = a.dot(b.cross(*c)) / 6.;
+                stats.triangles += 1;
+                stats.volume += volume;
+                stats.area += (b - a).cross(c - a).length() / 2.;
+                moment += volume * (a + b + c) / 4.;
+            }
+            pause().await;
+        }
+    }
+    stats.vertices = vertices.len();
+    if stats.volume.abs() > f32::EPSILON {
+        stats.center_of_mass = Some(moment / stats.volume);
+    }
+    stats
+}
+
+/// The statistics being computed for the leaf entity `target`.
+#[derive(Component)]
+struct StatsTask {
+    target: Entity,
+    task: Task<MeshStats>,
+}
+
+/// The part of the info panel listing the statistics of the leaf entity `target`.
+#[derive(Component)]
+struct StatsSection {
+    target: Entity,
+}
+
+/// Starts computing the statistics of the leaves as soon as their model is loaded and fitted.
+#[allow(clippy::type_complexity)]
+fn start_stats_tasks(
+    mut commands: Commands,
+    new_leaves: Query<(Entity, &Children), (Added<ModelDimensions>, With<ShownNode>, Without<GridItem>)>,
+    models: Query<(), With<FitMesh>>,
+    descendants: Query<&Children>,
+    nodes: Query<(&Transform, &ChildOf, Option<&Mesh3d>)>,
+    meshes: Res<Assets<Mesh>>,
+    panels: Query<(Entity, &InfoPanel)>,
+) {
+    for (leaf, children) in &new_leaves {
+        let mut parts = Vec::new();
+        for model in children.iter().filter(|child| models.contains(*child)) {
+            for entity in [model].into_iter().chain(descendants.iter_descendants(model)) {
+                let Ok((_, _, Some(mesh))) = nodes.get(entity) else { continue; };
+                let Some(mesh) = meshes.get(&mesh.0) else { continue; };
+                let mesh_to_model = mesh_to_model(entity, model, &nodes);
+                parts.extend(MeshPart::new(mesh, mesh_to_model));
+            }
+        }
+        if parts.is_empty() {
+            continue;
+        }
+
+        let task = AsyncComputeTaskPool::get().spawn(async move { compute_stats(&parts).await });
+        commands.spawn(StatsTask { target: leaf, task });
+        for (panel, _) in panels.iter().filter(|(_, panel)| panel.target == leaf) {
+            commands.entity(panel).with_children(|panel| {
+                panel.spawn((
+                    StatsSection { target: leaf },
+                    Node {
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(4.),
+                        margin: UiRect::top(Val::Px(6.)),
+                        ..default()
+                    },
+                    children![(
+                        Text::new("Computing statistics…"),
+                        TextFont { font_size: 13., ..default() },
+                        TextColor(Color::from(GRAY_400)),
+                    )],
+                ));
+            });
+        }
+    }
+}
+
+/// Fills in the statistics once computed, and drops the tasks of the leaves no longer shown.
+fn finish_stats_tasks(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut StatsTask)>,
+    leaves: Query<&ShownNode>,
+    sections: Query<(Entity, &StatsSection)>,
+    density: Res<FilamentDensity>,
+) {
+    for (entity, mut task) in &mut tasks {
+        let Some(node) = leaves.get(task.target).ok().and_then(|shown_node| shown_node.0.upgrade()) else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+        let Some(stats) = block_on(poll_once(&mut task.task)) else { continue; };
+        commands.entity(entity).despawn();
+        console_log!("Statistics of {}: {stats:?}", node.display_name());
+
+        for (section, _) in sections.iter().filter(|(_, section)| section.target == task.target) {
+            commands.entity(section).despawn_children().with_children(|section| {
+                for line in stats_lines(&stats, node.units(), density.0) {
+                    section.spawn((
+                        Text::new(line),
+                        TextFont { font_size: 13., ..default() },
+                        TextColor(Color::from(GRAY_400)),
+                    ));
+                }
+            });
+        }
+    }
+}
+
+/// The lines of text describing the statistics of a model in `units`, whose filament has the
+/// given `density` in g/cm³.
+fn stats_lines(stats: &MeshStats, units: Units, density: f32) -> Vec<String> {
+    let symbol = units.symbol();
+    let mm = units.millimeters();
+    let mut lines = vec![format!("Triangles: {}, vertices: {}", stats.triangles, stats.vertices)];
+    if stats.triangles == 0 {
+        return lines;
+    }
+
+    let volume_mm3 = stats.volume.abs() * mm.powi(3);
+    let inside_out = if stats.volume < 0. { " (inside out)" } else { "" };
+    lines.push(format!("Volume: {}{inside_out}", format_metric(volume_mm3, "mm³", "cm³", 1000.)));
+    lines.push(format!("Surface: {}", format_metric(stats.area * mm.powi(2), "mm²", "cm²", 100.)));
+    let [x, y, z] = (stats.max - stats.min).to_array().map(round_to_significant);
+    lines.push(format!("Bounding box: {x} × {y} × {z} {symbol}"));
+    if let Some(center) = stats.center_of_mass {
+        let [x, y, z] = center.to_array().map(round_to_significant);
+        lines.push(format!("Center of mass: ({x}, {y}, {z}) {symbol}"));
+    }
+    // the density is in g/cm³, i.e. mg/mm³
+    let mass_g = volume_mm3 * density / 1000.;
+    lines.push(format!("Mass: {} at {density} g/cm³", format_metric(mass_g, "g", "kg", 1000.)));
+    lines
+}
+
+/// Formats `value`, given in `unit`, switching to `big_unit` when it is `factor` times bigger.
+pub fn format_metric(value: f32, unit: &str, big_unit: &str, factor: f32) -> String {
+    if value >= factor {
+        format!("{} {big_unit}", round_to_significant(value / factor))
+    } else {
+        format!("{} {unit}", round_to_significant(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{math::{Affine3A, Vec3}, prelude::{Cuboid, MeshBuilder, Meshable}, tasks::block_on};
+
+    use crate::{
+        meshes_tree::Units,
+        mesh_stats::{compute_stats, stats_lines, FilamentDensity, MeshPart},
+    };
+
+    #[test]
+    fn test_compute_stats() {
+        let cube = Cuboid::new(10., 20., 30.).mesh().build();
+        let part = MeshPart::new(&cube, Affine3A::from_translation(Vec3::new(5., 10., 15.))).unwrap();
+        let stats = block_on(compute_stats(&[part]));
+        assert_eq!((stats.triangles, stats.vertices), (12, 8));
+        assert!((stats.volume - 6000.).abs() < 0.1);
+        assert!((stats.area - 2200.).abs() < 0.1);
+        assert_eq!((stats.min, stats.max), (Vec3::ZERO, Vec3::new(10., 20., 30.)));
+        assert!(stats.center_of_mass.unwrap().distance(Vec3::new(5., 10., 15.)) < 0.01);
+
+        assert_eq!(stats_lines(&stats, Units::Centimeters, 1.), vec![
+            "Triangles: 12, vertices: 8",
+            "Volume: 6000 cm³",
+            "Surface: 2200 cm²",
+            "Bounding box: 10 × 20 × 30 cm",
+            "Center of mass: (5, 10, 15) cm",
+            "Mass: 6 kg at 1 g/cm³",
+        ]);
+    }
+
+    #[test]
+    fn test_filament_density() {
+        assert_eq!(FilamentDensity::from_fragment("manifest=/tree.json&density=1.04").0, 1.04);
+        assert_eq!(FilamentDensity::from_fragment("/tree.json").0, 1.24);
+        assert_eq!(FilamentDensity::from_fragment("density=-1").0, 1.24);
+    }
+}
@@ -1,43 +1,421 @@
-use std::sync::{Arc, Weak};
+use std::{collections::HashSet, fmt, sync::{Arc, OnceLock, Weak}};
 
-use serde::Deserialize;
+use bevy::{color::{Color, Srgba}, math::Quat};
+use serde::{Deserialize, Deserializer};
+use thiserror::Error;
+
+use crate::formats::MeshFormat;
+
+/// Manifests nested deeper than this are rejected, since they are most likely the result of a
+/// mistake and would otherwise be impossible to navigate anyway.
+pub const MAX_DEPTH: usize = 32;
 
 #[derive(Debug)]
 pub struct MeshTreeNode {
     pub url: String,
+    pub metadata: MeshMetadata,
     pub parent: Weak<MeshTreeNode>,
-    pub children: Vec<Arc<MeshTreeNode>>,
+    children: MeshTreeChildren,
+}
+
+#[derive(Debug)]
+pub enum MeshTreeChildren {
+    /// children listed directly in the manifest containing the node
+    Inline(Vec<Arc<MeshTreeNode>>),
+    /// children listed in another manifest, which is downloaded only when the user opens the
+    /// node, and then kept here so that the download happens just once
+    Lazy {
+        manifest: String,
+        loaded: OnceLock<Vec<Arc<MeshTreeNode>>>,
+    },
 }
 
 #[derive(Debug, Deserialize)]
 pub struct MeshTreeNodeSerde {
-    url: String,
+    // only leaves need an url, since the other nodes are shown through their children
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(flatten)]
+    metadata: MeshMetadata,
     #[serde(default)]
     children: Vec<MeshTreeNodeSerde>,
+    // the url of another manifest containing the children, alternative to `children`
+    #[serde(default)]
+    manifest: Option<String>,
+}
+
+/// Optional information about a node, to be shown to the user alongside the mesh.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct MeshMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub thumbnail: Option<String>,
+    /// the color of the mesh, as a CSS-like hex string (e.g. `#ff8800`)
+    #[serde(deserialize_with = "deserialize_color")]
+    pub color: Option<Color>,
+    pub units: Units,
+    pub up_axis: UpAxis,
+    /// the format of the mesh file, needed only if the url does not end with its extension
+    pub format: Option<MeshFormat>,
+    pub license: Option<String>,
+    pub author: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// The unit of measure of the coordinates in the mesh file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Units {
+    #[default]
+    #[serde(rename = "mm")]
+    Millimeters,
+    #[serde(rename = "cm")]
+    Centimeters,
+    #[serde(rename = "m")]
+    Meters,
+    #[serde(rename = "in")]
+    Inches,
+}
+
+impl Units {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Units::Millimeters => "mm",
+            Units::Centimeters => "cm",
+            Units::Meters => "m",
+            Units::Inches => "in",
+        }
+    }
+
+    pub fn millimeters(&self) -> f32 {
+        match self {
+            Units::Millimeters => 1.,
+            Units::Centimeters => 10.,
+            Units::Meters => 1000.,
+            Units::Inches => 25.4,
+        }
+    }
+}
+
+/// The axis pointing upwards in the mesh file, which is Z for most 3D printing software. When
+/// not given, the model is laid flat on the largest face of its convex hull once loaded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpAxis {
+    X,
+    Y,
+    Z,
+    #[default]
+    Auto,
+}
+
+impl UpAxis {
+    /// The rotation that brings this axis onto Bevy's Y axis, which points upwards. Models laid
+    /// flat automatically start with Z up until then.
+    pub fn rotation(&self) -> Quat {
+        match self {
+            UpAxis::X => Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+            UpAxis::Y => Quat::IDENTITY,
+            UpAxis::Z | UpAxis::Auto => Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2),
+        }
+    }
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Color>, D::Error> {
+    let Some(hex) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    Srgba::hex(&hex)
+        .map(|color| Some(color.into()))
+        .map_err(|_| serde::de::Error::custom(format!("invalid color '{hex}'")))
+}
+
+/// The reasons why a manifest can be rejected. Nodes are identified through a JSON pointer
+/// (e.g. `/children/2/children/0`), where the empty string is the root.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum MeshTreeError {
+    #[error("invalid JSON at line {line}, column {column}: {message}")]
+    Json { line: usize, column: usize, message: String },
+    #[error("the leaf at '{path}' has no `url`")]
+    MissingUrl { path: String },
+    #[error("the leaf at '{path}' has an empty `url`")]
+    EmptyUrl { path: String },
+    #[error("the node at '{path}' is nested deeper than {max_depth} levels")]
+    TooDeep { path: String, max_depth: usize },
+    #[error("the node at '{path}' has both `children` and a `manifest` to load them from")]
+    ManifestWithChildren { path: String },
+}
+
+impl From<serde_json::Error> for MeshTreeError {
+    fn from(error: serde_json::Error) -> Self {
+        // serde_json appends the position to the message, remove it since we store it apart
+        let message = error.to_string();
+        let message = match message.rfind(" at line ") {
+            Some(position) => message[..position].to_string(),
+            None => message,
+        };
+        MeshTreeError::Json { line: error.line(), column: error.column(), message }
+    }
+}
+
+/// Problems in a manifest that do not prevent it from being shown.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MeshTreeWarning {
+    DuplicateChild { path: String, url: String },
+}
+
+impl fmt::Display for MeshTreeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshTreeWarning::DuplicateChild { path, url } =>
+                write!(f, "the node at '{path}' contains '{url}' more than once"),
+        }
+    }
 }
 
 impl MeshTreeNode {
     fn from_serde(mns: MeshTreeNodeSerde, parent: Weak<MeshTreeNode>) -> Arc<MeshTreeNode> {
         Arc::new_cyclic(|current_node| {
             MeshTreeNode {
-                url: mns.url,
-                children: mns.children.into_iter()
-                    .map(|e| Self::from_serde(e, current_node.clone()))
-                    .collect(),
+                url: mns.url.unwrap_or_default(),
+                metadata: mns.metadata,
+                children: match mns.manifest {
+                    Some(manifest) => MeshTreeChildren::Lazy { manifest, loaded: OnceLock::new() },
+                    None => MeshTreeChildren::Inline(mns.children.into_iter()
+                        .map(|e| Self::from_serde(e, current_node.clone()))
+                        .collect()),
+                },
+                parent,
+            }
+        })
+    }
+
+    /// Parses and validates a manifest downloaded from `manifest_url`, resolving the relative
+    /// urls it contains against the directory the manifest was downloaded from. Along with the
+    /// tree, returns the problems found in the manifest that are not serious enough to reject it.
+    pub fn from_json(data: &[u8], manifest_url: &str) -> Result<(Arc<MeshTreeNode>, Vec<MeshTreeWarning>), MeshTreeError> {
+        let mut root: MeshTreeNodeSerde = serde_json::from_slice(data)?;
+        let mut warnings = Vec::new();
+        root.validate("", 0, &mut warnings)?;
+        root.resolve_urls(manifest_url);
+        Ok((Self::from_serde(root, Weak::new()), warnings))
+    }
+
+    /// Copies this subtree, attaching it to a different parent.
+    fn reparented(&self, parent: Weak<MeshTreeNode>) -> Arc<MeshTreeNode> {
+        Arc::new_cyclic(|current_node| {
+            MeshTreeNode {
+                url: self.url.clone(),
+                metadata: self.metadata.clone(),
+                children: match &self.children {
+                    MeshTreeChildren::Lazy { manifest, .. } =>
+                        MeshTreeChildren::Lazy { manifest: manifest.clone(), loaded: OnceLock::new() },
+                    MeshTreeChildren::Inline(children) => MeshTreeChildren::Inline(children.iter()
+                        .map(|child| child.reparented(current_node.clone()))
+                        .collect()),
+                },
                 parent,
             }
         })
     }
 
-    pub fn from_json(data: &str) -> Arc<MeshTreeNode> {
-        let root: MeshTreeNodeSerde = serde_json::from_str(data).unwrap();
-        Self::from_serde(root, Weak::new())
+    /// The children of this node, which are empty for lazy nodes whose manifest has not been
+    /// loaded yet.
+    pub fn children(&self) -> &[Arc<MeshTreeNode>] {
+        match &self.children {
+            MeshTreeChildren::Inline(children) => children,
+            MeshTreeChildren::Lazy { loaded, .. } => loaded.get().map(Vec::as_slice).unwrap_or_default(),
+        }
+    }
+
+    /// Whether this node is a single mesh, rather than a menu to choose from.
+    pub fn is_leaf(&self) -> bool {
+        matches!(&self.children, MeshTreeChildren::Inline(children) if children.is_empty())
+    }
+
+    /// The url of the manifest that still needs to be loaded to know the children of this node.
+    pub fn pending_manifest(&self) -> Option<&str> {
+        match &self.children {
+            MeshTreeChildren::Lazy { manifest, loaded } if loaded.get().is_none() => Some(manifest),
+            _ => None,
+        }
+    }
+
+    /// Fills in the children of a lazy node with the tree loaded from its manifest. If the
+    /// manifest is made of just one mesh, that mesh becomes the only child.
+    pub fn load_children(self: &Arc<Self>, manifest_root: &MeshTreeNode) {
+        let MeshTreeChildren::Lazy { loaded, .. } = &self.children else { return; };
+        let children = if manifest_root.is_leaf() {
+            vec![manifest_root.reparented(Arc::downgrade(self))]
+        } else {
+            manifest_root.children().iter()
+                .map(|child| child.reparented(Arc::downgrade(self)))
+                .collect()
+        };
+        // if the children were loaded in the meantime, keep the previous ones
+        let _ = loaded.set(children);
+    }
+
+    /// The indices of the children to follow from the root to reach this node, which identify
+    /// the node in the url of the page.
+    pub fn path(&self) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut child: *const MeshTreeNode = self;
+        let mut parent = self.parent.upgrade();
+        while let Some(node) = parent {
+            let Some(index) = node.children().iter().position(|sibling| std::ptr::eq(Arc::as_ptr(sibling), child)) else {
+                break;
+            };
+            path.push(index);
+            child = Arc::as_ptr(&node);
+            parent = node.parent.upgrade();
+        }
+        path.reverse();
+        path
+    }
+
+    /// The format of the mesh file, from the manifest or from the extension of the url.
+    pub fn format(&self) -> Option<MeshFormat> {
+        self.metadata.format.or_else(|| MeshFormat::from_url(&self.url))
+    }
+
+    /// The units of the mesh file, which are always meters for glTF files.
+    pub fn units(&self) -> Units {
+        match self.format() {
+            Some(MeshFormat::Gltf) => Units::Meters,
+            _ => self.metadata.units,
+        }
+    }
+
+    /// The axis pointing upwards in the mesh file, which is always Y for glTF files.
+    pub fn up_axis(&self) -> UpAxis {
+        match self.format() {
+            Some(MeshFormat::Gltf) => UpAxis::Y,
+            _ => self.metadata.up_axis,
+        }
+    }
+
+    /// The node whose mesh represents this node, which for nodes without an url of their own
+    /// is the one of their first child. Lazy nodes whose manifest was not loaded yet might have
+    /// no mesh representing them.
+    pub fn preview(&self) -> Option<&MeshTreeNode> {
+        if self.url.is_empty() {
+            self.children().first().and_then(|child| child.preview())
+        } else {
+            Some(self)
+        }
+    }
+
+    /// The url of the mesh representing this node, see [`MeshTreeNode::preview`].
+    pub fn preview_url(&self) -> Option<&str> {
+        self.preview().map(|node| node.url.as_str())
+    }
+
+    /// The name to show to the user for this node, i.e. its title or the name of its file.
+    pub fn display_name(&self) -> &str {
+        if let Some(title) = &self.metadata.title {
+            return title;
+        }
+        let url = match &self.children {
+            MeshTreeChildren::Lazy { manifest, .. } if self.url.is_empty() => manifest,
+            _ => self.preview_url().unwrap_or_default(),
+        };
+        let path = url.split(['?', '#']).next().unwrap_or(url);
+        path.rsplit('/').find(|segment| !segment.is_empty()).unwrap_or(path)
+    }
+
+    /// Builds a tree made of just one mesh, used when the viewer is opened on a single file.
+    pub fn leaf(url: String) -> Arc<MeshTreeNode> {
+        Arc::new(MeshTreeNode {
+            url,
+            metadata: MeshMetadata::default(),
+            parent: Weak::new(),
+            children: MeshTreeChildren::Inline(Vec::new()),
+        })
+    }
+
+    /// Builds a node listing nodes of the tree that are not its own children, e.g. the results of
+    /// a search, which keep their real parent so that opening one of them moves into the tree.
+    /// `parent` is where the back button leads from the listing.
+    pub fn listing(title: String, parent: Weak<MeshTreeNode>, nodes: Vec<Arc<MeshTreeNode>>) -> Arc<MeshTreeNode> {
+        Arc::new(MeshTreeNode {
+            url: String::new(),
+            metadata: MeshMetadata { title: Some(title), ..Default::default() },
+            parent,
+            children: MeshTreeChildren::Inline(nodes),
+        })
+    }
+}
+
+impl MeshTreeNodeSerde {
+    fn validate(&self, path: &str, depth: usize, warnings: &mut Vec<MeshTreeWarning>) -> Result<(), MeshTreeError> {
+        if depth > MAX_DEPTH {
+            return Err(MeshTreeError::TooDeep { path: path.to_string(), max_depth: MAX_DEPTH });
+        }
+
+        if self.manifest.is_some() && !self.children.is_empty() {
+            return Err(MeshTreeError::ManifestWithChildren { path: path.to_string() });
+        }
+
+        if self.manifest.is_none() && self.children.is_empty() {
+            match &self.url {
+                None => return Err(MeshTreeError::MissingUrl { path: path.to_string() }),
+                Some(url) if url.trim().is_empty() => return Err(MeshTreeError::EmptyUrl { path: path.to_string() }),
+                Some(_) => {},
+            }
+        }
+
+        let mut seen_urls = HashSet::new();
+        for (index, child) in self.children.iter().enumerate() {
+            let child_path = format!("{path}/children/{index}");
+            child.validate(&child_path, depth + 1, warnings)?;
+
+            // only leaves are compared, since other nodes are identified by their children
+            if let (true, None, Some(url)) = (child.children.is_empty(), &child.manifest, &child.url) {
+                if !seen_urls.insert(url) {
+                    warnings.push(MeshTreeWarning::DuplicateChild { path: path.to_string(), url: url.clone() });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_urls(&mut self, base_url: &str) {
+        if let Some(url) = &mut self.url {
+            *url = resolve_url(base_url, url);
+        }
+        if let Some(thumbnail) = &mut self.metadata.thumbnail {
+            *thumbnail = resolve_url(base_url, thumbnail);
+        }
+        if let Some(manifest) = &mut self.manifest {
+            *manifest = resolve_url(base_url, manifest);
+        }
+        self.children.iter_mut().for_each(|child| child.resolve_urls(base_url));
+    }
+}
+
+/// Urls with a scheme (e.g. `https://`) or starting with `/` are kept as they are, while
+/// the other ones are considered relative to the directory containing `base_url`.
+fn resolve_url(base_url: &str, url: &str) -> String {
+    if url.contains("://") || url.starts_with('/') {
+        return url.to_string();
+    }
+    match base_url.rfind('/') {
+        Some(slash) => format!("{}{url}", &base_url[..=slash]),
+        None => url.to_string(),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::meshes_tree::MeshTreeNodeSerde;
+    use std::sync::Arc;
+
+    use bevy::color::Color;
+
+    use crate::formats::MeshFormat;
+    use crate::meshes_tree::{
+        resolve_url, MeshTreeError, MeshTreeNode, MeshTreeNodeSerde, MeshTreeWarning, Units, UpAxis, MAX_DEPTH,
+    };
 
     const TEST: &str = r#"{
         "url": "http://localhost:8080/mendocino.stl",
@@ -52,4 +430,129 @@ mod tests {
     fn test_from_json() {
         println!("{:?}", serde_json::from_str::<MeshTreeNodeSerde>(TEST).unwrap());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_resolve_url() {
+        assert_eq!(resolve_url("https://host/dir/tree.json", "parts/a.stl"), "https://host/dir/parts/a.stl");
+        assert_eq!(resolve_url("https://host/dir/tree.json", "/benchy.stl"), "/benchy.stl");
+        assert_eq!(resolve_url("/tree.json", "benchy.stl"), "/benchy.stl");
+        assert_eq!(resolve_url("/tree.json", "http://other/b.stl"), "http://other/b.stl");
+    }
+
+    #[test]
+    fn test_from_json_resolves_urls() {
+        let (root, warnings) = MeshTreeNode::from_json(br#"{
+            "url": "mendocino.stl",
+            "children": [{ "url": "parts/benchy.stl" }]
+        }"#, "http://localhost:8080/catalog/tree.json").unwrap();
+        assert_eq!(root.url, "http://localhost:8080/catalog/mendocino.stl");
+        assert_eq!(root.children()[0].url, "http://localhost:8080/catalog/parts/benchy.stl");
+        assert!(Arc::ptr_eq(&root.children()[0].parent.upgrade().unwrap(), &root));
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_metadata() {
+        let (root, _) = MeshTreeNode::from_json(br##"{
+            "url": "parts/benchy.stl?download=1",
+            "thumbnail": "thumbs/benchy.png",
+            "color": "#ff8800",
+            "units": "in",
+            "up_axis": "y",
+            "format": "3mf",
+            "tags": ["boat", "calibration"]
+        }"##, "https://host/tree.json").unwrap();
+        assert_eq!(root.display_name(), "benchy.stl");
+        assert_eq!(root.metadata.thumbnail.as_deref(), Some("https://host/thumbs/benchy.png"));
+        assert_eq!(root.metadata.color, Some(Color::srgb_u8(0xff, 0x88, 0x00)));
+        assert_eq!(root.metadata.units, Units::Inches);
+        assert_eq!(root.metadata.up_axis, UpAxis::Y);
+        assert_eq!(root.metadata.format, Some(MeshFormat::ThreeMf));
+        assert_eq!(root.metadata.tags, vec!["boat", "calibration"]);
+
+        let (root, _) = MeshTreeNode::from_json(br#"{ "url": "/a.stl", "title": "Benchy" }"#, "/tree.json").unwrap();
+        assert_eq!(root.display_name(), "Benchy");
+        assert_eq!(root.metadata.units, Units::Millimeters);
+        assert_eq!(root.up_axis(), UpAxis::Auto);
+
+        // glTF files have fixed units and orientation, whatever the manifest says
+        let (root, _) = MeshTreeNode::from_json(br#"{ "url": "/a.glb", "units": "mm", "up_axis": "z" }"#, "/tree.json").unwrap();
+        assert_eq!(root.units(), Units::Meters);
+        assert_eq!(root.up_axis(), UpAxis::Y);
+
+        assert!(matches!(
+            MeshTreeNode::from_json(br#"{ "url": "/a.stl", "color": "orange" }"#, "/tree.json").unwrap_err(),
+            MeshTreeError::Json { line: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn test_preview_url() {
+        let (root, _) = MeshTreeNode::from_json(br#"{
+            "children": [{ "children": [{ "url": "/a.stl" }] }, { "url": "/b.stl", "children": [{ "url": "/c.stl" }] }]
+        }"#, "/tree.json").unwrap();
+        assert_eq!(root.preview_url(), Some("/a.stl"));
+        assert_eq!(root.children()[1].preview_url(), Some("/b.stl"));
+    }
+
+    #[test]
+    fn test_lazy_children() {
+        let (root, _) = MeshTreeNode::from_json(br#"{
+            "children": [{ "url": "/a.stl" }, { "manifest": "parts/tree.json" }]
+        }"#, "/catalog/tree.json").unwrap();
+        let lazy = &root.children()[1];
+        assert!(!lazy.is_leaf());
+        assert!(lazy.children().is_empty());
+        assert_eq!(lazy.preview_url(), None);
+        assert_eq!(lazy.display_name(), "tree.json");
+        assert_eq!(lazy.pending_manifest(), Some("/catalog/parts/tree.json"));
+
+        let (sub_root, _) = MeshTreeNode::from_json(br#"{
+            "children": [{ "url": "b.stl" }, { "url": "c.stl" }]
+        }"#, "/catalog/parts/tree.json").unwrap();
+        lazy.load_children(&sub_root);
+        assert_eq!(lazy.pending_manifest(), None);
+        assert_eq!(lazy.children().len(), 2);
+        assert_eq!(lazy.preview_url(), Some("/catalog/parts/b.stl"));
+        assert!(Arc::ptr_eq(&lazy.children()[0].parent.upgrade().unwrap(), lazy));
+        assert_eq!(lazy.children()[1].path(), vec![1, 1]);
+        assert_eq!(root.path(), Vec::<usize>::new());
+
+        assert_eq!(
+            MeshTreeNode::from_json(br#"{ "manifest": "a.json", "children": [{ "url": "/a.stl" }] }"#, "/tree.json").unwrap_err(),
+            MeshTreeError::ManifestWithChildren { path: "".to_string() }
+        );
+    }
+
+    #[test]
+    fn test_from_json_errors() {
+        assert_eq!(
+            MeshTreeNode::from_json(b"{\n  \"children\": [,]\n}", "/tree.json").unwrap_err(),
+            MeshTreeError::Json { line: 2, column: 16, message: "expected value".to_string() }
+        );
+        assert_eq!(
+            MeshTreeNode::from_json(br#"{ "children": [{ "url": "/a.stl" }, {}] }"#, "/tree.json").unwrap_err(),
+            MeshTreeError::MissingUrl { path: "/children/1".to_string() }
+        );
+        assert_eq!(
+            MeshTreeNode::from_json(br#"{ "url": " " }"#, "/tree.json").unwrap_err(),
+            MeshTreeError::EmptyUrl { path: "".to_string() }
+        );
+
+        let too_deep = format!("{}{{ \"url\": \"/a.stl\" }}{}",
+            "{ \"children\": [".repeat(MAX_DEPTH + 1), "] }".repeat(MAX_DEPTH + 1));
+        assert!(matches!(
+            MeshTreeNode::from_json(too_deep.as_bytes(), "/tree.json").unwrap_err(),
+            MeshTreeError::TooDeep { max_depth: MAX_DEPTH, .. }
+        ));
+    }
+
+    #[test]
+    fn test_from_json_warnings() {
+        let (_, warnings) = MeshTreeNode::from_json(
+            br#"{ "children": [{ "url": "/a.stl" }, { "url": "/b.stl" }, { "url": "/a.stl" }] }"#,
+            "/tree.json",
+        ).unwrap();
+        assert_eq!(warnings, vec![MeshTreeWarning::DuplicateChild { path: "".to_string(), url: "/a.stl".to_string() }]);
+    }
+}
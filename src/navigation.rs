@@ -0,0 +1,185 @@
+//! Moves through the tree with the back button and the keyboard: Escape or Backspace go up to
+//! the parent node, the arrow keys highlight a child in the grid and Enter opens it.
+
+use std::sync::Arc;
+
+use bevy::prelude::*;
+
+use crate::{
+    search::not_typing, set_scene_material, thumbnails::ThumbnailItem, GridItem, MeshTreeRes, OneShotSystemsRes,
+    SceneMaterialQuery, ShownNode,
+};
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GridHighlight>()
+            .add_systems(Update, (
+                navigate_with_keyboard.run_if(not_typing),
+                show_grid_highlight.run_if(resource_changed::<GridHighlight>),
+            ).chain().run_if(resource_exists::<MeshTreeRes>));
+    }
+}
+
+/// The child highlighted with the keyboard in the grid of the current node, if any.
+#[derive(Resource, Default)]
+pub struct GridHighlight {
+    /// the position in the grid of the highlighted child
+    pub index: Option<usize>,
+    /// the index of the child in the first cell, when showing a page of the children
+    first: usize,
+    count: usize,
+    columns: usize,
+}
+
+impl GridHighlight {
+    /// No highlight yet, in a grid of `count` children starting from the child at `first`, laid
+    /// out in `columns` columns.
+    pub fn new(first: usize, count: usize, columns: usize) -> GridHighlight {
+        GridHighlight { index: None, first, count, columns }
+    }
+
+    /// The index among the children of the current node of the highlighted child.
+    pub fn child_index(&self) -> Option<usize> {
+        self.index.map(|index| self.first + index)
+    }
+
+    /// The index highlighted after pressing the arrow `key`, which starts from the first child
+    /// and stops at the borders of the grid.
+    fn moved(&self, key: KeyCode) -> Option<usize> {
+        if self.count == 0 {
+            return None;
+        }
+        let Some(index) = self.index else { return Some(0); };
+        Some(match key {
+            KeyCode::ArrowLeft => index.saturating_sub(1),
+            KeyCode::ArrowRight => (index + 1).min(self.count - 1),
+            KeyCode::ArrowUp => index.checked_sub(self.columns).unwrap_or(index),
+            KeyCode::ArrowDown if index + self.columns < self.count => index + self.columns,
+            _ => index,
+        })
+    }
+}
+
+/// Shows the child at `child_index` of the current node.
+pub fn open_child(commands: &mut Commands, mesh_tree: &mut MeshTreeRes, one_shot_systems: &OneShotSystemsRes, child_index: usize) {
+    let Some(current) = mesh_tree.current.upgrade() else { return; };
+    let Some(child) = current.children().get(child_index) else { return; };
+    mesh_tree.current = Arc::downgrade(child);
+    commands.run_system(one_shot_systems.update_current_sys);
+}
+
+/// Shows the parent of the current node, unless it is the root.
+pub fn open_parent(commands: &mut Commands, mesh_tree: &mut MeshTreeRes, one_shot_systems: &OneShotSystemsRes) {
+    let Some(current) = mesh_tree.current.upgrade() else { return; };
+    let Some(parent) = current.parent.upgrade() else { return; };
+    mesh_tree.current = Arc::downgrade(&parent);
+    commands.run_system(one_shot_systems.update_current_sys);
+}
+
+/// An observer that shows the parent of the current node, used by the back button.
+pub fn open_parent_on<E: EntityEvent>(
+    _: On<E>,
+    mut commands: Commands,
+    mut mesh_tree: ResMut<MeshTreeRes>,
+    one_shot_systems: Res<OneShotSystemsRes>,
+) {
+    open_parent(&mut commands, &mut mesh_tree, &one_shot_systems);
+}
+
+fn navigate_with_keyboard(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut highlight: ResMut<GridHighlight>,
+    mut mesh_tree: ResMut<MeshTreeRes>,
+    one_shot_systems: Res<OneShotSystemsRes>,
+) {
+    if keys.any_just_pressed([KeyCode::Escape, KeyCode::Backspace]) {
+        open_parent(&mut commands, &mut mesh_tree, &one_shot_systems);
+        return;
+    }
+    if keys.any_just_pressed([KeyCode::Enter, KeyCode::NumpadEnter]) {
+        if let Some(index) = highlight.child_index() {
+            open_child(&mut commands, &mut mesh_tree, &one_shot_systems, index);
+        }
+        return;
+    }
+    for key in [KeyCode::ArrowLeft, KeyCode::ArrowRight, KeyCode::ArrowUp, KeyCode::ArrowDown] {
+        if keys.just_pressed(key) {
+            let index = highlight.moved(key);
+            if index != highlight.index {
+                highlight.index = index;
+            }
+        }
+    }
+}
+
+/// Gives the highlighted child the same material as when the mouse is over it, and the other
+/// children their own material back.
+#[allow(clippy::too_many_arguments)]
+fn show_grid_highlight(
+    mut commands: Commands,
+    highlight: Res<GridHighlight>,
+    mut mesh_tree: ResMut<MeshTreeRes>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    items: Query<(Entity, &GridItem, &ShownNode, &Children)>,
+    mut thumbnail_items: Query<&mut ThumbnailItem>,
+    scenes: Query<(), With<SceneRoot>>,
+    children: Query<&Children>,
+    mut mesh_materials: SceneMaterialQuery,
+) {
+    for (entity, item, shown_node, item_children) in &items {
+        let Some(node) = shown_node.0.upgrade() else { continue; };
+        let highlighted = highlight.child_index() == Some(item.child_index);
+        if let Ok(mut thumbnail_item) = thumbnail_items.get_mut(entity) {
+            // thumbnails show their model instead
+            thumbnail_item.highlighted = highlighted;
+            continue;
+        }
+        for model in item_children.iter() {
+            if scenes.contains(model) {
+                let hover_matl = mesh_tree.hover_matl.clone();
+                set_scene_material(&mut commands, model, highlighted.then_some(&hover_matl), &children, &mut mesh_materials);
+            } else if let Ok((mut material, _)) = mesh_materials.get_mut(model) {
+                // meshes that failed to load keep showing the error
+                if material.0 != mesh_tree.error_matl {
+                    material.0 = if highlighted {
+                        mesh_tree.hover_matl.clone()
+                    } else {
+                        mesh_tree.material_for(&node, &mut materials)
+                    };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::input::keyboard::KeyCode;
+
+    use crate::navigation::GridHighlight;
+
+    #[test]
+    fn test_moved() {
+        // 3 columns, with the last row made of just 1 child
+        let mut highlight = GridHighlight::new(0, 7, 3);
+        assert_eq!(highlight.moved(KeyCode::ArrowDown), Some(0));
+        highlight.index = Some(4);
+        assert_eq!(highlight.moved(KeyCode::ArrowLeft), Some(3));
+        assert_eq!(highlight.moved(KeyCode::ArrowRight), Some(5));
+        assert_eq!(highlight.moved(KeyCode::ArrowUp), Some(1));
+        assert_eq!(highlight.moved(KeyCode::ArrowDown), Some(4));
+        highlight.index = Some(3);
+        assert_eq!(highlight.moved(KeyCode::ArrowDown), Some(6));
+        highlight.index = Some(0);
+        assert_eq!(highlight.moved(KeyCode::ArrowLeft), Some(0));
+        assert_eq!(GridHighlight::default().moved(KeyCode::ArrowRight), None);
+
+        // on the second page of 10 children
+        let mut highlight = GridHighlight::new(10, 4, 2);
+        highlight.index = highlight.moved(KeyCode::ArrowDown);
+        assert_eq!(highlight.child_index(), Some(10));
+    }
+}
@@ -0,0 +1,259 @@
+//! Splits the children of nodes with many of them into pages, so that the meshes in the grid
+//! stay big enough to be seen and only a few of them are downloaded at once. The meshes of the
+//! pages around the one shown (or just their thumbnails) are loaded in advance, so that turning
+//! the page is quick.
+//!
+//! The page size and the number of pages loaded in advance can be set in the url fragment, e.g.
+//! `#manifest=/tree.json&page_size=24&prefetch=2`.
+
+use std::{ops::Range, sync::{Arc, Weak}};
+
+use bevy::{color::palettes::tailwind::{CYAN_300, GRAY_400}, prelude::*};
+
+use crate::{
+    formats::{load_model, Model}, loading::VisualizationComponents, manifest::fragment_parameters,
+    meshes_tree::MeshTreeNode, search::not_typing, MeshTreeRes, OneShotSystemsRes,
+};
+
+pub struct PaginationPlugin;
+
+impl Plugin for PaginationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (
+            turn_page_with_keyboard.run_if(resource_exists::<MeshTreeRes>.and(resource_exists::<Pagination>).and(not_typing)),
+            show_page_controls.run_if(resource_exists_and_changed::<Pagination>),
+        ).chain());
+    }
+}
+
+const DEFAULT_PAGE_SIZE: usize = 36;
+const DEFAULT_PREFETCH_PAGES: usize = 1;
+
+/// The page of children shown in the grid of the current node.
+#[derive(Resource)]
+pub struct Pagination {
+    /// how many children are shown at once
+    page_size: usize,
+    /// how many pages before and after the shown one get their meshes loaded in advance
+    prefetch_pages: usize,
+    /// the node whose children are being paged through
+    node: Weak<MeshTreeNode>,
+    page: usize,
+    page_count: usize,
+    /// keeps the models of the pages around the shown one loaded
+    prefetched: Vec<UntypedHandle>,
+}
+
+impl Pagination {
+    pub fn new(page_size: usize, prefetch_pages: usize) -> Pagination {
+        Pagination {
+            page_size: page_size.max(1),
+            prefetch_pages,
+            node: Weak::new(),
+            page: 0,
+            page_count: 0,
+            prefetched: Vec::new(),
+        }
+    }
+
+    /// Reads the `page_size` and `prefetch` parameters of the url fragment, if any.
+    pub fn from_fragment(fragment: &str) -> Pagination {
+        let parameters = fragment_parameters(fragment).unwrap_or_default();
+        let parameter = |name: &str| parameters.iter()
+            .find(|(key, _)| *key == name)
+            .and_then(|(_, value)| value.parse().ok());
+        Pagination::new(
+            parameter("page_size").unwrap_or(DEFAULT_PAGE_SIZE),
+            parameter("prefetch").unwrap_or(DEFAULT_PREFETCH_PAGES),
+        )
+    }
+
+    /// The indices of the children of `node` to show, starting from the first page whenever
+    /// `node` is not the one shown last, and staying on the same page otherwise.
+    pub fn show(&mut self, node: &Arc<MeshTreeNode>) -> Range<usize> {
+        if !self.node.ptr_eq(&Arc::downgrade(node)) {
+            self.node = Arc::downgrade(node);
+            self.page = 0;
+        }
+        let child_count = node.children().len();
+        self.page_count = child_count.div_ceil(self.page_size).max(1);
+        self.page = self.page.min(self.page_count - 1);
+        self.page_range(self.page, child_count)
+    }
+
+    /// Forgets the node being paged through, when showing a single mesh.
+    pub fn clear(&mut self) {
+        self.node = Weak::new();
+        self.page_count = 0;
+        self.prefetched.clear();
+    }
+
+    fn page_range(&self, page: usize, child_count: usize) -> Range<usize> {
+        let start = (page * self.page_size).min(child_count);
+        start..(start + self.page_size).min(child_count)
+    }
+
+    /// Begins loading the models of the children in the pages around the shown one, and lets
+    /// go of the ones that are now farther away.
+    pub fn prefetch(&mut self, asset_server: &AssetServer, node: &MeshTreeNode) {
+        let child_count = node.children().len();
+        let first_page = self.page.saturating_sub(self.prefetch_pages);
+        let last_page = self.page.saturating_add(self.prefetch_pages).min(self.page_count.saturating_sub(1));
+        self.prefetched = (first_page..=last_page)
+            .filter(|&page| page != self.page)
+            .flat_map(|page| self.page_range(page, child_count))
+            .filter_map(|index| {
+                // children with a thumbnail only need their image until they are hovered
+                let child = &node.children()[index];
+                if let Some(thumbnail) = &child.metadata.thumbnail {
+                    return Some(asset_server.load::<Image>(thumbnail.clone()).untyped());
+                }
+                let preview = child.preview()?;
+                Some(match load_model(asset_server, &preview.url, preview.format()) {
+                    Model::Mesh(mesh) => mesh.untyped(),
+                    Model::Scene(scene) => scene.untyped(),
+                })
+            })
+            .collect();
+    }
+
+    /// Moves to another page, returning whether it exists.
+    fn turn_to(&mut self, page: usize) -> bool {
+        if page >= self.page_count || page == self.page {
+            return false;
+        }
+        self.page = page;
+        true
+    }
+}
+
+/// Shows another page of the current node, if it exists.
+fn turn_page(
+    commands: &mut Commands,
+    pagination: &mut Pagination,
+    one_shot_systems: &OneShotSystemsRes,
+    page: impl FnOnce(&Pagination) -> Option<usize>,
+) {
+    if let Some(page) = page(pagination) {
+        if pagination.turn_to(page) {
+            commands.run_system(one_shot_systems.update_current_sys);
+        }
+    }
+}
+
+fn turn_page_with_keyboard(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut pagination: ResMut<Pagination>,
+    one_shot_systems: Res<OneShotSystemsRes>,
+) {
+    let page: fn(&Pagination) -> Option<usize> = if keys.just_pressed(KeyCode::PageDown) {
+        |pagination| Some(pagination.page + 1)
+    } else if keys.just_pressed(KeyCode::PageUp) {
+        |pagination| pagination.page.checked_sub(1)
+    } else if keys.just_pressed(KeyCode::Home) {
+        |_| Some(0)
+    } else if keys.just_pressed(KeyCode::End) {
+        |pagination| pagination.page_count.checked_sub(1)
+    } else {
+        return;
+    };
+    turn_page(&mut commands, &mut pagination, &one_shot_systems, page);
+}
+
+// Marker component for the bar with the page controls.
+#[derive(Component)]
+pub struct PageControls;
+
+fn show_page_controls(
+    mut commands: Commands,
+    pagination: Res<Pagination>,
+    controls: Query<Entity, With<PageControls>>,
+) {
+    controls.iter().for_each(|entity| commands.entity(entity).despawn());
+    if pagination.page_count < 2 {
+        return;
+    }
+
+    let arrow = |text: &str, enabled: bool| (
+        Text::new(text),
+        TextFont { font_size: 20., ..default() },
+        TextColor(if enabled { Color::from(CYAN_300) } else { Color::from(GRAY_400) }),
+    );
+    commands.spawn((
+        PageControls,
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(12.),
+            width: Val::Percent(100.),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        Pickable::IGNORE,
+        VisualizationComponents,
+        Visibility::Hidden,
+    )).with_children(|bar| {
+        bar.spawn((
+            Node {
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(12.),
+                padding: UiRect::axes(Val::Px(12.), Val::Px(4.)),
+                ..default()
+            },
+            BackgroundColor(Color::BLACK.with_alpha(0.6)),
+        )).with_children(|controls| {
+            controls.spawn(arrow("‹", pagination.page > 0)).observe(turn_page_on_click(-1));
+            controls.spawn((
+                Text::new(format!("Page {} of {}", pagination.page + 1, pagination.page_count)),
+                TextFont { font_size: 14., ..default() },
+                TextColor(Color::WHITE),
+            ));
+            controls.spawn(arrow("›", pagination.page + 1 < pagination.page_count)).observe(turn_page_on_click(1));
+        });
+    });
+}
+
+/// Returns an observer that moves `delta` pages forward or back.
+fn turn_page_on_click(delta: isize) -> impl Fn(On<Pointer<Click>>, Commands, ResMut<Pagination>, Res<OneShotSystemsRes>) {
+    move |_, mut commands, mut pagination, one_shot_systems| {
+        turn_page(&mut commands, &mut pagination, &one_shot_systems, |pagination| pagination.page.checked_add_signed(delta));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{meshes_tree::MeshTreeNode, pagination::Pagination};
+
+    fn node_with_children(count: usize) -> Arc<MeshTreeNode> {
+        let children: Vec<String> = (0..count).map(|i| format!(r#"{{ "url": "/{i}.stl" }}"#)).collect();
+        let json = format!(r#"{{ "children": [{}] }}"#, children.join(","));
+        MeshTreeNode::from_json(json.as_bytes(), "/tree.json").unwrap().0
+    }
+
+    #[test]
+    fn test_show() {
+        let mut pagination = Pagination::new(4, 1);
+        let node = node_with_children(10);
+        assert_eq!(pagination.show(&node), 0..4);
+        assert_eq!(pagination.page_count, 3);
+        assert!(pagination.turn_to(2));
+        assert!(!pagination.turn_to(3));
+        assert_eq!(pagination.show(&node), 8..10);
+
+        // another node starts from its first page
+        let other = node_with_children(2);
+        assert_eq!(pagination.show(&other), 0..2);
+        assert_eq!(pagination.page_count, 1);
+    }
+
+    #[test]
+    fn test_from_fragment() {
+        let pagination = Pagination::from_fragment("manifest=/t.json&page_size=12&prefetch=0");
+        assert_eq!((pagination.page_size, pagination.prefetch_pages), (12, 0));
+        let pagination = Pagination::from_fragment("/t.json");
+        assert_eq!((pagination.page_size, pagination.prefetch_pages), (36, 1));
+        assert_eq!(Pagination::from_fragment("page_size=0").page_size, 1);
+    }
+}
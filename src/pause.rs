@@ -0,0 +1,56 @@
+//! Lets long computations share the main thread. On the web, the tasks of the
+//! [`AsyncComputeTaskPool`](bevy::tasks::AsyncComputeTaskPool) run on it rather than on other
+//! threads, and a task woken right away runs again before the browser draws anything, so the
+//! computations await [`pause`] regularly to go on at the next frame instead.
+
+use std::{sync::Mutex, task::Waker};
+
+use bevy::prelude::*;
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(First, wake_paused_tasks);
+    }
+}
+
+/// How many triangles the computations go through between two pauses, which takes a few
+/// milliseconds.
+pub const TRIANGLES_PER_PAUSE: usize = 20_000;
+
+/// The tasks waiting for the next frame.
+static PAUSED: Mutex<Vec<Waker>> = Mutex::new(Vec::new());
+
+/// Waits for the next frame on the web, and just lets other tasks run elsewhere.
+pub async fn pause() {
+    #[cfg(target_arch = "wasm32")]
+    NextFrame { paused: false }.await;
+    #[cfg(not(target_arch = "wasm32"))]
+    bevy::tasks::futures_lite::future::yield_now().await;
+}
+
+#[cfg(target_arch = "wasm32")]
+struct NextFrame {
+    paused: bool,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl std::future::Future for NextFrame {
+    type Output = ();
+
+    fn poll(mut self: std::pin::Pin<&mut Self>, context: &mut std::task::Context) -> std::task::Poll<()> {
+        if self.paused {
+            return std::task::Poll::Ready(());
+        }
+        self.paused = true;
+        PAUSED.lock().unwrap().push(context.waker().clone());
+        std::task::Poll::Pending
+    }
+}
+
+/// The tasks woken here run once the frame is over.
+fn wake_paused_tasks() {
+    let paused = std::mem::take(&mut *PAUSED.lock().unwrap());
+    paused.into_iter().for_each(Waker::wake);
+}
@@ -0,0 +1,497 @@
+//! Colors the model rendered on its own by how well it would print: the faces looking down are
+//! shaded by their overhang angle, and those past the angle slicers need supports for are red,
+//! the walls thinner than the nozzle are purple, and the faces resting on the bed are blue, with
+//! the area of each shown in a legend. Pressing P turns the overlay on and off, and B goes
+//! through the build directions, i.e. the axis of the model pointing up on the printer.
+//!
+//! The analysis runs in a task pausing now and then, casting a ray inwards from each triangle to
+//! measure the thickness of the walls. The url fragment can change the build direction, the overhang
+//! angle in degrees and the nozzle width in mm, e.g. `#node=2&build=-y&overhang=50&nozzle=0.6`.
+
+use bevy::{
+    color::palettes::tailwind::{AMBER_400, GRAY_200, GRAY_400, PURPLE_500, RED_500, SKY_500},
+    math::Affine3A,
+    mesh::{Indices, PrimitiveTopology},
+    prelude::*,
+    tasks::{block_on, poll_once, AsyncComputeTaskPool, Task},
+};
+
+use crate::{
+    bvh::Bvh, dimensions::{CurrentLeaf, ModelDimensions, TrackCurrentLeaf}, manifest::fragment_parameters, mesh_stats::format_metric,
+    pause::{pause, TRIANGLES_PER_PAUSE}, render_modes::Overlay, search::not_typing,
+};
+
+pub struct PrintabilityPlugin;
+
+impl Plugin for PrintabilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (
+            switch_printability.run_if(not_typing),
+            follow_leaf.after(TrackCurrentLeaf).run_if(resource_changed::<CurrentLeaf>),
+            start_analysis,
+            finish_analysis,
+            show_legend.run_if(resource_changed::<Printability>),
+        ).chain().run_if(resource_exists::<Printability>));
+    }
+}
+
+/// The angle from the vertical past which slicers usually add supports.
+const DEFAULT_OVERHANG_ANGLE: f32 = 45.;
+
+/// The most common nozzle width.
+const DEFAULT_NOZZLE_MM: f32 = 0.4;
+
+/// The axis of the model pointing up on the printer, named after the axes of the world as in
+/// slicers, Z being the up axis of the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BuildDirection {
+    #[default]
+    Z,
+    NegZ,
+    X,
+    NegX,
+    Y,
+    NegY,
+}
+
+impl BuildDirection {
+    const ALL: [BuildDirection; 6] = [
+        BuildDirection::Z, BuildDirection::NegZ, BuildDirection::X, BuildDirection::NegX, BuildDirection::Y, BuildDirection::NegY,
+    ];
+
+    fn next(self) -> BuildDirection {
+        let index = BuildDirection::ALL.iter().position(|direction| *direction == self).unwrap_or(0);
+        BuildDirection::ALL[(index + 1) % BuildDirection::ALL.len()]
+    }
+
+    /// The name used in the url fragment.
+    fn name(self) -> &'static str {
+        match self {
+            BuildDirection::Z => "z",
+            BuildDirection::NegZ => "-z",
+            BuildDirection::X => "x",
+            BuildDirection::NegX => "-x",
+            BuildDirection::Y => "y",
+            BuildDirection::NegY => "-y",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<BuildDirection> {
+        BuildDirection::ALL.into_iter().find(|direction| direction.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The direction in the world, where Y is up and Z points to the viewer, as for the clipping
+    /// plane.
+    fn up(self) -> Vec3 {
+        match self {
+            BuildDirection::Z => Vec3::Y,
+            BuildDirection::NegZ => Vec3::NEG_Y,
+            BuildDirection::X => Vec3::X,
+            BuildDirection::NegX => Vec3::NEG_X,
+            BuildDirection::Y => Vec3::NEG_Z,
+            BuildDirection::NegY => Vec3::Z,
+        }
+    }
+}
+
+/// Whether the printability overlay is shown on the leaf, and how it is analyzed.
+#[derive(Resource)]
+pub struct Printability {
+    enabled: bool,
+    build: BuildDirection,
+    /// in degrees from the vertical
+    overhang_angle: f32,
+    nozzle_mm: f32,
+    /// the leaf entity shown
+    target: Option<Entity>,
+    /// the leaf entity and build direction the overlay is made or being made for
+    analyzed: Option<(Entity, BuildDirection)>,
+    /// the areas in mm², once analyzed
+    summary: Option<Summary>,
+    overlay_matl: Option<Handle<StandardMaterial>>,
+}
+
+impl Printability {
+    /// Reads the `build`, `overhang` and `nozzle` parameters of the url fragment, if any.
+    pub fn from_fragment(fragment: &str) -> Printability {
+        let parameters = fragment_parameters(fragment).unwrap_or_default();
+        let parameter = |name: &str| parameters.iter().find(|(key, _)| *key == name).map(|(_, value)| value.as_ref());
+        Printability {
+            enabled: false,
+            build: parameter("build").and_then(BuildDirection::from_name).unwrap_or_default(),
+            overhang_angle: parameter("overhang")
+                .and_then(|value| value.parse().ok())
+                .filter(|angle: &f32| *angle > 0. && *angle < 90.)
+                .unwrap_or(DEFAULT_OVERHANG_ANGLE),
+            nozzle_mm: parameter("nozzle")
+                .and_then(|value| value.parse().ok())
+                .filter(|width: &f32| *width > 0.)
+                .unwrap_or(DEFAULT_NOZZLE_MM),
+            target: None,
+            analyzed: None,
+            summary: None,
+            overlay_matl: None,
+        }
+    }
+}
+
+/// How a triangle would print.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Face {
+    /// looking up or sideways
+    Printable,
+    /// looking down, by this angle from the vertical in degrees
+    Overhang(f32),
+    /// part of a wall thinner than the nozzle
+    ThinWall,
+    /// resting on the bed
+    BedContact,
+}
+
+impl Face {
+    fn color(self, overhang_angle: f32) -> Color {
+        match self {
+            Face::Printable => Color::from(GRAY_200),
+            Face::Overhang(angle) if angle >= overhang_angle => Color::from(RED_500),
+            Face::Overhang(angle) => Color::from(GRAY_200).mix(&Color::from(AMBER_400), angle / overhang_angle),
+            Face::ThinWall => Color::from(PURPLE_500),
+            Face::BedContact => Color::from(SKY_500),
+        }
+    }
+}
+
+/// The areas of the faces needing attention, summed over the whole model.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Summary {
+    overhang_area: f32,
+    thin_wall_area: f32,
+    bed_area: f32,
+}
+
+/// A mesh entity of the leaf, with its transform to the world, where the build direction is.
+struct PrintPart {
+    entity: Entity,
+    positions: Vec<Vec3>,
+    indices: Vec<u32>,
+    transform: Affine3A,
+}
+
+impl PrintPart {
+    fn new(entity: Entity, mesh: &Mesh, transform: Affine3A) -> Option<PrintPart> {
+        if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+            return None;
+        }
+        let positions: Vec<Vec3> = mesh.attribute(Mesh::ATTRIBUTE_POSITION)?
+            .as_float3()?
+            .iter()
+            .map(|position| Vec3::from(*position))
+            .collect();
+        let indices: Vec<u32> = match mesh.indices() {
+            Some(indices) => indices.iter().map(|index| index as u32).collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        if indices.iter().any(|index| *index as usize >= positions.len()) {
+            return None;
+        }
+        Some(PrintPart { entity, positions, indices, transform })
+    }
+
+    /// The corners of the triangles in the world.
+    fn triangles(&self) -> impl Iterator<Item = [Vec3; 3]> + '_ {
+        self.indices.chunks_exact(3)
+            .map(|triangle| [0, 1, 2].map(|corner| self.transform.transform_point3(self.positions[triangle[corner] as usize])))
+    }
+}
+
+/// Classifies each triangle of the parts, in the units of the world. `thin_wall` is the
+/// thickness under which walls are too thin.
+async fn analyze(parts: &[PrintPart], up: Vec3, overhang_angle: f32, thin_wall: f32) -> (Vec<Vec<Face>>, Summary) {
+    let triangles: Vec<[Vec3; 3]> = parts.iter().flat_map(PrintPart::triangles).collect();
+    let normals: Vec<Vec3> = triangles.iter().map(|[a, b, c]| (b - a).cross(c - a).normalize_or_zero()).collect();
+    let (bottom, top) = triangles.iter().flatten()
+        .map(|corner| corner.dot(up))
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(bottom, top), height| (bottom.min(height), top.max(height)));
+    let tolerance = (top - bottom).abs() * 1e-4 + f32::EPSILON;
+    let bvh = Bvh::new(&triangles);
+
+    let mut summary = Summary::default();
+    let mut classify = |index: usize| {
+        let (triangle, normal) = (triangles[index], normals[index]);
+        if normal == Vec3::ZERO {
+            return Face::Printable;
+        }
+        let area = (triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]).length() / 2.;
+        let down = -normal.dot(up);
+        if down > 0.999 && triangle.iter().all(|corner| corner.dot(up) < bottom + tolerance) {
+            summary.bed_area += area;
+            return Face::BedContact;
+        }
+
+        // the other side of the wall faces the opposite way, unlike the faces meeting this
+        // one at a corner
+        let center = (triangle[0] + triangle[1] + triangle[2]) / 3.;
+        let is_other_side = |other: usize| other != index && normals[other].dot(normal) < -0.5;
+        if bvh.any_hit(&triangles, center, -normal, thin_wall, is_other_side) {
+            summary.thin_wall_area += area;
+            return Face::ThinWall;
+        }
+
+        if down <= 0. {
+            return Face::Printable;
+        }
+        let angle = down.min(1.).asin().to_degrees();
+        if angle >= overhang_angle {
+            summary.overhang_area += area;
+        }
+        Face::Overhang(angle)
+    };
+    let mut faces = Vec::with_capacity(triangles.len());
+    for start in (0..triangles.len()).step_by(TRIANGLES_PER_PAUSE) {
+        faces.extend((start..triangles.len().min(start + TRIANGLES_PER_PAUSE)).map(&mut classify));
+        pause().await;
+    }
+
+    let mut faces = faces.into_iter();
+    let faces = parts.iter().map(|part| faces.by_ref().take(part.indices.len() / 3).collect()).collect();
+    (faces, summary)
+}
+
+/// A copy of the mesh of `part`, with a corner for each triangle colored by its face.
+fn overlay_mesh(part: &PrintPart, faces: &[Face], overhang_angle: f32) -> Mesh {
+    let mut positions = Vec::with_capacity(part.indices.len());
+    let mut normals = Vec::with_capacity(part.indices.len());
+    let mut colors = Vec::with_capacity(part.indices.len());
+    for (triangle, face) in part.indices.chunks_exact(3).zip(faces) {
+        let corners = [0, 1, 2].map(|corner| part.positions[triangle[corner] as usize]);
+        let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]).normalize_or_zero();
+        let color = LinearRgba::from(face.color(overhang_angle)).to_f32_array();
+        for corner in corners {
+            positions.push(corner.to_array());
+            normals.push(normal.to_array());
+            colors.push(color);
+        }
+    }
+    let count = positions.len() as u32;
+    Mesh::new(PrimitiveTopology::TriangleList, default())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+        .with_inserted_indices(Indices::U32((0..count).collect()))
+}
+
+/// The overlay being computed for the leaf entity `target`, giving the colored mesh of each
+/// mesh entity.
+#[derive(Component)]
+struct AnalysisTask {
+    target: Entity,
+    task: Task<(Vec<(Entity, Mesh)>, Summary)>,
+}
+
+/// The colored copy of a mesh entity, drawn over it as its child.
+#[derive(Component)]
+#[require(Overlay)]
+struct PrintabilityOverlay;
+
+#[derive(Component)]
+struct PrintabilityLegend;
+
+fn switch_printability(keys: Res<ButtonInput<KeyCode>>, mut printability: ResMut<Printability>) {
+    if printability.target.is_none() {
+        return;
+    }
+    if keys.just_pressed(KeyCode::KeyP) {
+        printability.enabled = !printability.enabled;
+        console_log!("Printability overlay {}", if printability.enabled { "on" } else { "off" });
+    }
+    if keys.just_pressed(KeyCode::KeyB) && printability.enabled {
+        printability.build = printability.build.next();
+        console_log!("Build direction {:?}", printability.build);
+    }
+}
+
+fn follow_leaf(mut printability: ResMut<Printability>, current_leaf: Res<CurrentLeaf>) {
+    if printability.target != current_leaf.0 {
+        printability.target = current_leaf.0;
+    } else if printability.analyzed.is_some() {
+        // the model was turned, e.g. laid flat, so it has to be analyzed again
+        printability.analyzed = None;
+    }
+}
+
+/// Starts analyzing the leaf whenever the overlay is turned on, another leaf is shown or the
+/// build direction changes, dropping the overlay made before.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn start_analysis(
+    mut commands: Commands,
+    mut printability: ResMut<Printability>,
+    tasks: Query<Entity, With<AnalysisTask>>,
+    overlays: Query<Entity, With<PrintabilityOverlay>>,
+    descendants: Query<&Children>,
+    mesh_entities: Query<(&Mesh3d, &GlobalTransform), Without<Overlay>>,
+    dimensions: Query<&ModelDimensions>,
+    meshes: Res<Assets<Mesh>>,
+) {
+    let wanted = printability.target.filter(|_| printability.enabled).map(|target| (target, printability.build));
+    if printability.analyzed == wanted {
+        return;
+    }
+    tasks.iter().chain(&overlays).for_each(|entity| commands.entity(entity).despawn());
+    printability.analyzed = wanted;
+    printability.summary = None;
+    let Some((target, build)) = wanted else { return; };
+    let Ok(dimensions) = dimensions.get(target) else { return; };
+
+    let parts: Vec<PrintPart> = descendants.iter_descendants(target)
+        .filter_map(|entity| {
+            let (mesh, transform) = mesh_entities.get(entity).ok()?;
+            PrintPart::new(entity, meshes.get(&mesh.0)?, transform.affine())
+        })
+        .collect();
+    let up = build.up();
+    let overhang_angle = printability.overhang_angle;
+    let thin_wall = printability.nozzle_mm / dimensions.mm_per_world_unit;
+    // the areas are given in mm²
+    let mm2_per_world_unit2 = dimensions.mm_per_world_unit.powi(2);
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        let (faces, summary) = analyze(&parts, up, overhang_angle, thin_wall).await;
+        let overlays = parts.iter().zip(&faces)
+            .map(|(part, faces)| (part.entity, overlay_mesh(part, faces, overhang_angle)))
+            .collect();
+        let summary = Summary {
+            overhang_area: summary.overhang_area * mm2_per_world_unit2,
+            thin_wall_area: summary.thin_wall_area * mm2_per_world_unit2,
+            bed_area: summary.bed_area * mm2_per_world_unit2,
+        };
+        (overlays, summary)
+    });
+    commands.spawn(AnalysisTask { target, task });
+}
+
+/// Draws the colored meshes over the ones of the leaf once computed.
+fn finish_analysis(
+    mut commands: Commands,
+    mut printability: ResMut<Printability>,
+    mut tasks: Query<(Entity, &mut AnalysisTask)>,
+    mesh_entities: Query<(), With<Mesh3d>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (entity, mut task) in &mut tasks {
+        let Some((overlays, summary)) = block_on(poll_once(&mut task.task)) else { continue; };
+        commands.entity(entity).despawn();
+        if printability.analyzed.map(|(target, _)| target) != Some(task.target) {
+            continue;
+        }
+        console_log!("Printability of {:?}: {summary:?}", task.target);
+
+        // drawn in front of the model, whose faces are at the same depth
+        let overlay_matl = printability.overlay_matl
+            .get_or_insert_with(|| materials.add(StandardMaterial { base_color: Color::WHITE, depth_bias: 1000., ..default() }))
+            .clone();
+        for (mesh_entity, mesh) in overlays.into_iter().filter(|(mesh_entity, _)| mesh_entities.contains(*mesh_entity)) {
+            commands.spawn((
+                PrintabilityOverlay,
+                ChildOf(mesh_entity),
+                Mesh3d(meshes.add(mesh)),
+                MeshMaterial3d(overlay_matl.clone()),
+                Pickable::IGNORE,
+            ));
+        }
+        printability.summary = Some(summary);
+    }
+}
+
+/// The lines of the legend, with their color.
+fn legend_lines(printability: &Printability) -> Vec<(String, Color)> {
+    let mut lines = vec![(
+        format!("Printability, build direction {}: P to hide, B to switch", printability.build.name()),
+        Color::from(GRAY_400),
+    )];
+    let Some(summary) = printability.summary else {
+        lines.push(("Analyzing…".to_string(), Color::from(GRAY_400)));
+        return lines;
+    };
+    let area = |area| format_metric(area, "mm²", "cm²", 100.);
+    lines.push((format!("Overhangs over {}°: {}", printability.overhang_angle, area(summary.overhang_area)), Color::from(RED_500)));
+    lines.push((format!("Walls under {} mm: {}", printability.nozzle_mm, area(summary.thin_wall_area)), Color::from(PURPLE_500)));
+    lines.push((format!("Bed contact: {}", area(summary.bed_area)), Color::from(SKY_500)));
+    lines
+}
+
+fn show_legend(
+    mut commands: Commands,
+    printability: Res<Printability>,
+    legends: Query<Entity, With<PrintabilityLegend>>,
+) {
+    legends.iter().for_each(|entity| commands.entity(entity).despawn());
+    if printability.target.is_none() || !printability.enabled {
+        return;
+    }
+    commands
+        .spawn((
+            PrintabilityLegend,
+            Node {
+                position_type: PositionType::Absolute,
+                // below the render mode
+                top: Val::Px(32.),
+                left: Val::Px(12.),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.),
+                ..default()
+            },
+            Pickable::IGNORE,
+        ))
+        .with_children(|legend| {
+            for (line, color) in legend_lines(&printability) {
+                legend.spawn((Text::new(line), TextFont { font_size: 12., ..default() }, TextColor(color), Pickable::IGNORE));
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        ecs::entity::Entity,
+        math::{Affine3A, Vec3},
+        prelude::{Cuboid, MeshBuilder, Meshable},
+        tasks::block_on,
+    };
+
+    use crate::printability::{analyze, BuildDirection, Face, PrintPart, Printability};
+
+    #[test]
+    fn test_analyze() {
+        let part = |size: Vec3, center: Vec3| {
+            PrintPart::new(Entity::PLACEHOLDER, &Cuboid::from_size(size).mesh().build(), Affine3A::from_translation(center)).unwrap()
+        };
+        // a cube on the bed, a wall too thin beside it, and a slab floating over them
+        let parts = [
+            part(Vec3::splat(10.), Vec3::new(0., 5., 0.)),
+            part(Vec3::new(0.2, 10., 10.), Vec3::new(10., 5., 0.)),
+            part(Vec3::new(20., 2., 10.), Vec3::new(5., 20., 0.)),
+        ];
+        let (faces, summary) = block_on(analyze(&parts, Vec3::Y, 45., 0.4));
+        assert_eq!(faces.iter().map(Vec::len).collect::<Vec<_>>(), vec![12, 12, 12]);
+        assert!(faces[0].iter().all(|face| matches!(face, Face::Printable | Face::BedContact)));
+        assert_eq!(faces[0].iter().filter(|face| **face == Face::BedContact).count(), 2);
+        assert_eq!(faces[1].iter().filter(|face| **face == Face::ThinWall).count(), 4);
+        assert_eq!(faces[2].iter().filter(|face| matches!(face, Face::Overhang(angle) if *angle > 89.9)).count(), 2);
+        assert!((summary.bed_area - 102.).abs() < 0.01);
+        assert!((summary.thin_wall_area - 200.).abs() < 0.01);
+        assert!((summary.overhang_area - 200.).abs() < 0.01);
+
+        // upside down, the top of the slab rests on the bed, and the top of the cube overhangs
+        let (faces, summary) = block_on(analyze(&parts, Vec3::NEG_Y, 45., 0.4));
+        assert_eq!(faces[2].iter().filter(|face| **face == Face::BedContact).count(), 2);
+        assert_eq!(faces[0].iter().filter(|face| matches!(face, Face::Overhang(angle) if *angle > 89.9)).count(), 2);
+        assert!((summary.bed_area - 200.).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_from_fragment() {
+        let printability = Printability::from_fragment("node=2&build=-Y&overhang=50&nozzle=0.6");
+        assert_eq!((printability.build, printability.overhang_angle, printability.nozzle_mm), (BuildDirection::NegY, 50., 0.6));
+        let printability = Printability::from_fragment("build=w&overhang=90&nozzle=0");
+        assert_eq!((printability.build, printability.overhang_angle, printability.nozzle_mm), (BuildDirection::Z, 45., 0.4));
+    }
+}
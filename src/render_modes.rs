@@ -0,0 +1,394 @@
+//! Switches how the model rendered on its own is drawn, to inspect its topology: with the flat
+//! triangles it was loaded with, smoothed, with the edges of the triangles drawn over it, colored
+//! by the direction each triangle faces (which shows triangles wound the wrong way), or
+//! semi-transparent. Pressing R goes through the modes, and the url fragment can choose the
+//! initial one, e.g. `#manifest=/tree.json&render=wireframe`.
+//!
+//! The modes replace the mesh and the material of each mesh entity of the leaf, keeping the
+//! originals to switch back, and work together with the clipping plane.
+
+use bevy::{
+    color::palettes::tailwind::GRAY_400,
+    mesh::{Indices, PrimitiveTopology},
+    platform::collections::HashMap,
+    prelude::*,
+};
+
+use crate::{
+    clipping::{ClipMaterial, SwapClipMaterials, Unclipped}, dimensions::{CurrentLeaf, TrackCurrentLeaf}, formats::srgb_to_linear,
+    manifest::fragment_parameters, search::not_typing,
+};
+
+pub struct RenderModesPlugin;
+
+impl Plugin for RenderModesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (
+            switch_render_mode.run_if(not_typing),
+            follow_leaf.after(TrackCurrentLeaf).run_if(resource_changed::<CurrentLeaf>),
+            apply_render_mode.before(SwapClipMaterials),
+            show_render_mode.run_if(resource_changed::<RenderModes>),
+        ).chain().run_if(resource_exists::<RenderModes>));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RenderMode {
+    /// as loaded, i.e. with one normal per triangle
+    #[default]
+    Flat,
+    /// with the normals averaged where triangles meet
+    Smooth,
+    /// with the edges of the triangles drawn over the model
+    Wireframe,
+    /// colored by the direction each triangle faces, according to the order of its corners
+    Normals,
+    /// semi-transparent, showing the inside of the model
+    XRay,
+}
+
+impl RenderMode {
+    const ALL: [RenderMode; 5] = [RenderMode::Flat, RenderMode::Smooth, RenderMode::Wireframe, RenderMode::Normals, RenderMode::XRay];
+
+    fn next(self) -> RenderMode {
+        let index = RenderMode::ALL.iter().position(|mode| *mode == self).unwrap_or(0);
+        RenderMode::ALL[(index + 1) % RenderMode::ALL.len()]
+    }
+
+    /// The name used in the url fragment.
+    fn name(self) -> &'static str {
+        match self {
+            RenderMode::Flat => "flat",
+            RenderMode::Smooth => "smooth",
+            RenderMode::Wireframe => "wireframe",
+            RenderMode::Normals => "normals",
+            RenderMode::XRay => "xray",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<RenderMode> {
+        RenderMode::ALL.into_iter().find(|mode| mode.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// The render mode of the leaf shown, with the meshes and materials made for it.
+#[derive(Resource)]
+pub struct RenderModes {
+    mode: RenderMode,
+    /// the leaf entity being rendered
+    target: Option<Entity>,
+    /// the meshes made from the ones of the leaf, by the mesh they replace and the mode
+    meshes: HashMap<(AssetId<Mesh>, RenderMode), Handle<Mesh>>,
+    /// the materials made from the ones of the leaf, by the material they replace and the mode
+    materials: HashMap<(AssetId<StandardMaterial>, RenderMode), Handle<StandardMaterial>>,
+    /// the material of the edges drawn in the wireframe mode
+    edges_matl: Option<Handle<StandardMaterial>>,
+}
+
+impl RenderModes {
+    /// Reads the `render` parameter of the url fragment, if any.
+    pub fn from_fragment(fragment: &str) -> RenderModes {
+        let mode = fragment_parameters(fragment).unwrap_or_default().into_iter()
+            .find(|(key, _)| *key == "render")
+            .and_then(|(_, value)| RenderMode::from_name(&value))
+            .unwrap_or_default();
+        RenderModes { mode, target: None, meshes: HashMap::new(), materials: HashMap::new(), edges_matl: None }
+    }
+
+    /// The mesh to render `mesh` with in the current mode, made once for each mesh.
+    fn mesh_for(&mut self, mesh: &Handle<Mesh>, meshes: &mut Assets<Mesh>) -> Handle<Mesh> {
+        let make: fn(&Mesh) -> Option<Mesh> = match self.mode {
+            RenderMode::Smooth => smooth_mesh,
+            RenderMode::Normals => normals_mesh,
+            RenderMode::Flat | RenderMode::Wireframe | RenderMode::XRay => return mesh.clone(),
+        };
+        self.meshes.entry((mesh.id(), self.mode))
+            .or_insert_with(|| match meshes.get(mesh).and_then(make) {
+                Some(made) => meshes.add(made),
+                None => mesh.clone(),
+            })
+            .clone()
+    }
+
+    /// The material to render a mesh with `material` in the current mode, made once for each
+    /// material.
+    fn material_for(&mut self, material: &Handle<StandardMaterial>, materials: &mut Assets<StandardMaterial>) -> Handle<StandardMaterial> {
+        if matches!(self.mode, RenderMode::Flat | RenderMode::Smooth) {
+            return material.clone();
+        }
+        let mode = self.mode;
+        self.materials.entry((material.id(), mode))
+            .or_insert_with(|| {
+                let base = materials.get(material).cloned().unwrap_or_default();
+                materials.add(match mode {
+                    // pushed back a little, so that the edges drawn over it are not hidden
+                    RenderMode::Wireframe => StandardMaterial { depth_bias: -1000., ..base },
+                    // the colors of the vertices, unchanged by lighting
+                    RenderMode::Normals => StandardMaterial { base_color: Color::WHITE, unlit: true, ..default() },
+                    RenderMode::XRay => StandardMaterial {
+                        base_color: base.base_color.with_alpha(XRAY_ALPHA),
+                        alpha_mode: AlphaMode::Blend,
+                        double_sided: true,
+                        cull_mode: None,
+                        ..base
+                    },
+                    RenderMode::Flat | RenderMode::Smooth => base,
+                })
+            })
+            .clone()
+    }
+}
+
+/// How opaque the model is in the x-ray mode.
+const XRAY_ALPHA: f32 = 0.25;
+
+/// The mesh and material a mesh entity of the leaf was loaded with.
+#[derive(Component)]
+pub struct LoadedLook {
+    pub mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+/// The render mode a mesh entity of the leaf is currently rendered with.
+#[derive(Component)]
+struct RenderedAs(RenderMode);
+
+/// A mesh drawn over a mesh entity of the leaf, as its child, which the render modes leave alone.
+#[derive(Component, Default)]
+pub struct Overlay;
+
+/// The edges drawn over a mesh entity in the wireframe mode, as its child.
+#[derive(Component)]
+#[require(Overlay)]
+struct WireframeOverlay;
+
+fn switch_render_mode(keys: Res<ButtonInput<KeyCode>>, mut render_modes: ResMut<RenderModes>) {
+    if keys.just_pressed(KeyCode::KeyR) && render_modes.target.is_some() {
+        render_modes.mode = render_modes.mode.next();
+        console_log!("Render mode {:?}", render_modes.mode);
+    }
+}
+
+/// Forgets the meshes and materials made for the last leaf whenever another one is shown.
+fn follow_leaf(mut render_modes: ResMut<RenderModes>, current_leaf: Res<CurrentLeaf>) {
+    if render_modes.target != current_leaf.0 {
+        render_modes.target = current_leaf.0;
+        render_modes.meshes.clear();
+        render_modes.materials.clear();
+    }
+}
+
+/// Renders each mesh entity of the leaf in the current mode. This runs every frame, since the
+/// meshes of glTF scenes might be spawned after the mode is chosen.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn apply_render_mode(
+    mut commands: Commands,
+    mut render_modes: ResMut<RenderModes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    descendants: Query<&Children>,
+    mesh_entities: Query<
+        (&Mesh3d, Option<&MeshMaterial3d<StandardMaterial>>, Option<&Unclipped>, Option<&LoadedLook>, Option<&RenderedAs>),
+        Without<Overlay>,
+    >,
+    overlays: Query<(), With<WireframeOverlay>>,
+) {
+    let Some(target) = render_modes.target else { return; };
+    let mode = render_modes.mode;
+    for entity in descendants.iter_descendants(target) {
+        let Ok((mesh, material, unclipped, loaded_look, rendered_as)) = mesh_entities.get(entity) else { continue; };
+        if rendered_as.is_some_and(|rendered_as| rendered_as.0 == mode) {
+            continue;
+        }
+        // the material is the one the clipping plane replaced, if any
+        let (mesh, material) = match loaded_look {
+            Some(loaded_look) => (loaded_look.mesh.clone(), loaded_look.material.clone()),
+            None => {
+                let Some(material) = material.map(|material| material.0.clone()).or(unclipped.map(|unclipped| unclipped.0.clone())) else {
+                    continue;
+                };
+                (mesh.0.clone(), material)
+            },
+        };
+
+        let render_modes = render_modes.bypass_change_detection();
+        let mut entity_commands = commands.entity(entity);
+        entity_commands
+            .insert((
+                Mesh3d(render_modes.mesh_for(&mesh, &mut meshes)),
+                MeshMaterial3d(render_modes.material_for(&material, &mut materials)),
+                RenderedAs(mode),
+            ))
+            // the clipping plane is applied again to the new material
+            .remove::<(MeshMaterial3d<ClipMaterial>, Unclipped)>();
+        if loaded_look.is_none() {
+            entity_commands.insert(LoadedLook { mesh: mesh.clone(), material });
+        }
+
+        for child in descendants.iter_descendants(entity).filter(|child| overlays.contains(*child)) {
+            commands.entity(child).despawn();
+        }
+        if mode == RenderMode::Wireframe {
+            // stored along with the meshes made for the other modes, which replace the mesh instead
+            let wireframe = render_modes.meshes.entry((mesh.id(), mode))
+                .or_insert_with(|| meshes.get(&mesh).and_then(wireframe_mesh).map(|made| meshes.add(made)).unwrap_or_default())
+                .clone();
+            let edges_matl = render_modes.edges_matl
+                .get_or_insert_with(|| materials.add(StandardMaterial { base_color: Color::BLACK, unlit: true, ..default() }))
+                .clone();
+            commands.spawn((WireframeOverlay, ChildOf(entity), Mesh3d(wireframe), MeshMaterial3d(edges_matl), Pickable::IGNORE));
+        }
+    }
+}
+
+/// Averages the normals of the corners at the same position, weighting each triangle by its area.
+fn smooth_mesh(mesh: &Mesh) -> Option<Mesh> {
+    let triangles = corner_triangles(mesh)?;
+    let positions = mesh.attribute(Mesh::ATTRIBUTE_POSITION)?.as_float3()?;
+    let mut sums: HashMap<[u32; 3], Vec3> = HashMap::new();
+    for [a, b, c] in &triangles {
+        let corners = [*a, *b, *c].map(|index| Vec3::from(positions[index]));
+        // the cross product is as long as twice the area of the triangle
+        let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+        for index in [a, b, c] {
+            *sums.entry(position_key(positions[*index])).or_default() += normal;
+        }
+    }
+    let normals: Vec<[f32; 3]> = positions.iter()
+        .map(|position| sums.get(&position_key(*position)).copied().unwrap_or_default().normalize_or_zero().to_array())
+        .collect();
+    Some(mesh.clone().with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals))
+}
+
+/// Colors each triangle by the direction it faces, mapping the axes to red, green and blue, so
+/// that a triangle wound the wrong way stands out among its neighbours.
+fn normals_mesh(mesh: &Mesh) -> Option<Mesh> {
+    let triangles = corner_triangles(mesh)?;
+    let positions = mesh.attribute(Mesh::ATTRIBUTE_POSITION)?.as_float3()?;
+    let mut colors = vec![[0., 0., 0., 1.]; positions.len()];
+    for [a, b, c] in triangles {
+        let corners = [a, b, c].map(|index| Vec3::from(positions[index]));
+        let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]).normalize_or_zero();
+        let color = srgb_to_linear((normal * 0.5 + 0.5).extend(1.).to_array());
+        for index in [a, b, c] {
+            colors[index] = color;
+        }
+    }
+    Some(mesh.clone().with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors))
+}
+
+/// A mesh with the edges of the triangles, each one just once even if shared by two triangles.
+fn wireframe_mesh(mesh: &Mesh) -> Option<Mesh> {
+    let triangles = corner_triangles(mesh)?;
+    let positions = mesh.attribute(Mesh::ATTRIBUTE_POSITION)?.as_float3()?;
+    let mut edges = HashMap::new();
+    for [a, b, c] in triangles {
+        for (start, end) in [(a, b), (b, c), (c, a)] {
+            let (start, end) = (positions[start], positions[end]);
+            let key = if position_key(start) < position_key(end) {
+                [position_key(start), position_key(end)]
+            } else {
+                [position_key(end), position_key(start)]
+            };
+            edges.entry(key).or_insert([start, end]);
+        }
+    }
+    let line_positions: Vec<[f32; 3]> = edges.into_values().flatten().collect();
+    let normals = vec![[0., 1., 0.]; line_positions.len()];
+    Some(Mesh::new(PrimitiveTopology::LineList, mesh.asset_usage)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, line_positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals))
+}
+
+/// The indices of the corners of each triangle of a mesh made of a list of triangles.
+fn corner_triangles(mesh: &Mesh) -> Option<Vec<[usize; 3]>> {
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+        return None;
+    }
+    let vertex_count = mesh.count_vertices();
+    let indices: Vec<usize> = match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.iter().map(|index| *index as usize).collect(),
+        Some(Indices::U32(indices)) => indices.iter().map(|index| *index as usize).collect(),
+        None => (0..vertex_count).collect(),
+    };
+    Some(indices.chunks_exact(3)
+        .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+        .filter(|triangle| triangle.iter().all(|index| *index < vertex_count))
+        .collect())
+}
+
+/// Identifies corners at exactly the same position.
+fn position_key(position: [f32; 3]) -> [u32; 3] {
+    position.map(f32::to_bits)
+}
+
+#[derive(Component)]
+struct RenderModeText;
+
+fn show_render_mode(
+    mut commands: Commands,
+    render_modes: Res<RenderModes>,
+    texts: Query<Entity, With<RenderModeText>>,
+) {
+    texts.iter().for_each(|entity| commands.entity(entity).despawn());
+    if render_modes.target.is_none() || render_modes.mode == RenderMode::Flat {
+        return;
+    }
+    commands.spawn((
+        RenderModeText,
+        Text::new(format!("Render mode: {}, R to switch", render_modes.mode.name())),
+        TextFont { font_size: 12., ..default() },
+        TextColor(Color::from(GRAY_400)),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(12.),
+            left: Val::Px(12.),
+            ..default()
+        },
+        Pickable::IGNORE,
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{math::Vec3, mesh::VertexAttributeValues, prelude::{Cuboid, Mesh, MeshBuilder, Meshable}};
+
+    use crate::{
+        formats::srgb_to_linear,
+        render_modes::{normals_mesh, smooth_mesh, wireframe_mesh, RenderMode, RenderModes},
+    };
+
+    #[test]
+    fn test_from_fragment() {
+        assert_eq!(RenderModes::from_fragment("manifest=/tree.json&render=XRay").mode, RenderMode::XRay);
+        assert_eq!(RenderModes::from_fragment("render=sketchy").mode, RenderMode::Flat);
+        assert_eq!(RenderModes::from_fragment("/a.stl").mode, RenderMode::Flat);
+    }
+
+    #[test]
+    fn test_meshes() {
+        let cube = Cuboid::new(1., 1., 1.).mesh().build();
+        let positions = cube.attribute(Mesh::ATTRIBUTE_POSITION).unwrap().as_float3().unwrap();
+
+        // 12 edges and a diagonal on each face
+        let wireframe = wireframe_mesh(&cube).unwrap();
+        assert_eq!(wireframe.count_vertices(), 18 * 2);
+
+        // the corners of the cube point away from its center, instead of along the faces
+        let smooth = smooth_mesh(&cube).unwrap();
+        let normals = smooth.attribute(Mesh::ATTRIBUTE_NORMAL).unwrap().as_float3().unwrap();
+        for (position, normal) in positions.iter().zip(normals) {
+            let (position, normal) = (Vec3::from(*position), Vec3::from(*normal));
+            assert!((normal.length() - 1.).abs() < 1e-5);
+            assert!(normal.dot(position.normalize()) > 0.9);
+        }
+
+        // only the four corners of the face looking along X get its color
+        let normals = normals_mesh(&cube).unwrap();
+        let Some(VertexAttributeValues::Float32x4(colors)) = normals.attribute(Mesh::ATTRIBUTE_COLOR) else {
+            panic!("the mesh has no colors");
+        };
+        let facing_x = srgb_to_linear([1., 0.5, 0.5, 1.]);
+        assert_eq!(colors.iter().filter(|color| **color == facing_x).count(), 4);
+    }
+}
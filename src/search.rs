@@ -0,0 +1,232 @@
+//! Finds nodes anywhere in the loaded tree by their title, url or tags. Pressing `/` opens a
+//! search bar, and Enter shows the matching nodes in a grid, from which they can be opened as if
+//! reached by clicking through the tree.
+//!
+//! Each word of the query must match, and words starting with `#` only match tags, e.g.
+//! `benchy #calibration`. Nodes in lazy manifests are found only once their manifest is loaded.
+
+use std::sync::Arc;
+
+use bevy::{
+    color::palettes::tailwind::{GRAY_400, RED_400},
+    input::{keyboard::{Key, KeyboardInput}, ButtonState},
+    prelude::*,
+};
+
+use crate::{meshes_tree::MeshTreeNode, MeshTreeRes, OneShotSystemsRes};
+
+pub struct SearchPlugin;
+
+impl Plugin for SearchPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Search>()
+            .add_systems(Update, (
+                type_search.run_if(resource_exists::<MeshTreeRes>),
+                show_search_bar.run_if(resource_changed::<Search>),
+            ).chain());
+    }
+}
+
+/// The state of the search bar.
+#[derive(Resource, Default)]
+pub struct Search {
+    /// whether the search bar is open, and thus receiving the keys pressed
+    typing: bool,
+    query: String,
+    /// how many nodes match the query
+    matches: usize,
+    /// the node listing the results last shown, kept here since the tree only holds weak
+    /// references to the current node
+    results: Option<Arc<MeshTreeNode>>,
+}
+
+/// A run condition for the systems using the keyboard, which are paused while typing a query.
+/// The keys pressed in the frame the search bar is closed are ignored too.
+pub fn not_typing(search: Option<Res<Search>>) -> bool {
+    search.is_none_or(|search| !search.typing && !search.is_changed())
+}
+
+/// A lowercase word of the query, which might be a tag to match exactly.
+#[derive(Debug, PartialEq)]
+enum Term {
+    Word(String),
+    Tag(String),
+}
+
+fn parse_query(query: &str) -> Vec<Term> {
+    query.split_whitespace()
+        .map(str::to_lowercase)
+        .filter_map(|word| match word.strip_prefix('#') {
+            Some("") => None,
+            Some(tag) => Some(Term::Tag(tag.to_string())),
+            None => Some(Term::Word(word)),
+        })
+        .collect()
+}
+
+/// Whether all the terms match the title, url or tags of the node.
+fn matches(node: &MeshTreeNode, terms: &[Term]) -> bool {
+    let metadata = &node.metadata;
+    let title = metadata.title.as_deref().unwrap_or_default().to_lowercase();
+    let url = node.url.to_lowercase();
+    let tags: Vec<String> = metadata.tags.iter().map(|tag| tag.to_lowercase()).collect();
+    terms.iter().all(|term| match term {
+        Term::Tag(tag) => tags.contains(tag),
+        Term::Word(word) => title.contains(word) || url.contains(word) || tags.iter().any(|tag| tag.contains(word)),
+    })
+}
+
+/// The nodes below `root` matching `query`, in the order they appear in the tree.
+fn find_nodes(root: &Arc<MeshTreeNode>, query: &str) -> Vec<Arc<MeshTreeNode>> {
+    let terms = parse_query(query);
+    if terms.is_empty() {
+        return Vec::new();
+    }
+    let mut found = Vec::new();
+    let mut pending: Vec<&Arc<MeshTreeNode>> = root.children().iter().rev().collect();
+    while let Some(node) = pending.pop() {
+        if matches(node, &terms) {
+            found.push(node.clone());
+        }
+        pending.extend(node.children().iter().rev());
+    }
+    found
+}
+
+fn type_search(
+    mut commands: Commands,
+    mut keyboard: MessageReader<KeyboardInput>,
+    mut search: ResMut<Search>,
+    mut mesh_tree: ResMut<MeshTreeRes>,
+    one_shot_systems: Res<OneShotSystemsRes>,
+) {
+    for input in keyboard.read() {
+        if input.state != ButtonState::Pressed {
+            continue;
+        }
+        if !search.typing {
+            if input.logical_key == Key::Character("/".into()) {
+                search.typing = true;
+            }
+            continue;
+        }
+
+        match &input.logical_key {
+            Key::Escape => search.typing = false,
+            Key::Enter => {
+                let Some(root) = mesh_tree.root.clone() else { continue; };
+                let found = find_nodes(&root, &search.query);
+                if found.is_empty() {
+                    continue;
+                }
+                console_log!("Showing {} nodes matching '{}'", found.len(), search.query);
+                // the back button leads to the node the search started from, skipping the
+                // results of the previous search, which are going away
+                let parent = match &search.results {
+                    Some(previous) if mesh_tree.current.ptr_eq(&Arc::downgrade(previous)) => previous.parent.clone(),
+                    _ => mesh_tree.current.clone(),
+                };
+                let results = MeshTreeNode::listing(format!("Search: {}", search.query.trim()), parent, found);
+                mesh_tree.current = Arc::downgrade(&results);
+                search.results = Some(results);
+                search.typing = false;
+                commands.run_system(one_shot_systems.update_current_sys);
+            },
+            Key::Backspace => {
+                search.query.pop();
+            },
+            _ => if let Some(text) = &input.text {
+                search.query.extend(text.chars().filter(|c| !c.is_control()));
+            },
+        }
+        let matches = mesh_tree.root.as_ref().map_or(0, |root| find_nodes(root, &search.query).len());
+        search.matches = matches;
+    }
+}
+
+// Marker component for the search bar.
+#[derive(Component)]
+pub struct SearchBar;
+
+fn show_search_bar(mut commands: Commands, search: Res<Search>, bars: Query<Entity, With<SearchBar>>) {
+    bars.iter().for_each(|entity| commands.entity(entity).despawn());
+    if !search.typing {
+        return;
+    }
+
+    let (hint, hint_color) = match (search.query.trim().is_empty(), search.matches) {
+        (true, _) => ("Type a name or #tag, Esc to close".to_string(), GRAY_400),
+        (false, 0) => ("No matches".to_string(), RED_400),
+        (false, 1) => ("1 match, Enter to show it".to_string(), GRAY_400),
+        (false, count) => (format!("{count} matches, Enter to show them"), GRAY_400),
+    };
+    commands.spawn((
+        SearchBar,
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(56.),
+            width: Val::Percent(100.),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        Pickable::IGNORE,
+        GlobalZIndex(1),
+    )).with_children(|bar| {
+        bar.spawn((
+            Node {
+                width: Val::Px(360.),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.),
+                padding: UiRect::all(Val::Px(10.)),
+                ..default()
+            },
+            BackgroundColor(Color::BLACK.with_alpha(0.8)),
+            children![
+                (
+                    Text::new(format!("Search: {}_", search.query)),
+                    TextFont { font_size: 16., ..default() },
+                    TextColor(Color::WHITE),
+                ),
+                (
+                    Text::new(hint),
+                    TextFont { font_size: 12., ..default() },
+                    TextColor(Color::from(hint_color)),
+                ),
+            ],
+        ));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{meshes_tree::MeshTreeNode, search::{find_nodes, parse_query, Term}};
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(parse_query("  Benchy #Boat # "), vec![Term::Word("benchy".to_string()), Term::Tag("boat".to_string())]);
+        assert_eq!(parse_query(""), vec![]);
+    }
+
+    #[test]
+    fn test_find_nodes() {
+        let (root, _) = MeshTreeNode::from_json(br#"{
+            "children": [
+                { "url": "/benchy.stl", "title": "3DBenchy", "tags": ["boat", "calibration"] },
+                { "title": "Boats", "children": [{ "url": "/boats/sailboat.stl" }, { "url": "/boats/tug.stl", "tags": ["tugboat"] }] },
+                { "url": "/cube.stl", "tags": ["calibration"] }
+            ]
+        }"#, "/tree.json").unwrap();
+        let names = |query: &str| -> Vec<String> {
+            find_nodes(&root, query).iter().map(|node| node.display_name().to_string()).collect()
+        };
+        assert_eq!(names("boat"), vec!["3DBenchy", "Boats", "sailboat.stl", "tug.stl"]);
+        assert_eq!(names("#boat"), vec!["3DBenchy"]);
+        assert_eq!(names("#calibration cube"), vec!["cube.stl"]);
+        assert_eq!(names("BOATS/"), vec!["sailboat.stl", "tug.stl"]);
+        assert!(names("").is_empty());
+
+        // the results keep their place in the tree
+        let results = find_nodes(&root, "tug");
+        assert_eq!(results[0].path(), vec![1, 1]);
+    }
+}
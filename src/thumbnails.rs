@@ -0,0 +1,188 @@
+//! Shows the children with a `thumbnail` in the manifest as a flat image in the grid, instead of
+//! downloading their whole mesh. The mesh is loaded only when the thumbnail is hovered (or
+//! highlighted with the keyboard), and then replaces the image, spinning like the other ones.
+
+use bevy::{ecs::system::EntityCommands, prelude::*, scene::SceneInstanceReady};
+
+use crate::{
+    child_child_as_current_on, dimensions::{FitMesh, FitWhenLoaded}, formats::{load_model, Model}, rotating::Rotate,
+    MeshTreeRes, ShownNode,
+};
+
+pub struct ThumbnailsPlugin;
+
+impl Plugin for ThumbnailsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ThumbnailAssets>()
+            .add_systems(Update, (fit_thumbnails, update_thumbnail_items.run_if(resource_exists::<MeshTreeRes>)));
+    }
+}
+
+/// The assets shared by all the thumbnails.
+#[derive(Resource)]
+pub struct ThumbnailAssets {
+    /// a unit square facing the camera, textured with each thumbnail
+    quad: Handle<Mesh>,
+    /// given to the thumbnail while its model is shown, keeping it there to be hovered and clicked
+    hidden_matl: Handle<StandardMaterial>,
+}
+
+impl FromWorld for ThumbnailAssets {
+    fn from_world(world: &mut World) -> Self {
+        let quad = world.resource_mut::<Assets<Mesh>>().add(Rectangle::new(1., 1.));
+        let hidden_matl = world.resource_mut::<Assets<StandardMaterial>>().add(StandardMaterial {
+            base_color: Color::NONE,
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        });
+        ThumbnailAssets { quad, hidden_matl }
+    }
+}
+
+/// Placed on a grid item shown with a thumbnail, to keep track of its model.
+#[derive(Component)]
+pub struct ThumbnailItem {
+    /// the entity with the image
+    thumbnail: Entity,
+    /// the entity with the model, once it has been requested
+    model: Option<Entity>,
+    /// the parent of the model turning while it is shown, so that the thumbnail keeps facing the
+    /// camera and receiving the pointer
+    pivot: Option<Entity>,
+    hovered: bool,
+    pub highlighted: bool,
+    /// whether the model is being shown in place of the image
+    showing_model: bool,
+}
+
+/// The image shown in place of a model.
+#[derive(Component)]
+pub struct Thumbnail {
+    pub image: Handle<Image>,
+    pub material: Handle<StandardMaterial>,
+}
+
+/// Adds a thumbnail showing `image` to the grid `item` of the child at `child_index`.
+pub fn spawn_thumbnail(
+    item: &mut EntityCommands,
+    image: Handle<Image>,
+    thumbnail_assets: &ThumbnailAssets,
+    materials: &mut Assets<StandardMaterial>,
+    child_index: usize,
+) {
+    let material = materials.add(StandardMaterial {
+        base_color_texture: Some(image.clone()),
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        ..default()
+    });
+    let mut thumbnail = Entity::PLACEHOLDER;
+    item.with_children(|parent| {
+        thumbnail = parent.spawn((
+            Thumbnail { image, material: material.clone() },
+            Mesh3d(thumbnail_assets.quad.clone()),
+            MeshMaterial3d(material),
+        ))
+            .observe(hover_thumbnail_on::<Pointer<Over>>(true))
+            .observe(hover_thumbnail_on::<Pointer<Out>>(false))
+            .observe(child_child_as_current_on::<Pointer<Release>>(child_index))
+            .id();
+    });
+    item.insert(ThumbnailItem { thumbnail, model: None, pivot: None, hovered: false, highlighted: false, showing_model: false });
+}
+
+/// Returns an observer that records whether the pointer is over the thumbnail.
+fn hover_thumbnail_on<E: EntityEvent>(hovered: bool) -> impl Fn(On<E>, Query<&ChildOf>, Query<&mut ThumbnailItem>) {
+    move |trigger, parents, mut items| {
+        let Ok(child_of) = parents.get(trigger.event().event_target()) else { return; };
+        if let Ok(mut item) = items.get_mut(child_of.parent()) {
+            item.hovered = hovered;
+        }
+    }
+}
+
+/// Scales the thumbnails to keep the aspect ratio of their image, once loaded.
+fn fit_thumbnails(mut thumbnails: Query<(&Thumbnail, &mut Transform)>, images: Res<Assets<Image>>) {
+    for (thumbnail, mut transform) in &mut thumbnails {
+        let Some(image) = images.get(&thumbnail.image) else { continue; };
+        let size = image.size_f32();
+        let scale = (size / size.max_element().max(1.)).extend(1.);
+        if transform.scale != scale {
+            transform.scale = scale;
+        }
+    }
+}
+
+/// Loads the model of the thumbnails being hovered or highlighted, and shows it in place of the
+/// image once it is loaded and fitted into the grid cell.
+#[allow(clippy::too_many_arguments)]
+fn update_thumbnail_items(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut mesh_tree: ResMut<MeshTreeRes>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    thumbnail_assets: Res<ThumbnailAssets>,
+    mut items: Query<(Entity, &mut ThumbnailItem, &ShownNode)>,
+    mut thumbnails: Query<(&Thumbnail, &mut MeshMaterial3d<StandardMaterial>)>,
+    mut models: Query<(&mut Visibility, Has<FitWhenLoaded>), With<FitMesh>>,
+) {
+    for (entity, mut item, shown_node) in &mut items {
+        let wanted = item.hovered || item.highlighted;
+        if wanted && item.model.is_none() {
+            let Some(node) = shown_node.0.upgrade() else { continue; };
+            let Some(preview) = node.preview() else { continue; };
+            // the model is not part of the loading screen, the thumbnail stays until it is ready
+            let model = load_model(&asset_server, &preview.url, preview.format());
+            let pivot = commands.spawn((ChildOf(entity), Transform::IDENTITY, Visibility::Inherited)).id();
+            let mut model_entity = commands.spawn((
+                ChildOf(pivot),
+                Transform::from_rotation(node.up_axis().rotation()),
+                FitMesh { normalize: true },
+                FitWhenLoaded,
+                Visibility::Hidden,
+                // the thumbnail behind it keeps receiving the pointer events
+                Pickable::IGNORE,
+            ));
+            match model {
+                Model::Mesh(mesh) => {
+                    model_entity.insert((Mesh3d(mesh), MeshMaterial3d(mesh_tree.material_for(&node, &mut materials))));
+                },
+                Model::Scene(scene) => {
+                    model_entity.insert(SceneRoot(scene)).observe(ignore_picking_in_scene);
+                },
+            }
+            item.model = Some(model_entity.id());
+            item.pivot = Some(pivot);
+        }
+
+        // the model is ready once it has been fitted, which also means it exists
+        let model_ready = item.model.is_some_and(|model| matches!(models.get(model), Ok((_, false))));
+        let show_model = wanted && model_ready;
+        if show_model == item.showing_model {
+            continue;
+        }
+        item.showing_model = show_model;
+
+        if let Some(Ok((mut visibility, _))) = item.model.map(|model| models.get_mut(model)) {
+            *visibility = if show_model { Visibility::Inherited } else { Visibility::Hidden };
+        }
+        if let Ok((thumbnail, mut material)) = thumbnails.get_mut(item.thumbnail) {
+            material.0 = if show_model { thumbnail_assets.hidden_matl.clone() } else { thumbnail.material.clone() };
+        }
+        let Some(mut pivot) = item.pivot.and_then(|pivot| commands.get_entity(pivot).ok()) else { continue; };
+        if show_model {
+            pivot.insert(Rotate);
+        } else {
+            // the model starts facing the camera again next time
+            pivot.remove::<Rotate>().insert(Transform::IDENTITY);
+        }
+    }
+}
+
+/// Lets the pointer reach the thumbnail through the meshes of a glTF scene.
+fn ignore_picking_in_scene(ready: On<SceneInstanceReady>, mut commands: Commands, children: Query<&Children>) {
+    for entity in children.iter_descendants(ready.event().event_target()) {
+        commands.entity(entity).insert(Pickable::IGNORE);
+    }
+}
@@ -0,0 +1,186 @@
+//! Shows the details of a grid item next to the pointer while it is over its mesh: the size of
+//! the file, the number of triangles and the real dimensions of the model.
+
+use bevy::{color::palettes::tailwind::GRAY_400, mesh::{Indices, PrimitiveTopology}, prelude::*};
+
+use crate::{
+    dimensions::{format_length, ModelDimensions}, formats::FileSizes, loading::format_bytes, meshes_tree::MeshTreeNode,
+    thumbnails::Thumbnail, GridItem, MeshTreeRes, ShownNode,
+};
+
+pub struct TooltipPlugin;
+
+impl Plugin for TooltipPlugin {
+    fn build(&self, app: &mut App) {
+        // pointer events on the meshes bubble up to their grid item
+        app.add_observer(show_tooltip)
+            .add_observer(move_tooltip)
+            .add_observer(hide_tooltip)
+            .add_systems(Update, despawn_orphan_tooltips);
+    }
+}
+
+/// The tooltip describing the grid item entity `target`.
+#[derive(Component)]
+pub struct Tooltip {
+    pub target: Entity,
+}
+
+/// How far from the pointer the tooltip is shown, so that it does not cover what is pointed at.
+const POINTER_OFFSET: Vec2 = Vec2::new(16., 16.);
+
+/// What is known about the model shown by a grid item.
+#[derive(Debug, Default)]
+struct ModelStats {
+    file_size: Option<usize>,
+    triangles: Option<usize>,
+    size_mm: Option<Vec3>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn show_tooltip(
+    over: On<Pointer<Over>>,
+    mut commands: Commands,
+    items: Query<(&ShownNode, Option<&ModelDimensions>), With<GridItem>>,
+    children: Query<&Children>,
+    models: Query<&Mesh3d, Without<Thumbnail>>,
+    meshes: Res<Assets<Mesh>>,
+    mesh_tree: Res<MeshTreeRes>,
+    file_sizes: Res<FileSizes>,
+) {
+    let item = over.event().event_target();
+    let Ok((shown_node, dimensions)) = items.get(item) else { return; };
+    let Some(node) = shown_node.0.upgrade() else { return; };
+
+    let mut stats = ModelStats { size_mm: dimensions.map(|dimensions| dimensions.size_mm), ..default() };
+    // nodes without a mesh of their own show a placeholder, which tells nothing about them
+    if node.preview().is_some() {
+        for model in children.iter_descendants(item) {
+            let Ok(mesh) = models.get(model) else { continue; };
+            if mesh.0 == mesh_tree.placeholder_mesh {
+                stats = ModelStats::default();
+                break;
+            }
+            if let Some(file_size) = file_sizes.get(&mesh.0) {
+                *stats.file_size.get_or_insert(0) += file_size;
+            }
+            if let Some(triangles) = meshes.get(&mesh.0).and_then(triangle_count) {
+                *stats.triangles.get_or_insert(0) += triangles;
+            }
+        }
+    } else {
+        stats.size_mm = None;
+    }
+
+    let position = over.pointer_location.position + POINTER_OFFSET;
+    commands.spawn((
+        Tooltip { target: item },
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(position.x),
+            top: Val::Px(position.y),
+            max_width: Val::Px(280.),
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(2.),
+            padding: UiRect::all(Val::Px(8.)),
+            ..default()
+        },
+        BackgroundColor(Color::BLACK.with_alpha(0.75)),
+        // the tooltip must not take the pointer away from the mesh it describes
+        Pickable::IGNORE,
+        GlobalZIndex(1),
+    )).with_children(|tooltip| {
+        tooltip.spawn((
+            Text::new(node.display_name()),
+            TextFont { font_size: 14., ..default() },
+            TextColor(Color::WHITE),
+            Pickable::IGNORE,
+        ));
+        for line in tooltip_lines(&node, &stats) {
+            tooltip.spawn((
+                Text::new(line),
+                TextFont { font_size: 12., ..default() },
+                TextColor(Color::from(GRAY_400)),
+                Pickable::IGNORE,
+            ));
+        }
+    });
+}
+
+fn move_tooltip(moved: On<Pointer<Move>>, mut tooltips: Query<(&Tooltip, &mut Node)>) {
+    let position = moved.pointer_location.position + POINTER_OFFSET;
+    for (tooltip, mut node) in &mut tooltips {
+        if tooltip.target == moved.event().event_target() {
+            node.left = Val::Px(position.x);
+            node.top = Val::Px(position.y);
+        }
+    }
+}
+
+fn hide_tooltip(out: On<Pointer<Out>>, mut commands: Commands, tooltips: Query<(Entity, &Tooltip)>) {
+    for (entity, tooltip) in &tooltips {
+        if tooltip.target == out.event().event_target() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn despawn_orphan_tooltips(
+    mut commands: Commands,
+    tooltips: Query<(Entity, &Tooltip)>,
+    items: Query<(), With<GridItem>>,
+) {
+    for (entity, tooltip) in &tooltips {
+        if !items.contains(tooltip.target) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// The lines of text to show below the name of the node, skipping what is not known (yet).
+fn tooltip_lines(node: &MeshTreeNode, stats: &ModelStats) -> Vec<String> {
+    let mut lines = Vec::new();
+    if !node.children().is_empty() {
+        lines.push(format!("{} items", node.children().len()));
+    }
+    if let Some(file_size) = stats.file_size {
+        lines.push(format!("File: {}", format_bytes(file_size as f64)));
+    }
+    if let Some(triangles) = stats.triangles {
+        lines.push(format!("Triangles: {triangles}"));
+    }
+    if let Some(size) = stats.size_mm {
+        lines.push(format!("Size: {} × {} × {}", format_length(size.x), format_length(size.z), format_length(size.y)));
+    }
+    lines
+}
+
+/// The number of triangles of a mesh made of a list of triangles.
+fn triangle_count(mesh: &Mesh) -> Option<usize> {
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+        return None;
+    }
+    Some(match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.len() / 3,
+        Some(Indices::U32(indices)) => indices.len() / 3,
+        None => mesh.count_vertices() / 3,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{asset::RenderAssetUsages, mesh::{Indices, Mesh, PrimitiveTopology}};
+
+    use crate::tooltip::triangle_count;
+
+    #[test]
+    fn test_triangle_count() {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0f32, 0., 0.]; 6]);
+        assert_eq!(triangle_count(&mesh), Some(2));
+        mesh.insert_indices(Indices::U32(vec![0, 1, 2, 2, 1, 0, 3, 4, 5]));
+        assert_eq!(triangle_count(&mesh), Some(3));
+        let lines = Mesh::new(PrimitiveTopology::LineList, RenderAssetUsages::default());
+        assert_eq!(triangle_count(&lines), None);
+    }
+}
@@ -0,0 +1,261 @@
+//! The convex hull of the points of a model, whose largest face is the side the model rests on
+//! most steadily. It is shared by the viewer and the thumbnail generator, so that thumbnails show
+//! models the way the viewer lays them flat, and thus only depends on glam.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use glam::{Quat, Vec3};
+
+/// The convex hull of a set of points, made of triangles wound counterclockwise seen from
+/// outside, built with the quickhull algorithm.
+pub struct Hull {
+    points: Vec<Vec3>,
+    faces: Vec<HullFace>,
+    /// the face on the left of each edge, going from its first point to its second one
+    edges: HashMap<(usize, usize), usize>,
+    /// how far outside of a face a point must be not to be on it
+    tolerance: f32,
+    /// the first face that might still have points outside of it
+    current: usize,
+}
+
+struct HullFace {
+    corners: [usize; 3],
+    normal: Vec3,
+    /// the points outside of the face, i.e. in front of it, which are yet to be added
+    outside: Vec<usize>,
+    removed: bool,
+}
+
+impl Hull {
+    /// The tetrahedron the hull starts from, made of points as far from each other as possible,
+    /// to which [`Hull::add_next_point`] adds the other points one by one. Returns None when
+    /// there are less than four points not on the same plane.
+    pub fn start(points: Vec<Vec3>) -> Option<Hull> {
+        if points.len() < 4 {
+            return None;
+        }
+        let (min, max) = points.iter().fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), point| (min.min(*point), max.max(*point)));
+        let tolerance = (max - min).max_element() * 1e-5;
+        if tolerance <= 0. {
+            return None;
+        }
+
+        let axis = (max - min).max_position();
+        let farthest = |distance: &dyn Fn(Vec3) -> f32| {
+            (0..points.len()).max_by(|a, b| distance(points[*a]).total_cmp(&distance(points[*b]))).unwrap()
+        };
+        let first = farthest(&|point| -point[axis]);
+        let second = farthest(&|point| point[axis]);
+        let direction = (points[second] - points[first]).normalize();
+        let third = farthest(&|point| (point - points[first]).reject_from_normalized(direction).length());
+        let normal = (points[second] - points[first]).cross(points[third] - points[first]).normalize_or_zero();
+        let fourth = farthest(&|point| normal.dot(point - points[first]).abs());
+        if normal == Vec3::ZERO || normal.dot(points[fourth] - points[first]).abs() <= tolerance {
+            return None;
+        }
+
+        let mut hull = Hull { points, faces: Vec::new(), edges: HashMap::new(), tolerance, current: 0 };
+        let inside = [first, second, third, fourth].map(|index| hull.points[index]).iter().sum::<Vec3>() / 4.;
+        for [a, b, c] in [[first, second, third], [first, second, fourth], [first, third, fourth], [second, third, fourth]] {
+            let normal = (hull.points[b] - hull.points[a]).cross(hull.points[c] - hull.points[a]);
+            let corners = if normal.dot(inside - hull.points[a]) > 0. { [a, c, b] } else { [a, b, c] };
+            hull.add_face(corners);
+        }
+        let all = (0..hull.points.len()).collect();
+        hull.assign_outside(all, 0);
+        Some(hull)
+    }
+
+    /// Adds the farthest point outside of the next face having any, returning false once all the
+    /// points are in the hull.
+    pub fn add_next_point(&mut self) -> bool {
+        // the faces added are after the current one, and only they get new outside points
+        while self.current < self.faces.len() {
+            let face = &self.faces[self.current];
+            if face.removed || face.outside.is_empty() {
+                self.current += 1;
+                continue;
+            }
+            let eye = *face.outside.iter()
+                .max_by(|a, b| self.distance(face, self.points[**a]).total_cmp(&self.distance(face, self.points[**b])))
+                .unwrap();
+            self.add_point(eye, self.current);
+            return true;
+        }
+        false
+    }
+
+    fn add_face(&mut self, corners: [usize; 3]) {
+        let [a, b, c] = corners.map(|corner| self.points[corner]);
+        let index = self.faces.len();
+        self.faces.push(HullFace { corners, normal: (b - a).cross(c - a).normalize_or_zero(), outside: Vec::new(), removed: false });
+        for corner in 0..3 {
+            self.edges.insert((corners[corner], corners[(corner + 1) % 3]), index);
+        }
+    }
+
+    /// How far in front of the face the point is.
+    fn distance(&self, face: &HullFace, point: Vec3) -> f32 {
+        face.normal.dot(point - self.points[face.corners[0]])
+    }
+
+    /// Gives each point to the first face from `first_face` on that it is outside of, dropping
+    /// the points inside the hull.
+    fn assign_outside(&mut self, points: Vec<usize>, first_face: usize) {
+        for point in points {
+            let face = (first_face..self.faces.len())
+                .find(|face| !self.faces[*face].removed && self.distance(&self.faces[*face], self.points[point]) > self.tolerance);
+            if let Some(face) = face {
+                self.faces[face].outside.push(point);
+            }
+        }
+    }
+
+    /// Replaces the faces the point `eye` sees, starting from `seen`, with the ones joining it to
+    /// their outline.
+    fn add_point(&mut self, eye: usize, seen: usize) {
+        let mut visible = HashSet::from([seen]);
+        let mut queue = VecDeque::from([seen]);
+        while let Some(face) = queue.pop_front() {
+            let corners = self.faces[face].corners;
+            for corner in 0..3 {
+                let Some(&neighbor) = self.edges.get(&(corners[(corner + 1) % 3], corners[corner])) else { continue; };
+                if !visible.contains(&neighbor) && self.distance(&self.faces[neighbor], self.points[eye]) > self.tolerance {
+                    visible.insert(neighbor);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+
+        // the outline is made of the edges between a visible face and a hidden one
+        let mut outline = Vec::new();
+        let mut orphans = Vec::new();
+        for face in &visible {
+            let corners = self.faces[*face].corners;
+            for corner in 0..3 {
+                let (start, end) = (corners[corner], corners[(corner + 1) % 3]);
+                if self.edges.get(&(end, start)).is_none_or(|neighbor| !visible.contains(neighbor)) {
+                    outline.push((start, end));
+                }
+            }
+            orphans.append(&mut self.faces[*face].outside);
+            self.faces[*face].removed = true;
+        }
+        for face in &visible {
+            let corners = self.faces[*face].corners;
+            for corner in 0..3 {
+                let edge = (corners[corner], corners[(corner + 1) % 3]);
+                if self.edges.get(&edge) == Some(face) {
+                    self.edges.remove(&edge);
+                }
+            }
+        }
+
+        let first_new = self.faces.len();
+        for (start, end) in outline {
+            self.add_face([start, end, eye]);
+        }
+        orphans.retain(|point| *point != eye);
+        self.assign_outside(orphans, first_new);
+    }
+
+    /// The outward normal of the largest face, merging the triangles lying on the same plane.
+    pub fn largest_face(&self) -> Option<Vec3> {
+        let area = |face: &HullFace| {
+            let [a, b, c] = face.corners.map(|corner| self.points[corner]);
+            (b - a).cross(c - a).length() / 2.
+        };
+        let mut grouped = vec![false; self.faces.len()];
+        let mut largest: Option<(f32, Vec3)> = None;
+        for start in 0..self.faces.len() {
+            if grouped[start] || self.faces[start].removed {
+                continue;
+            }
+            // the neighbors facing the same way are on the same plane, since the hull is convex
+            let normal = self.faces[start].normal;
+            let mut total = 0.;
+            let mut stack = vec![start];
+            grouped[start] = true;
+            while let Some(face) = stack.pop() {
+                total += area(&self.faces[face]);
+                let corners = self.faces[face].corners;
+                for corner in 0..3 {
+                    let Some(&neighbor) = self.edges.get(&(corners[(corner + 1) % 3], corners[corner])) else { continue; };
+                    if !grouped[neighbor] && self.faces[neighbor].normal.dot(normal) > 1. - 1e-5 {
+                        grouped[neighbor] = true;
+                        stack.push(neighbor);
+                    }
+                }
+            }
+            if largest.is_none_or(|(area, _)| total > area) {
+                largest = Some((total, normal));
+            }
+        }
+        largest.map(|(_, normal)| normal)
+    }
+}
+
+/// The points without the ones repeated, e.g. by STL files which list the corners of each
+/// triangle.
+pub fn unique_points(points: &[Vec3]) -> Vec<Vec3> {
+    let mut seen = HashSet::new();
+    points.iter()
+        // adding zero turns -0 into 0, so that both have the same bits
+        .filter(|point| seen.insert(point.to_array().map(|coordinate| (coordinate + 0.).to_bits())))
+        .copied()
+        .collect()
+}
+
+/// The rotation of a model putting down the side facing `normal`, in its own coordinates.
+pub fn face_down_rotation(normal: Vec3) -> Quat {
+    Quat::from_rotation_arc(normal.normalize(), Vec3::NEG_Y)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use crate::{unique_points, Hull};
+
+    fn full_hull(points: Vec<Vec3>) -> Hull {
+        let mut hull = Hull::start(points).unwrap();
+        while hull.add_next_point() {}
+        hull
+    }
+
+    #[test]
+    fn test_hull() {
+        // the points inside the hull are dropped, and each side of the box is made of two triangles
+        let sign = |corner: i32, axis: i32| if corner & (1 << axis) == 0 { -1. } else { 1. };
+        let mut points: Vec<Vec3> = (0..8)
+            .map(|corner| Vec3::new(5. * sign(corner, 0), 10. * sign(corner, 1), 15. * sign(corner, 2)))
+            .collect();
+        points.extend([Vec3::ZERO, Vec3::new(1., 2., 3.)]);
+        let hull = full_hull(points);
+        assert_eq!(hull.faces.iter().filter(|face| !face.removed).count(), 12);
+        assert!(hull.largest_face().unwrap().abs().distance(Vec3::X) < 1e-5);
+
+        // points spread over a sphere are all on the hull
+        let sphere: Vec<Vec3> = (0..200)
+            .map(|index| {
+                let height = 1. - 2. * (index as f32 + 0.5) / 200.;
+                let angle = index as f32 * std::f32::consts::PI * (3. - 5f32.sqrt());
+                let radius = (1. - height * height).sqrt();
+                Vec3::new(radius * angle.cos(), height, radius * angle.sin())
+            })
+            .collect();
+        let hull = full_hull(sphere.clone());
+        for face in hull.faces.iter().filter(|face| !face.removed) {
+            assert!(sphere.iter().all(|point| hull.distance(face, *point) < 1e-4));
+        }
+
+        assert!(Hull::start(vec![Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::ONE.with_z(0.)]).is_none());
+    }
+
+    #[test]
+    fn test_unique_points() {
+        let points = [Vec3::ZERO, Vec3::X, Vec3::new(-0., 0., 0.), Vec3::X, Vec3::Y];
+        assert_eq!(unique_points(&points), vec![Vec3::ZERO, Vec3::X, Vec3::Y]);
+    }
+}
@@ -0,0 +1,398 @@
+//! Renders a thumbnail of each STL mesh listed in a manifest, and writes their urls back into it,
+//! so that the viewer can show a grid of children without downloading all their meshes.
+//!
+//! The meshes are turned upright, centered and scaled to fit in a unit cube like in the grid of
+//! the viewer, and drawn on the CPU, so that no GPU is needed. Manifests referenced by lazy nodes
+//! are processed too.
+
+mod raster;
+
+use std::{
+    collections::HashSet, fs, hash::{DefaultHasher, Hash, Hasher}, path::{Path, PathBuf}, process::ExitCode,
+};
+
+use glam::{Quat, Vec3};
+use serde_json::{ser::PrettyFormatter, Serializer, Value};
+// the viewer lays models flat with the same code
+use stlviewer_hull::{face_down_rotation, unique_points, Hull};
+
+use raster::CameraAngle;
+
+const USAGE: &str = "Usage: stlviewer-thumbs <manifest.json> [options]
+
+Options:
+    --root <dir>      directory that urls starting with / refer to (default: the one of the manifest)
+    --out <dir>       where to write the thumbnails, relative to each manifest (default: thumbs)
+    --size <pixels>   width and height of the thumbnails (default: 256)
+    --yaw <degrees>   rotation of the camera around the vertical axis (default: 30)
+    --pitch <degrees> rotation of the camera above the horizon (default: 25)
+    --force           render again the nodes that already have a thumbnail";
+
+/// The gray of meshes without a color, like the white material of the viewer under its light.
+const DEFAULT_COLOR: Vec3 = Vec3::splat(0.8);
+
+#[derive(Debug)]
+struct Options {
+    manifest: PathBuf,
+    root: Option<PathBuf>,
+    out: PathBuf,
+    size: usize,
+    angle: CameraAngle,
+    force: bool,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut manifest = None;
+        let mut options = Options {
+            manifest: PathBuf::new(),
+            root: None,
+            out: PathBuf::from("thumbs"),
+            size: 256,
+            angle: CameraAngle { yaw: 30f32.to_radians(), pitch: 25f32.to_radians() },
+            force: false,
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("missing value for {arg}"));
+            match arg.as_str() {
+                "--root" => options.root = Some(PathBuf::from(value()?)),
+                "--out" => options.out = PathBuf::from(value()?),
+                "--size" => options.size = parse_number(&value()?)?,
+                "--yaw" => options.angle.yaw = parse_number::<f32>(&value()?)?.to_radians(),
+                "--pitch" => options.angle.pitch = parse_number::<f32>(&value()?)?.to_radians(),
+                "--force" => options.force = true,
+                _ if arg.starts_with("--") => return Err(format!("unknown option {arg}")),
+                _ if manifest.is_none() => manifest = Some(PathBuf::from(arg)),
+                _ => return Err(format!("unexpected argument {arg}")),
+            }
+        }
+        options.manifest = manifest.ok_or("missing manifest")?;
+        if options.size == 0 {
+            return Err("the size must be at least 1 pixel".to_string());
+        }
+        Ok(options)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid number '{value}'"))
+}
+
+fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{error}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        },
+    };
+    let root = options.root.clone()
+        .unwrap_or_else(|| options.manifest.parent().map(Path::to_path_buf).unwrap_or_default());
+
+    let mut generator = Generator { options: &options, root, visited: HashSet::new(), rendered: HashSet::new(), failures: 0 };
+    if let Err(error) = generator.process_manifest(&options.manifest) {
+        eprintln!("{}: {error}", options.manifest.display());
+        return ExitCode::FAILURE;
+    }
+    println!("Rendered {} thumbnails", generator.rendered.len());
+    if generator.failures > 0 {
+        eprintln!("{} meshes or manifests could not be processed", generator.failures);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+struct Generator<'a> {
+    options: &'a Options,
+    root: PathBuf,
+    /// the manifests already processed, in case they refer to each other
+    visited: HashSet<PathBuf>,
+    /// the thumbnails written so far, since the same mesh can be listed many times
+    rendered: HashSet<PathBuf>,
+    failures: usize,
+}
+
+impl Generator<'_> {
+    fn process_manifest(&mut self, path: &Path) -> Result<(), String> {
+        let canonical = fs::canonicalize(path).map_err(|error| error.to_string())?;
+        if !self.visited.insert(canonical) {
+            return Ok(());
+        }
+        let json = fs::read(path).map_err(|error| error.to_string())?;
+        let mut root: Value = serde_json::from_slice(&json).map_err(|error| error.to_string())?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        let before = root.clone();
+        self.process_node(&mut root, directory);
+        if root != before {
+            // same indentation as the manifests in this repository
+            let mut json = Vec::new();
+            let mut serializer = Serializer::with_formatter(&mut json, PrettyFormatter::with_indent(b"    "));
+            serde::Serialize::serialize(&root, &mut serializer).map_err(|error| error.to_string())?;
+            json.push(b'\n');
+            fs::write(path, json).map_err(|error| error.to_string())?;
+            println!("Updated {}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Renders the thumbnail of the node and of its children, returning the url of the one of
+    /// the node. Nodes without a mesh get the thumbnail of their first child, which is the one
+    /// the viewer shows for them.
+    fn process_node(&mut self, node: &mut Value, directory: &Path) -> Option<String> {
+        let Value::Object(fields) = node else { return None; };
+
+        let mut children_thumbnail = None;
+        if let Some(Value::Array(children)) = fields.get_mut("children") {
+            for (index, child) in children.iter_mut().enumerate() {
+                let thumbnail = self.process_node(child, directory);
+                if index == 0 {
+                    children_thumbnail = thumbnail;
+                }
+            }
+        }
+        if let Some(Value::String(manifest)) = fields.get("manifest") {
+            match self.local_path(manifest, directory) {
+                Some(manifest) => if let Err(error) = self.process_manifest(&manifest) {
+                    eprintln!("{}: {error}", manifest.display());
+                    self.failures += 1;
+                },
+                None => eprintln!("Skipping remote manifest {manifest}"),
+            }
+        }
+
+        let existing = fields.get("thumbnail").and_then(Value::as_str).map(str::to_string);
+        if existing.is_some() && !self.options.force {
+            return existing;
+        }
+        let thumbnail = match fields.get("url").and_then(Value::as_str) {
+            Some(url) if !url.is_empty() => self.render_thumbnail(url, fields, directory),
+            _ => children_thumbnail,
+        };
+        match thumbnail {
+            Some(thumbnail) => {
+                fields.insert("thumbnail".to_string(), Value::String(thumbnail.clone()));
+                Some(thumbnail)
+            },
+            None => existing,
+        }
+    }
+
+    /// Renders the mesh at `url` with the color and orientation of the node, returning the url
+    /// of the thumbnail relative to the manifest.
+    fn render_thumbnail(&mut self, url: &str, fields: &serde_json::Map<String, Value>, directory: &Path) -> Option<String> {
+        let is_stl = match fields.get("format").and_then(Value::as_str) {
+            Some(format) => format.eq_ignore_ascii_case("stl"),
+            None => url.split(['?', '#']).next().unwrap_or(url).to_ascii_lowercase().ends_with(".stl"),
+        };
+        if !is_stl {
+            eprintln!("Skipping {url}, only STL meshes are supported");
+            return None;
+        }
+        let Some(path) = self.local_path(url, directory) else {
+            eprintln!("Skipping remote mesh {url}");
+            return None;
+        };
+
+        let color = fields.get("color").and_then(Value::as_str);
+        let up_axis = fields.get("up_axis").and_then(Value::as_str).unwrap_or("auto");
+        let file_name = thumbnail_file_name(url, color, up_axis);
+        let thumbnail_path = directory.join(&self.options.out).join(&file_name);
+        let thumbnail_url = self.options.out.join(&file_name).to_string_lossy().replace('\\', "/");
+        if self.rendered.contains(&thumbnail_path) {
+            return Some(thumbnail_url);
+        }
+
+        let result = read_stl(&path).and_then(|triangles| {
+            let color = match color {
+                Some(color) => parse_color(color).ok_or_else(|| format!("invalid color '{color}'"))?,
+                None => DEFAULT_COLOR,
+            };
+            let rotation = upright_rotation(up_axis, &triangles);
+            let triangles = normalize(triangles, rotation);
+            let image = raster::render(&triangles, color, self.options.angle, self.options.size);
+            write_png(&thumbnail_path, &image)
+        });
+        match result {
+            Ok(()) => {
+                println!("{} -> {}", path.display(), thumbnail_path.display());
+                self.rendered.insert(thumbnail_path);
+                Some(thumbnail_url)
+            },
+            Err(error) => {
+                eprintln!("{}: {error}", path.display());
+                self.failures += 1;
+                None
+            },
+        }
+    }
+
+    /// The file an url refers to, resolved like the viewer does, or `None` for remote urls.
+    fn local_path(&self, url: &str, directory: &Path) -> Option<PathBuf> {
+        if url.contains("://") {
+            return None;
+        }
+        let url = url.split(['?', '#']).next().unwrap_or(url);
+        Some(match url.strip_prefix('/') {
+            Some(absolute) => self.root.join(absolute),
+            None => directory.join(url),
+        })
+    }
+}
+
+/// A name for the thumbnail of the mesh at `url`, which is different when the mesh is shown with
+/// another color or orientation.
+fn thumbnail_file_name(url: &str, color: Option<&str>, up_axis: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    let stem = file_name.rsplit_once('.').map_or(file_name, |(stem, _)| stem);
+    let stem: String = stem.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect();
+    let mut hasher = DefaultHasher::new();
+    (url, color, up_axis).hash(&mut hasher);
+    format!("{stem}-{:08x}.png", hasher.finish() as u32)
+}
+
+/// The linear RGB of a hex color such as `#f80` or `#ff8800cc`, read like the manifests of the
+/// viewer, the alpha being ignored.
+fn parse_color(color: &str) -> Option<Vec3> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if !hex.is_ascii() {
+        return None;
+    }
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).ok();
+    let [r, g, b] = match hex.len() {
+        3 | 4 => [0, 1, 2].map(|index| channel(&hex[index..index + 1]).map(|value| value * 17)),
+        6 | 8 => [0, 2, 4].map(|index| channel(&hex[index..index + 2])),
+        _ => return None,
+    };
+    if matches!(hex.len(), 4 | 8) {
+        // the alpha still has to be valid
+        channel(&hex[hex.len() / 4 * 3..])?;
+    }
+    let srgb = Vec3::new(r? as f32, g? as f32, b? as f32) / 255.;
+    Some(srgb.map(|value| if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }))
+}
+
+fn read_stl(path: &Path) -> Result<Vec<[Vec3; 3]>, String> {
+    let mut file = fs::File::open(path).map_err(|error| error.to_string())?;
+    let mesh = stl_io::read_stl(&mut file).map_err(|error| format!("invalid STL: {error}"))?;
+    Ok(mesh.faces.iter()
+        .map(|face| face.vertices.map(|index| Vec3::from_array(mesh.vertices[index].0)))
+        .collect())
+}
+
+/// The rotation that brings the up axis of the manifest onto the vertical axis, as in the viewer,
+/// which lays flat the models without one, and the flat ones with Z up.
+fn upright_rotation(up_axis: &str, triangles: &[[Vec3; 3]]) -> Quat {
+    let z_up = Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+    match up_axis {
+        "x" => Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+        "y" => Quat::IDENTITY,
+        "z" => z_up,
+        _ => {
+            let Some(mut hull) = Hull::start(unique_points(triangles.as_flattened())) else { return z_up; };
+            while hull.add_next_point() {}
+            hull.largest_face().map_or(z_up, face_down_rotation)
+        },
+    }
+}
+
+/// Centers the triangles on the origin and scales them to fit in a unit cube, after rotating
+/// them upright, like the grid of the viewer does.
+fn normalize(mut triangles: Vec<[Vec3; 3]>, rotation: Quat) -> Vec<[Vec3; 3]> {
+    // the bounding box is the one of the rotated model, which is the one seen in the thumbnail
+    triangles.iter_mut().flatten().for_each(|point| *point = rotation * *point);
+    let (min, max) = triangles.iter().flatten()
+        .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), point| (min.min(*point), max.max(*point)));
+    if min.cmpgt(max).any() {
+        return triangles;
+    }
+    let center = (min + max) / 2.;
+    let scale = 1. / (max - min).max_element().max(f32::EPSILON);
+    triangles.iter_mut().flatten().for_each(|point| *point = (*point - center) * scale);
+    triangles
+}
+
+fn write_png(path: &Path, image: &raster::Image) -> Result<(), String> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory).map_err(|error| error.to_string())?;
+    }
+    let file = fs::File::create(path).map_err(|error| error.to_string())?;
+    let size = image.size as u32;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), size, size);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|error| error.to_string())?;
+    writer.write_image_data(image.pixels.as_flattened()).map_err(|error| error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Quat, Vec3};
+
+    use crate::{normalize, parse_color, thumbnail_file_name, upright_rotation, Options};
+
+    #[test]
+    fn test_options() {
+        let args = ["tree.json", "--size", "64", "--force", "--yaw", "90"].map(str::to_string);
+        let options = Options::parse(args.into_iter()).unwrap();
+        assert_eq!(options.manifest.to_str(), Some("tree.json"));
+        assert_eq!(options.size, 64);
+        assert!(options.force);
+        assert!((options.angle.yaw - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+
+        assert!(Options::parse(["--size".to_string()].into_iter()).is_err());
+        assert!(Options::parse(["--size", "0", "tree.json"].map(str::to_string).into_iter()).is_err());
+        assert!(Options::parse(std::iter::empty()).is_err());
+    }
+
+    #[test]
+    fn test_normalize() {
+        let triangles = vec![[Vec3::new(10., 0., 0.), Vec3::new(14., 0., 0.), Vec3::new(10., 2., 1.)]];
+        let normalized = normalize(triangles, Quat::IDENTITY);
+        assert_eq!(normalized[0], [Vec3::new(-0.5, -0.25, -0.125), Vec3::new(0.5, -0.25, -0.125), Vec3::new(-0.5, 0.25, 0.125)]);
+    }
+
+    #[test]
+    fn test_normalize_rotated() {
+        // turned by 45° around Z, the triangle is wider than it was and no longer starts at the origin
+        let triangles = vec![[Vec3::ZERO, Vec3::new(2., 0., 0.), Vec3::new(0., 2., 0.)]];
+        let normalized = normalize(triangles, Quat::from_rotation_z(std::f32::consts::FRAC_PI_4));
+        let expected = [Vec3::new(0., -0.25, 0.), Vec3::new(0.5, 0.25, 0.), Vec3::new(-0.5, 0.25, 0.)];
+        for (point, expected) in normalized[0].iter().zip(expected) {
+            assert!(point.distance(expected) < 1e-6, "{point} != {expected}");
+        }
+    }
+
+    #[test]
+    fn test_thumbnail_file_name() {
+        let name = thumbnail_file_name("/models/3D Benchy.stl", None, "z");
+        assert!(name.starts_with("3D_Benchy-") && name.ends_with(".png"));
+        assert_ne!(name, thumbnail_file_name("/models/3D Benchy.stl", Some("#ff8800"), "z"));
+        assert_ne!(name, thumbnail_file_name("/other/3D Benchy.stl", None, "z"));
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#ffffff"), Some(Vec3::ONE));
+        assert_eq!(parse_color("000"), Some(Vec3::ZERO));
+        assert_eq!(parse_color("#f00c"), Some(Vec3::X));
+        assert!((parse_color("#808080ff").unwrap() - Vec3::splat(0.2158605)).abs().max_element() < 1e-6);
+        assert!(parse_color("#ff88").is_some());
+        assert!(parse_color("#ff880").is_none());
+        assert!(parse_color("#ff8800zz").is_none());
+        assert!(parse_color("#ééé").is_none());
+    }
+
+    #[test]
+    fn test_upright_rotation() {
+        // a slab standing on its side is laid flat unless the manifest gives its up axis
+        let corners: Vec<Vec3> = (0..8).map(|corner| Vec3::new((corner & 1) as f32 * 10., (corner >> 1 & 1) as f32, (corner >> 2) as f32 * 20.)).collect();
+        // only the corners of the triangles matter
+        let triangles: Vec<[Vec3; 3]> = corners.windows(3).map(|window| [window[0], window[1], window[2]]).collect();
+        let up = |up_axis: &str| (upright_rotation(up_axis, &triangles).inverse() * Vec3::Y).abs();
+        assert!(up("auto").distance(Vec3::Y) < 1e-5);
+        assert!(up("z").distance(Vec3::Z) < 1e-5);
+        assert!(up("x").distance(Vec3::X) < 1e-5);
+    }
+}
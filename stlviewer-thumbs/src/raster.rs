@@ -0,0 +1,171 @@
+//! A small software rasterizer, drawing flat shaded triangles with a depth buffer, so that
+//! thumbnails can be rendered on machines without a GPU.
+
+use glam::{Mat4, Vec3, Vec4Swizzles};
+
+/// Where the camera looks at the model from, orbiting around it like in the viewer.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraAngle {
+    /// rotation around the vertical axis, in radians
+    pub yaw: f32,
+    /// rotation above the horizontal plane, in radians
+    pub pitch: f32,
+}
+
+/// Same distance and field of view as the camera in the grid of the viewer, which shows meshes
+/// normalized to fit in a unit cube.
+const CAMERA_DISTANCE: f32 = 1.5;
+const FIELD_OF_VIEW: f32 = std::f32::consts::FRAC_PI_4;
+
+/// The light is fixed relative to the camera, so that the visible side is always lit.
+const LIGHT_DIRECTION: Vec3 = Vec3::new(1., 1., 1.);
+const AMBIENT: f32 = 0.3;
+
+/// Each pixel is the average of this many samples along each axis, to smooth the edges.
+const SUPERSAMPLING: usize = 2;
+
+/// An image with 8 bit RGBA pixels, row by row from the top.
+pub struct Image {
+    pub size: usize,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+/// Renders `triangles` on a transparent background, with the given linear `color`.
+pub fn render(triangles: &[[Vec3; 3]], color: Vec3, angle: CameraAngle, size: usize) -> Image {
+    let samples = size * SUPERSAMPLING;
+    let eye = Vec3::new(
+        CAMERA_DISTANCE * angle.yaw.sin() * angle.pitch.cos(),
+        CAMERA_DISTANCE * angle.pitch.sin(),
+        CAMERA_DISTANCE * angle.yaw.cos() * angle.pitch.cos(),
+    );
+    let view = Mat4::look_at_rh(eye, Vec3::ZERO, Vec3::Y);
+    let projection = Mat4::perspective_rh(FIELD_OF_VIEW, 1., 0.01, 10.);
+    let view_projection = projection * view;
+    let light = view.inverse().transform_vector3(LIGHT_DIRECTION).normalize();
+
+    let mut depth = vec![f32::INFINITY; samples * samples];
+    let mut colors: Vec<Option<Vec3>> = vec![None; samples * samples];
+    for triangle in triangles {
+        let normal = (triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]).normalize_or_zero();
+        if normal == Vec3::ZERO {
+            continue;
+        }
+        // both sides are lit the same, since the normals of STL files are often wrong
+        let facing = if normal.dot(eye - triangle[0]) < 0. { -normal } else { normal };
+        let shade = AMBIENT + (1. - AMBIENT) * facing.dot(light).max(0.);
+
+        let Some(corners) = project(triangle, &view_projection, samples) else { continue; };
+        fill_triangle(corners, samples, |index, z| {
+            if z < depth[index] {
+                depth[index] = z;
+                colors[index] = Some(color * shade);
+            }
+        });
+    }
+
+    Image { size, pixels: downsample(&colors, size) }
+}
+
+/// The corners of the triangle in sample coordinates, with their depth, or `None` if the
+/// triangle is behind the camera.
+fn project(triangle: &[Vec3; 3], view_projection: &Mat4, samples: usize) -> Option<[Vec3; 3]> {
+    let mut corners = [Vec3::ZERO; 3];
+    for (corner, point) in corners.iter_mut().zip(triangle) {
+        let clip = *view_projection * point.extend(1.);
+        if clip.w <= 0. {
+            return None;
+        }
+        let ndc = clip.xyz() / clip.w;
+        *corner = Vec3::new(
+            (ndc.x + 1.) / 2. * samples as f32,
+            (1. - ndc.y) / 2. * samples as f32,
+            ndc.z,
+        );
+    }
+    Some(corners)
+}
+
+/// Calls `plot` with the index and depth of each sample whose center is inside the triangle.
+fn fill_triangle(corners: [Vec3; 3], samples: usize, mut plot: impl FnMut(usize, f32)) {
+    let [a, b, c] = corners;
+    let area = edge(a, b, c);
+    if area.abs() < f32::EPSILON {
+        return;
+    }
+    let min = a.min(b).min(c).max(Vec3::ZERO);
+    let max = a.max(b).max(c).min(Vec3::splat(samples as f32 - 1.));
+    for y in min.y.floor() as usize..=max.y.ceil().max(0.) as usize {
+        for x in min.x.floor() as usize..=max.x.ceil().max(0.) as usize {
+            if x >= samples || y >= samples {
+                continue;
+            }
+            let point = Vec3::new(x as f32 + 0.5, y as f32 + 0.5, 0.);
+            let weights = Vec3::new(edge(b, c, point), edge(c, a, point), edge(a, b, point)) / area;
+            if weights.min_element() < 0. {
+                continue;
+            }
+            plot(y * samples + x, weights.dot(Vec3::new(a.z, b.z, c.z)));
+        }
+    }
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `point` on the XY plane.
+fn edge(a: Vec3, b: Vec3, point: Vec3) -> f32 {
+    (b.x - a.x) * (point.y - a.y) - (b.y - a.y) * (point.x - a.x)
+}
+
+/// Averages each block of samples into a pixel, converting the color to sRGB.
+fn downsample(colors: &[Option<Vec3>], size: usize) -> Vec<[u8; 4]> {
+    let samples = size * SUPERSAMPLING;
+    let mut pixels = Vec::with_capacity(size * size);
+    for y in 0..size {
+        for x in 0..size {
+            let (mut sum, mut covered) = (Vec3::ZERO, 0);
+            for sample_y in y * SUPERSAMPLING..(y + 1) * SUPERSAMPLING {
+                for sample_x in x * SUPERSAMPLING..(x + 1) * SUPERSAMPLING {
+                    if let Some(color) = colors[sample_y * samples + sample_x] {
+                        sum += color;
+                        covered += 1;
+                    }
+                }
+            }
+            if covered == 0 {
+                pixels.push([0; 4]);
+                continue;
+            }
+            let color = sum / covered as f32;
+            let alpha = covered as f32 / (SUPERSAMPLING * SUPERSAMPLING) as f32;
+            let [r, g, b] = color.to_array().map(linear_to_srgb);
+            pixels.push([r, g, b, (alpha * 255.).round() as u8]);
+        }
+    }
+    pixels
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0., 1.);
+    let srgb = if value <= 0.003_130_8 { value * 12.92 } else { 1.055 * value.powf(1. / 2.4) - 0.055 };
+    (srgb * 255.).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use crate::raster::{render, CameraAngle};
+
+    #[test]
+    fn test_render() {
+        // a square facing the camera, covering the middle of the image
+        let square = [
+            [Vec3::new(-0.2, -0.2, 0.), Vec3::new(0.2, -0.2, 0.), Vec3::new(0.2, 0.2, 0.)],
+            [Vec3::new(-0.2, -0.2, 0.), Vec3::new(0.2, 0.2, 0.), Vec3::new(-0.2, 0.2, 0.)],
+        ];
+        let image = render(&square, Vec3::ONE, CameraAngle { yaw: 0., pitch: 0. }, 32);
+        assert_eq!(image.pixels.len(), 32 * 32);
+        assert_eq!(image.pixels[0], [0; 4]);
+        let center = image.pixels[16 * 32 + 16];
+        assert_eq!(center[3], 255);
+        assert!(center[0] > 0 && center[0] == center[1] && center[1] == center[2]);
+    }
+}